@@ -0,0 +1,190 @@
+use buffers::{face_normal, RelativeBuffers};
+use cgmath::{vec3, InnerSpace, Vector3};
+
+impl RelativeBuffers {
+    /// Returns the indices of the triangles (where triangle `i` is made of
+    /// `indices[3 * i..3 * i + 3]`) whose winding makes them face away from
+    /// the direction they are expected to face. A triangle is expected to
+    /// face along the sum of its vertex normals, or along `up` if its vertex
+    /// normals cancel out.
+    pub fn check_winding(&self, up: Vector3<f32>) -> Vec<usize> {
+        self.indices
+            .chunks(3)
+            .enumerate()
+            .filter_map(|(i, t)| {
+                let a = &self.attributes[t[0] as usize];
+                let b = &self.attributes[t[1] as usize];
+                let c = &self.attributes[t[2] as usize];
+                let normal =
+                    face_normal(a.space_coord_px, b.space_coord_px, c.space_coord_px);
+                let expected = a.normal + b.normal + c.normal;
+                let expected = if expected.magnitude2() > 0. {
+                    expected
+                } else {
+                    up
+                };
+                if normal.dot(expected) < 0. {
+                    Some(i)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the component-wise minimum and maximum vertex positions, or
+    /// `None` if there are no vertices.
+    pub fn bounds(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let mut attributes = self.attributes.iter();
+        let first = attributes.next()?.space_coord_px;
+        Some(attributes.fold((first, first), |(min, max), a| {
+            let p = a.space_coord_px;
+            (
+                vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        }))
+    }
+
+    /// Returns the centre of the bounding box, or `None` if there are no
+    /// vertices.
+    pub fn center(&self) -> Option<Vector3<f32>> {
+        self.bounds().map(|(min, max)| (min + max) / 2.)
+    }
+
+    /// Returns the dimensions of the bounding box, or `None` if there are no
+    /// vertices.
+    pub fn size(&self) -> Option<Vector3<f32>> {
+        self.bounds().map(|(min, max)| max - min)
+    }
+
+    /// Returns the total area of all triangles, in the units of
+    /// `space_coord_px`. The area is accumulated in double precision, so
+    /// large meshes don't lose the contributions of small triangles.
+    pub fn surface_area(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| {
+                let normal =
+                    face_normal(a.space_coord_px, b.space_coord_px, c.space_coord_px);
+                normal.magnitude() as f64 / 2.
+            })
+            .sum::<f64>() as f32
+    }
+
+    /// Returns the volume enclosed by the triangles, which is positive when
+    /// they face outwards. This is only meaningful for closed meshes, such as
+    /// those made by `wall_collision_mesh`. Generated walls aren't closed, as
+    /// they have no bottom faces, and faces are missing where walls meet, so
+    /// the result for them is meaningless.
+    pub fn signed_volume(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| {
+                let (a, b, c) = (a.space_coord_px, b.space_coord_px, c.space_coord_px);
+                // front faces are wound clockwise, so the triple product is
+                // taken in the opposite order to the usual formula
+                a.dot(c.cross(b)) as f64 / 6.
+            })
+            .sum::<f64>() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cell::Piece;
+    use cgmath::vec2;
+    use direction::OrdinalDirections;
+    use geometry::{make_geometry, quarter_size_px, PieceHeights};
+    use style::{ConfigBuilder, StyleBuilder};
+
+    fn vertex(x: f32, y: f32, z: f32) -> Attribute {
+        Attribute {
+            space_coord_px: vec3(x, y, z),
+            tex_coord_px: vec2(0., 0.),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        }
+    }
+
+    #[test]
+    fn pieces_are_wound_consistently() {
+        let style = StyleBuilder::new().top_bevel_px(2.).build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .corner_segments(4)
+            .build();
+        for &piece in [Piece::Outer, Piece::Inner, Piece::Left, Piece::DoorwayLeft].iter()
+        {
+            for direction in OrdinalDirections {
+                let geometry = make_geometry(
+                    piece,
+                    direction,
+                    quarter_size_px(direction, &config),
+                    PieceHeights {
+                        bottom_px: 0.,
+                        top_px: style.height_px,
+                        bevel_px: style.top_bevel_px,
+                    },
+                    &style,
+                    &config,
+                );
+                assert_eq!(
+                    geometry.check_winding(vec3(0., 1., 0.)),
+                    Vec::<usize>::new()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_triangle_is_found() {
+        // the second triangle is wound anticlockwise viewed from above
+        let buffers = RelativeBuffers {
+            attributes: vec![
+                vertex(0., 0., 0.),
+                vertex(1., 0., 0.),
+                vertex(1., 0., 1.),
+                vertex(0., 0., 1.),
+            ],
+            indices: vec![0, 1, 2, 0, 3, 2],
+        };
+        assert_eq!(buffers.check_winding(vec3(0., 1., 0.)), vec![1]);
+    }
+
+    #[test]
+    fn two_vertex_bounds() {
+        let buffers = RelativeBuffers {
+            attributes: vec![vertex(1., -2., 3.), vertex(-4., 5., 0.5)],
+            indices: Vec::new(),
+        };
+        assert_eq!(
+            buffers.bounds(),
+            Some((vec3(-4., -2., 0.5), vec3(1., 5., 3.)))
+        );
+        assert_eq!(buffers.center(), Some(vec3(-1.5, 1.5, 1.75)));
+        assert_eq!(buffers.size(), Some(vec3(5., 7., 2.5)));
+        let empty = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: Vec::new(),
+        };
+        assert_eq!(empty.bounds(), None);
+    }
+
+    #[test]
+    fn unit_quad_area() {
+        let buffers = RelativeBuffers {
+            attributes: vec![
+                vertex(0., 0., 0.),
+                vertex(1., 0., 0.),
+                vertex(1., 0., 1.),
+                vertex(0., 0., 1.),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+        assert_eq!(buffers.surface_area(), 1.);
+    }
+}
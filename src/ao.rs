@@ -0,0 +1,103 @@
+use buffers::RelativeBuffers;
+use cell::{CellType, Piece, Quarter};
+use direction::OrdinalDirection;
+use grid_2d::{Coord, Grid};
+use style::Config;
+
+// ambient occlusion of vertices in each kind of quarter
+const AO_INNER: f32 = 0.5;
+const AO_STRAIGHT: f32 = 0.8;
+const AO_OUTER: f32 = 1.;
+
+fn piece_ao(piece: Piece) -> f32 {
+    match piece {
+        Piece::Inner => AO_INNER,
+        Piece::Outer | Piece::Pillar => AO_OUTER,
+        Piece::Left
+        | Piece::Right
+        | Piece::DoorwayLeft
+        | Piece::DoorwayRight
+        | Piece::WindowLeft
+        | Piece::WindowRight => AO_STRAIGHT,
+    }
+}
+
+impl RelativeBuffers {
+    /// Sets the ambient occlusion of each vertex according to the shape of
+    /// the quarter of the solid cell it lies in, such that vertices in inner
+    /// corners are darkest and those on outer corners are lightest. Vertices
+    /// outside of solid cells aren't occluded. `grid` and `config` must be
+    /// those the geometry was generated with.
+    pub fn bake_ao(&mut self, grid: &Grid<CellType>, config: &Config) {
+        let cell_size = config.cell_size_px * config.units_per_px;
+        // vertices on the boundary between cells or quarters are treated as
+        // being in the cell or quarter behind them
+        let nudge = config.units_per_px / 1024.;
+        for a in self.attributes.iter_mut() {
+            let p = a.space_coord_px - a.normal * nudge;
+            let x = p.x / cell_size.x;
+            let z = p.z / cell_size.y;
+            let coord = Coord::new(x.floor() as i32, z.floor() as i32);
+            let is_solid = grid
+                .get(coord)
+                .is_some_and(|cell_type| cell_type.is_solid());
+            a.ao = if is_solid {
+                let east = x - coord.x as f32 >= 0.5;
+                let south = z - coord.y as f32 >= 0.5;
+                let direction = match (east, south) {
+                    (true, false) => OrdinalDirection::NorthEast,
+                    (true, true) => OrdinalDirection::SouthEast,
+                    (false, true) => OrdinalDirection::SouthWest,
+                    (false, false) => OrdinalDirection::NorthWest,
+                };
+                piece_ao(Quarter::from_grid(grid, coord, direction, config).piece)
+            } else {
+                AO_OUTER
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+    use generate::generate_mesh;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    #[test]
+    fn inner_corners_are_darker_than_outer_corners() {
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build();
+        let grid = parse_terrain("###\n#.#\n###\n").unwrap();
+        let quarter =
+            |direction| Quarter::from_grid(&grid, Coord::new(0, 0), direction, &config);
+        assert_eq!(quarter(OrdinalDirection::SouthEast).piece, Piece::Inner);
+        assert_eq!(quarter(OrdinalDirection::NorthWest).piece, Piece::Outer);
+        let mut buffers = generate_mesh(&grid, &style, &config);
+        buffers.bake_ao(&grid, &config);
+        // the corners of the top-left cell, at floor level, on the side of the
+        // room and the outside of the grid
+        let ao = |min: f32, max: f32| {
+            let corners = buffers
+                .attributes
+                .iter()
+                .filter(|a| a.space_coord_px.y == 0.)
+                .filter(|a| {
+                    let p = a.space_coord_px;
+                    min < p.x && p.x < max && min < p.z && p.z < max
+                })
+                .map(|a| a.ao)
+                .collect::<Vec<_>>();
+            assert!(!corners.is_empty());
+            corners
+        };
+        let inner = ao(16., 32.);
+        let outer = ao(0., 16.);
+        assert!(inner.iter().all(|&i| outer.iter().all(|&o| i < o)));
+    }
+}
@@ -0,0 +1,166 @@
+use buffers::RelativeBuffers;
+use cell::CellType;
+use cgmath::Vector2;
+use generate::generate_mesh;
+use grid_2d::Grid;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use style::{Config, Style};
+
+/// A rectangle of a texture atlas, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AtlasRect {
+    pub top_left_px: Vector2<f32>,
+    pub size_px: Vector2<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtlasError {
+    MissingEntry(String),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AtlasError::MissingEntry(ref name) => {
+                write!(f, "no entry named {:?} in atlas", name)
+            }
+        }
+    }
+}
+
+impl Error for AtlasError {}
+
+/// Mapping from names to rectangles of a texture atlas.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Atlas {
+    rects: HashMap<String, AtlasRect>,
+}
+
+impl Atlas {
+    pub fn empty() -> Self {
+        Self {
+            rects: HashMap::new(),
+        }
+    }
+    pub fn with<S: Into<String>>(mut self, name: S, rect: AtlasRect) -> Self {
+        self.insert(name, rect);
+        self
+    }
+    pub fn insert<S: Into<String>>(&mut self, name: S, rect: AtlasRect) {
+        self.rects.insert(name.into(), rect);
+    }
+    /// Returns the rectangle named `name`, or an error if there is no such
+    /// entry.
+    pub fn get(&self, name: &str) -> Result<AtlasRect, AtlasError> {
+        self.rects
+            .get(name)
+            .cloned()
+            .ok_or_else(|| AtlasError::MissingEntry(name.to_string()))
+    }
+}
+
+/// Names of the atlas entries used for each surface (see
+/// `Style::texture_names`)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TextureNames {
+    pub face: String,
+    pub top: String,
+    pub floor: String,
+    pub ceiling: String,
+}
+
+impl Style {
+    /// Returns the style with the texture offsets of every surface set to
+    /// the top-left corners of the entries of `atlas` named by
+    /// `texture_names`, if there are any. Fails if any name is missing from
+    /// `atlas`, rather than leaving the offset unset. The style is only
+    /// copied if it names any entries.
+    pub fn resolve_atlas<'a>(
+        &'a self,
+        atlas: &Atlas,
+    ) -> Result<Cow<'a, Style>, AtlasError> {
+        let names = match self.texture_names {
+            Some(ref names) => names,
+            None => return Ok(Cow::Borrowed(self)),
+        };
+        let mut style = self.clone();
+        style.face_tex_top_left_px = atlas.get(&names.face)?.top_left_px;
+        style.top_tex_top_left_px = atlas.get(&names.top)?.top_left_px;
+        style.floor_tex_top_left_px = atlas.get(&names.floor)?.top_left_px;
+        style.ceiling_tex_top_left_px = atlas.get(&names.ceiling)?.top_left_px;
+        style.texture_names = None;
+        Ok(Cow::Owned(style))
+    }
+}
+
+/// Like `generate_mesh`, but with the textures named by `style` (see
+/// `Style::texture_names`) looked up in `atlas`. Fails if any name is missing
+/// from `atlas`.
+pub fn generate_mesh_with_atlas(
+    grid: &Grid<CellType>,
+    style: &Style,
+    atlas: &Atlas,
+    config: &Config,
+) -> Result<RelativeBuffers, AtlasError> {
+    let style = style.resolve_atlas(atlas)?;
+    Ok(generate_mesh(grid, &style, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    fn names() -> TextureNames {
+        TextureNames {
+            face: "brick".to_string(),
+            top: "stone".to_string(),
+            floor: "stone".to_string(),
+            ceiling: "plaster".to_string(),
+        }
+    }
+
+    fn rect(x: f32, y: f32) -> AtlasRect {
+        AtlasRect {
+            top_left_px: vec2(x, y),
+            size_px: vec2(16., 16.),
+        }
+    }
+
+    #[test]
+    fn missing_name_is_an_error() {
+        let atlas = Atlas::empty()
+            .with("brick", rect(0., 0.))
+            .with("stone", rect(16., 0.));
+        let style = StyleBuilder::new().texture_names(Some(names())).build();
+        let grid = parse_terrain("#").unwrap();
+        let config = ConfigBuilder::new().build();
+        assert_eq!(
+            style.resolve_atlas(&atlas).err(),
+            Some(AtlasError::MissingEntry("plaster".to_string()))
+        );
+        assert!(generate_mesh_with_atlas(&grid, &style, &atlas, &config).is_err());
+    }
+
+    #[test]
+    fn names_are_resolved_when_generating() {
+        let atlas = Atlas::empty()
+            .with("brick", rect(0., 0.))
+            .with("stone", rect(16., 0.))
+            .with("plaster", rect(32., 0.));
+        let style = StyleBuilder::new().texture_names(Some(names())).build();
+        let resolved = style.resolve_atlas(&atlas).unwrap();
+        assert_eq!(resolved.face_tex_top_left_px, vec2(0., 0.));
+        assert_eq!(resolved.top_tex_top_left_px, vec2(16., 0.));
+        assert_eq!(resolved.floor_tex_top_left_px, vec2(16., 0.));
+        assert_eq!(resolved.ceiling_tex_top_left_px, vec2(32., 0.));
+    }
+}
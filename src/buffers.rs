@@ -0,0 +1,254 @@
+use cgmath::{InnerSpace, Matrix3, Matrix4, Rad, SquareMatrix, Vector2, Vector3};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Attribute {
+    pub space_coord_px: Vector3<f32>,
+    pub tex_coord_px: Vector2<f32>,
+    pub normal: Vector3<f32>,
+    /// Identifies the material the vertex is rendered with (see `Style`)
+    pub material: u32,
+    /// Layer of the texture array the vertex samples from (see `Style`)
+    pub tex_layer: u32,
+    /// Ambient occlusion, from 0 (fully occluded) to 1 (not occluded at all).
+    /// Generated geometry has no occlusion until `bake_ao` is used.
+    pub ao: f32,
+}
+
+/// Returns the (unnormalized) normal of the front of the triangle abc. Front
+/// faces are wound clockwise when viewed from in front.
+pub fn face_normal(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    (c - a).cross(b - a)
+}
+
+/// Returns the matrix which transforms normals such that they stay
+/// perpendicular to surfaces transformed by `m`, up to their length. This is
+/// the inverse transpose of the linear part of `m`, scaled by its
+/// determinant, which unlike the inverse is defined even when `m` is
+/// singular.
+fn normal_matrix(m: Matrix4<f32>) -> Matrix3<f32> {
+    let (x, y, z) = (m.x.truncate(), m.y.truncate(), m.z.truncate());
+    let cofactor = Matrix3::from_cols(y.cross(z), z.cross(x), x.cross(y));
+    // a negative determinant would otherwise turn normals inside out
+    if Matrix3::from_cols(x, y, z).determinant() < 0. {
+        -cofactor
+    } else {
+        cofactor
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RelativeBuffers {
+    pub attributes: Vec<Attribute>,
+    pub indices: Vec<u32>,
+}
+
+impl RelativeBuffers {
+    pub fn concat(&self, b: &Self) -> Self {
+        let attributes = self
+            .attributes
+            .iter()
+            .chain(b.attributes.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let indices = self
+            .indices
+            .iter()
+            .cloned()
+            .chain(b.indices.iter().map(|i| i + self.attributes.len() as u32))
+            .collect::<Vec<_>>();
+        Self {
+            attributes,
+            indices,
+        }
+    }
+    /// Like `concat`, but appends `b` to the buffers of `self` rather than
+    /// copying both into new buffers
+    pub fn concat_into(mut self, b: Self) -> Self {
+        let offset = self.attributes.len() as u32;
+        self.indices.extend(b.indices.iter().map(|i| i + offset));
+        self.attributes.extend(b.attributes);
+        self
+    }
+    pub fn concat_all<I>(i: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+    {
+        // the buffers are collected first so the total size can be reserved
+        let buffers = i.into_iter().collect::<Vec<_>>();
+        let mut attributes =
+            Vec::with_capacity(buffers.iter().map(|b| b.attributes.len()).sum());
+        let mut indices =
+            Vec::with_capacity(buffers.iter().map(|b| b.indices.len()).sum());
+
+        for b in buffers {
+            indices.extend(b.indices.iter().map(|&i| i + attributes.len() as u32));
+            attributes.extend(b.attributes);
+        }
+
+        Self {
+            attributes,
+            indices,
+        }
+    }
+    pub fn transform(self, m: Matrix4<f32>) -> Self {
+        let Self {
+            mut attributes,
+            indices,
+        } = self;
+        let normal_matrix = normal_matrix(m);
+        attributes.iter_mut().for_each(|a| {
+            a.space_coord_px = (m * a.space_coord_px.extend(1.)).truncate();
+            let normal = normal_matrix * a.normal;
+            // surfaces flattened by a singular matrix keep their normal
+            if normal.magnitude2() > 0. {
+                a.normal = normal.normalize();
+            }
+        });
+        Self {
+            attributes,
+            indices,
+        }
+    }
+    pub fn translate(self, offset: Vector3<f32>) -> Self {
+        self.transform(Matrix4::from_translation(offset))
+    }
+    /// Rotates about the y axis by `radians`.
+    pub fn rotate_y(self, radians: f32) -> Self {
+        self.transform(Matrix4::from_angle_y(Rad(radians)))
+    }
+    /// Scales by `factor` about the origin.
+    pub fn scale(self, factor: Vector3<f32>) -> Self {
+        self.transform(Matrix4::from_nonuniform_scale(factor.x, factor.y, factor.z))
+    }
+    pub fn vertex_count(&self) -> usize {
+        self.attributes.len()
+    }
+    pub fn triangle_count(&self) -> usize {
+        debug_assert!(
+            self.indices.len().is_multiple_of(3),
+            "number of indices is not a multiple of 3"
+        );
+        self.indices.len() / 3
+    }
+    /// Iterates over the vertices of each triangle. Any indices after the
+    /// last whole triangle are ignored.
+    pub fn triangles(&self) -> impl Iterator<Item = [&Attribute; 3]> {
+        debug_assert!(
+            self.indices.len().is_multiple_of(3),
+            "number of indices is not a multiple of 3"
+        );
+        let attributes = &self.attributes;
+        let num_indices = self.indices.len() - self.indices.len() % 3;
+        self.indices[..num_indices].chunks(3).map(move |t| {
+            [
+                &attributes[t[0] as usize],
+                &attributes[t[1] as usize],
+                &attributes[t[2] as usize],
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec2, vec3};
+    use collision::aabb_mesh;
+
+    fn vertex(x: f32, z: f32) -> Attribute {
+        Attribute {
+            space_coord_px: vec3(x, 0., z),
+            tex_coord_px: vec2(x, z),
+            normal: vec3(0., 1., 0.),
+            material: 1,
+            tex_layer: 2,
+            ao: 0.5,
+        }
+    }
+
+    fn quad() -> RelativeBuffers {
+        RelativeBuffers {
+            attributes: vec![
+                vertex(0., 0.),
+                vertex(1., 0.),
+                vertex(1., 1.),
+                vertex(0., 1.),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    fn pieces() -> Vec<RelativeBuffers> {
+        let triangle = RelativeBuffers {
+            attributes: vec![vertex(0., 0.), vertex(2., 0.), vertex(2., 2.)],
+            indices: vec![0, 1, 2],
+        };
+        vec![quad(), triangle, quad().translate(vec3(1., 0., 0.))]
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let buffers = quad();
+        let json = ::serde_json::to_string(&buffers).unwrap();
+        let parsed: RelativeBuffers = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.attributes, buffers.attributes);
+        assert_eq!(parsed.indices, buffers.indices);
+    }
+
+    #[test]
+    fn triangles_of_a_quad() {
+        let buffers = quad();
+        let triangles = buffers.triangles().collect::<Vec<_>>();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1][1], &vertex(1., 1.));
+    }
+
+    #[test]
+    fn counts_of_a_quad() {
+        let buffers = quad();
+        assert_eq!(buffers.vertex_count(), 4);
+        assert_eq!(buffers.triangle_count(), 2);
+    }
+
+    #[test]
+    fn translate_then_scale() {
+        let buffers = quad().translate(vec3(1., 2., 0.)).scale(vec3(3., 1., 2.));
+        assert_eq!(buffers.attributes[2].space_coord_px, vec3(6., 2., 2.));
+    }
+
+    #[test]
+    fn scaled_box_normals_stay_axis_aligned() {
+        let buffers =
+            aabb_mesh(vec3(0., 0., 0.), vec3(1., 1., 1.)).scale(vec3(2., 1., 1.));
+        for a in buffers.attributes.iter() {
+            let n = a.normal;
+            assert_eq!(n.magnitude(), 1.);
+            assert_eq!(n.x.abs() + n.y.abs() + n.z.abs(), 1.);
+        }
+    }
+
+    #[test]
+    fn concat_all_equals_repeated_concat() {
+        let repeated = pieces().iter().fold(
+            RelativeBuffers {
+                attributes: Vec::new(),
+                indices: Vec::new(),
+            },
+            |acc, b| acc.concat(b),
+        );
+        assert_eq!(RelativeBuffers::concat_all(pieces()), repeated);
+    }
+
+    #[test]
+    fn concat_into_equals_concat() {
+        let pieces = pieces();
+        assert_eq!(
+            pieces[0].clone().concat_into(pieces[1].clone()),
+            pieces[0].concat(&pieces[1])
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use cgmath::{self, vec3, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+
+/// Returns a view-projection matrix which looks at the box with the minimum
+/// and maximum corners `bounds` (as returned by `RelativeBuffers::bounds`)
+/// from above, at the standard isometric angle, with the whole box in view.
+/// The centre of the box is mapped to the origin of clip space. The
+/// projection is orthographic with an aspect ratio of 1, so for other aspect
+/// ratios, scale the x axis of the result by the inverse of the aspect ratio.
+pub fn isometric_view_proj(bounds: (Vector3<f32>, Vector3<f32>)) -> Matrix4<f32> {
+    let (min, max) = bounds;
+    let centre = Point3::from_vec((min + max) / 2.);
+    // radius of a sphere containing the box, so it stays in view from any
+    // angle
+    let radius = ((max - min).magnitude() / 2.).max(f32::EPSILON);
+    // looking along (-1, -1, -1), which is halfway between the x and z
+    // axes, and tilted down by atan(1 / sqrt(2)) radians
+    let direction = vec3(1., 1., 1.).normalize();
+    let distance = radius * 2.;
+    let eye = centre + direction * distance;
+    let view = Matrix4::look_at(eye, centre, vec3(0., 1., 0.));
+    let projection = cgmath::ortho(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        distance - radius,
+        distance + radius,
+    );
+    projection * view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centre_maps_to_origin() {
+        let (min, max) = (vec3(0., 0., 0.), vec3(64., 32., 96.));
+        let view_proj = isometric_view_proj((min, max));
+        let centre = view_proj * ((min + max) / 2.).extend(1.);
+        let centre = centre.truncate() / centre.w;
+        assert!(centre.magnitude() < 1e-4);
+        // corners of the box stay within clip space
+        for &corner in [min, max, vec3(min.x, max.y, max.z)].iter() {
+            let p = view_proj * corner.extend(1.);
+            assert!(p.x.abs() <= p.w && p.y.abs() <= p.w && p.z.abs() <= p.w);
+        }
+    }
+}
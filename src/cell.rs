@@ -0,0 +1,1164 @@
+use buffers::RelativeBuffers;
+use cgmath::Vector2;
+use direction::{
+    CardinalDirection, CardinalDirections, OrdinalDirection, OrdinalDirections,
+};
+use geometry::{
+    junctions_share_tops, make_crown, make_faces, make_geometry, make_junction_top,
+    make_top, move_to_cell_centre, quarter_rotation, quarter_size_px, PieceHeights,
+};
+use grid_2d::{Coord, Grid, Size};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use style::{Config, Style};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CellType {
+    Wall,
+    Floor,
+    /// A wall with an opening through its middle, if the cell is part of a
+    /// straight run of wall. Door cells at corners or junctions are solid.
+    Door,
+    /// Like a door, but the opening doesn't extend to the floor or ceiling.
+    Window,
+    /// A wall running between opposite corners of the cell, with floor on
+    /// either side. Neighbouring walls don't connect to diagonal walls, but
+    /// diagonal walls in consecutive cells along the diagonal join up.
+    Diagonal(Diagonal),
+    /// A wall which is `Style::railing_height_px` tall rather than
+    /// `Style::height_px`. Railings connect to walls and other railings, and
+    /// walls next to railings have faces above the height of the railing.
+    Railing,
+    /// Stairs filling the cell, which climb from the floor to
+    /// `Style::height_px` in the given direction (see
+    /// `Config::stair_step_count`). Walls don't connect to stairs.
+    Stairs(CardinalDirection),
+    /// A ramp filling the cell, which slopes up from the floor to
+    /// `Style::height_px` in the given direction. Walls don't connect to
+    /// ramps.
+    Ramp(CardinalDirection),
+}
+
+impl CellType {
+    /// Returns true for cells which neighbouring walls connect to.
+    pub fn is_solid(self) -> bool {
+        match self {
+            CellType::Wall | CellType::Door | CellType::Window | CellType::Railing => {
+                true
+            }
+            CellType::Floor
+            | CellType::Diagonal(_)
+            | CellType::Stairs(_)
+            | CellType::Ramp(_) => false,
+        }
+    }
+
+    /// Returns true for cells which have floor in them.
+    pub fn has_floor(self) -> bool {
+        match self {
+            CellType::Floor | CellType::Diagonal(_) => true,
+            CellType::Wall
+            | CellType::Door
+            | CellType::Window
+            | CellType::Railing
+            | CellType::Stairs(_)
+            | CellType::Ramp(_) => false,
+        }
+    }
+}
+
+/// The corners of a cell which a diagonal wall runs between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Diagonal {
+    NorthWestToSouthEast,
+    NorthEastToSouthWest,
+}
+
+impl Diagonal {
+    /// Returns the directions of the two ends of the wall from the centre of
+    /// the cell.
+    pub fn ends(self) -> (OrdinalDirection, OrdinalDirection) {
+        match self {
+            Diagonal::NorthWestToSouthEast => {
+                (OrdinalDirection::NorthWest, OrdinalDirection::SouthEast)
+            }
+            Diagonal::NorthEastToSouthWest => {
+                (OrdinalDirection::NorthEast, OrdinalDirection::SouthWest)
+            }
+        }
+    }
+}
+
+// Inner:
+// #.
+// ##
+//
+// Outer:
+// ..
+// #.
+//
+// Left:
+// ..
+// ##
+//
+// Right:
+// #.
+// #.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Piece {
+    Inner,
+    Outer,
+    Left,
+    Right,
+    // Left and Right, with an opening in the wall
+    DoorwayLeft,
+    DoorwayRight,
+    WindowLeft,
+    WindowRight,
+    // A quarter of a round pillar, in cells with no solid neighbours (see
+    // `Config::pillar_segments`)
+    Pillar,
+}
+
+/// The neighbours passed to `Piece::choose` weren't in adjacent cardinal
+/// directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceError {
+    pub neigh_a: (CellType, CardinalDirection),
+    pub neigh_b: (CellType, CardinalDirection),
+}
+
+impl fmt::Display for PieceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "neighbours {:?} and {:?} aren't in adjacent directions",
+            self.neigh_a, self.neigh_b
+        )
+    }
+}
+
+impl Error for PieceError {}
+
+impl Piece {
+    /// Chooses the piece for the quarter of a wall cell between the
+    /// neighbours `neigh_a` and `neigh_b`, whose directions must be a quarter
+    /// turn apart.
+    pub fn choose(
+        neigh_a: (CellType, CardinalDirection),
+        neigh_b: (CellType, CardinalDirection),
+    ) -> Result<Self, PieceError> {
+        if neigh_a.1.left90() != neigh_b.1 && neigh_a.1.right90() != neigh_b.1 {
+            return Err(PieceError { neigh_a, neigh_b });
+        }
+
+        let (wall_direction, floor_direction) =
+            match (neigh_a.0.is_solid(), neigh_b.0.is_solid()) {
+                (false, false) => return Ok(Piece::Outer),
+                (true, true) => return Ok(Piece::Inner),
+                (true, false) => (neigh_a.1, neigh_b.1),
+                (false, true) => (neigh_b.1, neigh_a.1),
+            };
+
+        if wall_direction.right90() == floor_direction {
+            Ok(Piece::Right)
+        } else {
+            Ok(Piece::Left)
+        }
+    }
+
+    /// Returns the equivalent piece with an opening in the wall for straight
+    /// pieces, and the same piece otherwise.
+    pub fn with_doorway(self) -> Self {
+        match self {
+            Piece::Left => Piece::DoorwayLeft,
+            Piece::Right => Piece::DoorwayRight,
+            other => other,
+        }
+    }
+
+    /// Returns the equivalent piece with a window in the wall for straight
+    /// pieces, and the same piece otherwise.
+    pub fn with_window(self) -> Self {
+        match self {
+            Piece::Left => Piece::WindowLeft,
+            Piece::Right => Piece::WindowRight,
+            other => other,
+        }
+    }
+}
+
+/// Chooses the piece for each quarter of a wall cell, so the shapes of
+/// corners can be customized (see `CellDetails::from_grid_with_chooser`).
+pub trait PieceChooser {
+    /// Chooses the piece for the quarter of a wall cell between the
+    /// neighbours `neigh_a` and `neigh_b`, whose directions must be a quarter
+    /// turn apart, as in `Piece::choose`.
+    fn choose(
+        &self,
+        neigh_a: (CellType, CardinalDirection),
+        neigh_b: (CellType, CardinalDirection),
+    ) -> Result<Piece, PieceError>;
+}
+
+/// Chooses pieces with `Piece::choose`, as when no chooser is given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultPieceChooser;
+
+impl PieceChooser for DefaultPieceChooser {
+    fn choose(
+        &self,
+        neigh_a: (CellType, CardinalDirection),
+        neigh_b: (CellType, CardinalDirection),
+    ) -> Result<Piece, PieceError> {
+        Piece::choose(neigh_a, neigh_b)
+    }
+}
+
+/// The shape of part of a quarter, from the top of the previous step (or the
+/// floor) up to `top_px`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub piece: Piece,
+    pub top_px: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quarter {
+    /// The shape of the quarter at the top of the wall
+    pub piece: Piece,
+    /// Where a neighbouring wall is lower than this one, the part of the
+    /// quarter up to the neighbour's height treats the neighbour as wall, and
+    /// the part above treats it as floor, so the taller wall still has a full
+    /// face above its lower neighbour. Each such part below the top is a step,
+    /// ordered from the floor upwards.
+    pub steps: [Option<Step>; 2],
+}
+
+/// A wall cell where three or four straight runs of wall meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Junction {
+    /// Runs of wall leave in every direction except the given one
+    Tee(CardinalDirection),
+    /// Runs of wall leave in all four directions
+    Cross,
+}
+
+impl Junction {
+    /// Returns the junction formed by the solid neighbours of the cell at
+    /// `coord`, if it has at least three.
+    pub fn from_grid(
+        grid: &Grid<CellType>,
+        coord: Coord,
+        config: &Config,
+    ) -> Option<Self> {
+        let mut open = CardinalDirections.into_iter().fuse().filter(|direction| {
+            !cell_type_at(grid, coord + direction.coord(), config).is_solid()
+        });
+        match (open.next(), open.next()) {
+            (None, _) => Some(Junction::Cross),
+            (Some(direction), None) => Some(Junction::Tee(direction)),
+            (Some(_), Some(_)) => None,
+        }
+    }
+
+    /// Returns true if a run of wall leaves the junction in `direction`.
+    pub fn has_arm(self, direction: CardinalDirection) -> bool {
+        match self {
+            Junction::Tee(open) => open != direction,
+            Junction::Cross => true,
+        }
+    }
+
+    /// Returns true if `quarter` has the shape this junction gives the
+    /// quarter in `direction`, with no steps.
+    fn fits(self, quarter: &Quarter, direction: OrdinalDirection) -> bool {
+        let (card_a, card_b) = direction.to_cardinals();
+        let is_inner = self.has_arm(card_a) && self.has_arm(card_b);
+        let is_flat = quarter.steps.iter().all(Option::is_none);
+        is_flat
+            && match quarter.piece {
+                Piece::Inner => is_inner,
+                Piece::Left | Piece::Right => !is_inner,
+                _ => false,
+            }
+    }
+}
+
+/// Returns the coordinate of the cell to look up for `coord`, which wraps
+/// around the edges of a grid of size `size` if `config.wrap` is set.
+pub fn wrap_coord(coord: Coord, size: Size, config: &Config) -> Coord {
+    if !config.wrap || size.x() == 0 || size.y() == 0 {
+        return coord;
+    }
+    let wrap = |x: i32, n: u32| {
+        let n = n as i32;
+        ((x % n) + n) % n
+    };
+    Coord::new(wrap(coord.x, size.x()), wrap(coord.y, size.y()))
+}
+
+/// Returns the type of the cell at `coord`, or `config.border` if `coord` is
+/// outside the grid and doesn't wrap around.
+pub fn cell_type_at(grid: &Grid<CellType>, coord: Coord, config: &Config) -> CellType {
+    grid.get(wrap_coord(coord, grid.size(), config))
+        .cloned()
+        .unwrap_or(config.border)
+}
+
+impl Quarter {
+    pub fn from_grid(
+        grid: &Grid<CellType>,
+        coord: Coord,
+        direction: OrdinalDirection,
+        config: &Config,
+    ) -> Self {
+        Self::from_grid_with_chooser(grid, coord, direction, &DefaultPieceChooser, config)
+            .expect("cardinals of an ordinal direction are adjacent")
+    }
+
+    /// Like `from_grid`, but where the piece is chosen by `chooser`. Returns
+    /// the error from `chooser` if it fails to choose a piece.
+    pub fn from_grid_with_chooser<C: PieceChooser + ?Sized>(
+        grid: &Grid<CellType>,
+        coord: Coord,
+        direction: OrdinalDirection,
+        chooser: &C,
+        config: &Config,
+    ) -> Result<Self, PieceError> {
+        let (card_a, card_b) = direction.to_cardinals();
+        let cell_type_a = cell_type_at(grid, coord + card_a.coord(), config);
+        let cell_type_b = cell_type_at(grid, coord + card_b.coord(), config);
+        let piece = chooser.choose((cell_type_a, card_a), (cell_type_b, card_b))?;
+        Ok(Self {
+            piece,
+            steps: [None, None],
+        })
+    }
+
+    /// Like `from_grid`, but where walls have the heights in `heights`, which
+    /// must be the same size as `grid`. A solid border is treated as being as
+    /// tall as the wall it's next to.
+    pub fn from_grid_with_heights(
+        grid: &Grid<CellType>,
+        heights: &Grid<f32>,
+        coord: Coord,
+        direction: OrdinalDirection,
+        config: &Config,
+    ) -> Self {
+        Self::from_grid_with_heights_and_chooser(
+            grid,
+            heights,
+            coord,
+            direction,
+            &DefaultPieceChooser,
+            config,
+        )
+        .expect("cardinals of an ordinal direction are adjacent")
+    }
+
+    /// Like `from_grid_with_heights`, but where pieces are chosen by
+    /// `chooser`. Returns the error from `chooser` if it fails to choose a
+    /// piece.
+    pub fn from_grid_with_heights_and_chooser<C: PieceChooser + ?Sized>(
+        grid: &Grid<CellType>,
+        heights: &Grid<f32>,
+        coord: Coord,
+        direction: OrdinalDirection,
+        chooser: &C,
+        config: &Config,
+    ) -> Result<Self, PieceError> {
+        let (card_a, card_b) = direction.to_cardinals();
+        let height_px = heights.get(coord).cloned().unwrap_or(0.);
+        let neighbour = |card: CardinalDirection| {
+            let coord = coord + card.coord();
+            let cell_type = cell_type_at(grid, coord, config);
+            let height_px = if cell_type.is_solid() {
+                heights
+                    .get(wrap_coord(coord, heights.size(), config))
+                    .cloned()
+                    .unwrap_or(height_px)
+            } else {
+                0.
+            };
+            (cell_type, height_px)
+        };
+        let (cell_type_a, height_a) = neighbour(card_a);
+        let (cell_type_b, height_b) = neighbour(card_b);
+        // neighbours only count as wall up to their own height
+        let piece_up_to = |y: f32| {
+            let at_y = |cell_type, height| {
+                if height >= y {
+                    cell_type
+                } else {
+                    CellType::Floor
+                }
+            };
+            chooser.choose(
+                (at_y(cell_type_a, height_a), card_a),
+                (at_y(cell_type_b, height_b), card_b),
+            )
+        };
+        let (low, high) = if height_a <= height_b {
+            (height_a, height_b)
+        } else {
+            (height_b, height_a)
+        };
+        let mut steps = [None, None];
+        let mut num_steps = 0;
+        for &top_px in [low, high].iter() {
+            let is_new = num_steps == 0 || top_px > low;
+            if top_px > 0. && top_px < height_px && is_new {
+                steps[num_steps] = Some(Step {
+                    piece: piece_up_to(top_px)?,
+                    top_px,
+                });
+                num_steps += 1;
+            }
+        }
+        Ok(Self {
+            piece: piece_up_to(height_px)?,
+            steps,
+        })
+    }
+}
+
+/// The style and options with which each quarter of a cell is made (see
+/// `CellDetails::visit_quarters`)
+#[derive(Clone, Copy)]
+struct QuarterParams<'a> {
+    style: &'a Style,
+    config: &'a Config,
+    /// Whether the top of each quarter has a crown
+    with_crown: bool,
+    /// Whether the pieces of each quarter include their tops, which
+    /// distinguishes the keys of quarters
+    with_top: bool,
+}
+
+/// Identifies the geometry of a quarter before it's moved into place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct QuarterKey {
+    piece: Piece,
+    steps: [Option<(Piece, u32)>; 2],
+    direction: usize,
+    height_px: u32,
+    with_crown: bool,
+    with_top: bool,
+}
+
+impl QuarterKey {
+    fn new(
+        quarter: &Quarter,
+        direction: OrdinalDirection,
+        height_px: f32,
+        params: QuarterParams,
+    ) -> Self {
+        let step = |step: &Option<Step>| step.map(|s| (s.piece, s.top_px.to_bits()));
+        Self {
+            piece: quarter.piece,
+            steps: [step(&quarter.steps[0]), step(&quarter.steps[1])],
+            direction: direction as usize,
+            height_px: height_px.to_bits(),
+            with_crown: params.with_crown,
+            with_top: params.with_top,
+        }
+    }
+}
+
+/// The geometry of each shape of quarter in a grid, made before the grid is
+/// generated so cells whose quarters have the same shapes can share them
+/// rather than making them again (see `CellDetails::make_geometry_cached`).
+/// Once made, the cache is only read, so it can be shared between threads
+/// without locking. Cells with their own style (see `Style::for_cell`) don't
+/// use the cache, and quarters missing from it are made as usual. A cache
+/// must only be used with the `Style` and `Config` it was made with.
+#[derive(Debug, Clone)]
+pub struct PieceCache {
+    quarters: HashMap<QuarterKey, RelativeBuffers>,
+}
+
+impl PieceCache {
+    /// Makes each shape of quarter of the cells in `detail_grid`
+    pub fn new(
+        detail_grid: &Grid<Option<CellDetails>>,
+        style: &Style,
+        config: &Config,
+    ) -> Self {
+        let mut quarters = HashMap::new();
+        for (coord, cell) in detail_grid.enumerate() {
+            if let Some(ref cell) = *cell {
+                cell.visit_geometry_quarters(coord, style, config, |_, key, make| {
+                    if let Some(key) = key {
+                        quarters.entry(key).or_insert_with(make);
+                    }
+                });
+            }
+        }
+        Self { quarters }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellDetails {
+    pub quarters: [Quarter; 4],
+    /// The height of the wall, or `None` to use `Style::height_px`
+    pub height_px: Option<f32>,
+    /// The junction at the cell if its quarters have the shapes it gives
+    /// them, in which case the quarters share a single top
+    pub junction: Option<Junction>,
+}
+
+impl CellDetails {
+    pub fn outer() -> Self {
+        let quarter = Quarter {
+            piece: Piece::Outer,
+            steps: [None, None],
+        };
+        Self {
+            quarters: [quarter, quarter, quarter, quarter],
+            height_px: None,
+            junction: None,
+        }
+    }
+    fn from_quarters<F>(
+        cell_type: CellType,
+        height_px: Option<f32>,
+        junction: Option<Junction>,
+        config: &Config,
+        mut f: F,
+    ) -> Result<Self, PieceError>
+    where
+        F: FnMut(OrdinalDirection) -> Result<Quarter, PieceError>,
+    {
+        let mut cell_details = Self::outer();
+        cell_details.height_px = height_px;
+        for o in OrdinalDirections {
+            let mut quarter = f(o)?;
+            // openings are only made in walls with no steps
+            if quarter.steps.iter().all(Option::is_none) {
+                match cell_type {
+                    CellType::Door => quarter.piece = quarter.piece.with_doorway(),
+                    CellType::Window => quarter.piece = quarter.piece.with_window(),
+                    CellType::Wall
+                    | CellType::Floor
+                    | CellType::Diagonal(_)
+                    | CellType::Railing
+                    | CellType::Stairs(_)
+                    | CellType::Ramp(_) => (),
+                }
+            }
+            cell_details.quarters[o as usize] = quarter;
+        }
+        // cells whose quarters are all outer corners have no solid neighbours
+        let is_isolated = cell_details
+            .quarters
+            .iter()
+            .all(|quarter| quarter.piece == Piece::Outer);
+        if config.pillar_segments > 0 && is_isolated {
+            for quarter in cell_details.quarters.iter_mut() {
+                quarter.piece = Piece::Pillar;
+            }
+        }
+        cell_details.junction = junction.filter(|junction| {
+            OrdinalDirections
+                .into_iter()
+                .all(|o| junction.fits(&cell_details.quarters[o as usize], o))
+        });
+        Ok(cell_details)
+    }
+    pub fn from_grid(
+        grid: &Grid<CellType>,
+        coord: Coord,
+        config: &Config,
+    ) -> Option<Self> {
+        Self::from_grid_with_chooser(grid, coord, &DefaultPieceChooser, config)
+            .expect("cardinals of an ordinal direction are adjacent")
+    }
+    /// Like `from_grid`, but where the piece of each quarter is chosen by
+    /// `chooser`. Doorways, windows and pillars are made from the chosen
+    /// pieces as usual. Returns the error from `chooser` if it fails to
+    /// choose a piece.
+    pub fn from_grid_with_chooser<C: PieceChooser + ?Sized>(
+        grid: &Grid<CellType>,
+        coord: Coord,
+        chooser: &C,
+        config: &Config,
+    ) -> Result<Option<Self>, PieceError> {
+        let cell_type = grid.get(coord).cloned().unwrap_or(CellType::Floor);
+        if !cell_type.is_solid() {
+            return Ok(None);
+        }
+        let junction = Junction::from_grid(grid, coord, config);
+        Self::from_quarters(cell_type, None, junction, config, |o| {
+            Quarter::from_grid_with_chooser(grid, coord, o, chooser, config)
+        })
+        .map(Some)
+    }
+    /// Like `from_grid`, but where walls have the heights in `heights`, which
+    /// must be the same size as `grid`.
+    pub fn from_grid_with_heights(
+        grid: &Grid<CellType>,
+        heights: &Grid<f32>,
+        coord: Coord,
+        config: &Config,
+    ) -> Option<Self> {
+        Self::from_grid_with_heights_and_chooser(
+            grid,
+            heights,
+            coord,
+            &DefaultPieceChooser,
+            config,
+        )
+        .expect("cardinals of an ordinal direction are adjacent")
+    }
+    /// Like `from_grid_with_heights`, but where the piece of each quarter is
+    /// chosen by `chooser`. Returns the error from `chooser` if it fails to
+    /// choose a piece.
+    pub fn from_grid_with_heights_and_chooser<C: PieceChooser + ?Sized>(
+        grid: &Grid<CellType>,
+        heights: &Grid<f32>,
+        coord: Coord,
+        chooser: &C,
+        config: &Config,
+    ) -> Result<Option<Self>, PieceError> {
+        let cell_type = grid.get(coord).cloned().unwrap_or(CellType::Floor);
+        if !cell_type.is_solid() {
+            return Ok(None);
+        }
+        let height_px = heights.get(coord).cloned();
+        let junction = Junction::from_grid(grid, coord, config);
+        Self::from_quarters(cell_type, height_px, junction, config, |o| {
+            Quarter::from_grid_with_heights_and_chooser(
+                grid, heights, coord, o, chooser, config,
+            )
+        })
+        .map(Some)
+    }
+    /// Calls `f` with the style of the cell (see `Style::for_cell`), and the
+    /// piece, direction, quarter size, heights and bevel of each step and the
+    /// top of each quarter, and calls `visit` with the direction of each
+    /// quarter, the key identifying its shape if it can be shared with other
+    /// cells, and a function which combines the results of `f` for the
+    /// quarter before it's moved into place. Only the top of each quarter is
+    /// bevelled, and has a crown if `params.with_crown` is set.
+    /// `params.with_top` is whether `f` makes the tops of pieces.
+    fn visit_quarters<F, V>(
+        &self,
+        coord: Coord,
+        params: QuarterParams,
+        f: F,
+        mut visit: V,
+    ) where
+        F: Fn(
+            &Style,
+            Piece,
+            OrdinalDirection,
+            Vector2<f32>,
+            PieceHeights,
+        ) -> RelativeBuffers,
+        V: FnMut(OrdinalDirection, Option<QuarterKey>, &dyn Fn() -> RelativeBuffers),
+    {
+        let style = params.style.for_cell(coord, params.config);
+        // cells with a style of their own can't share quarters with others
+        let shared = match style {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        };
+        let height_px = self.height_px.unwrap_or(style.height_px);
+        let params = QuarterParams {
+            style: &style,
+            ..params
+        };
+        for (o, q) in OrdinalDirections.into_iter().zip(self.quarters.iter()) {
+            let key = if shared {
+                Some(QuarterKey::new(q, o, height_px, params))
+            } else {
+                None
+            };
+            visit(o, key, &|| Self::make_quarter(q, o, height_px, params, &f));
+        }
+    }
+    /// Like `visit_quarters`, but returns the results for each quarter
+    /// combined and moved into place.
+    fn make_quarters<F>(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+        with_crown: bool,
+        f: F,
+    ) -> Vec<RelativeBuffers>
+    where
+        F: Fn(
+            &Style,
+            Piece,
+            OrdinalDirection,
+            Vector2<f32>,
+            PieceHeights,
+        ) -> RelativeBuffers,
+    {
+        let translate = move_to_cell_centre(coord, config);
+        let params = QuarterParams {
+            style,
+            config,
+            with_crown,
+            with_top: false,
+        };
+        let mut quarters = Vec::new();
+        self.visit_quarters(coord, params, f, |o, _, make| {
+            quarters.push(make().transform(translate * quarter_rotation(o)));
+        });
+        quarters
+    }
+    /// Calls `f` for each step and the top of the quarter `q` in the direction
+    /// `o` (see `visit_quarters`), and returns the results combined.
+    fn make_quarter<F>(
+        q: &Quarter,
+        o: OrdinalDirection,
+        height_px: f32,
+        params: QuarterParams,
+        f: &F,
+    ) -> RelativeBuffers
+    where
+        F: Fn(
+            &Style,
+            Piece,
+            OrdinalDirection,
+            Vector2<f32>,
+            PieceHeights,
+        ) -> RelativeBuffers,
+    {
+        let QuarterParams {
+            style,
+            config,
+            with_crown,
+            ..
+        } = params;
+        let size = quarter_size_px(o, config);
+        let mut bottom_px = 0.;
+        let mut parts = Vec::new();
+        for step in q.steps.iter().filter_map(|s| s.as_ref()) {
+            let heights = PieceHeights {
+                bottom_px,
+                top_px: step.top_px,
+                bevel_px: 0.,
+            };
+            parts.push(f(style, step.piece, o, size, heights));
+            bottom_px = step.top_px;
+        }
+        let bevel_px = style.top_bevel_px.min(height_px - bottom_px);
+        let heights = PieceHeights {
+            bottom_px,
+            top_px: height_px,
+            bevel_px,
+        };
+        parts.push(f(style, q.piece, o, size, heights));
+        if with_crown {
+            parts.push(make_crown(
+                q.piece,
+                size,
+                bottom_px,
+                height_px - bevel_px,
+                style,
+                config,
+            ));
+        }
+        RelativeBuffers::concat_all(parts)
+    }
+    /// Returns the single top of the quarters of a junction, or `None` if each
+    /// quarter has its own top.
+    fn make_junction_top(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+    ) -> Option<RelativeBuffers> {
+        let junction = self.junction?;
+        let style = style.for_cell(coord, config);
+        let height_px = self.height_px.unwrap_or(style.height_px);
+        make_junction_top(junction, height_px, &style, config)
+            .map(|top| top.transform(move_to_cell_centre(coord, config)))
+    }
+    /// Returns true if the quarters of the cell share a single top (see
+    /// `make_junction_top`), without making it.
+    fn has_junction_top(&self, coord: Coord, style: &Style, config: &Config) -> bool {
+        self.junction.is_some()
+            && junctions_share_tops(&style.for_cell(coord, config), config)
+    }
+    /// Calls `visit` for each quarter of the geometry of the cell (see
+    /// `visit_quarters`). The quarters of a junction which share a single top
+    /// are made without their own tops.
+    fn visit_geometry_quarters<V>(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+        visit: V,
+    ) where
+        V: FnMut(OrdinalDirection, Option<QuarterKey>, &dyn Fn() -> RelativeBuffers),
+    {
+        let with_top = !self.has_junction_top(coord, style, config);
+        let params = QuarterParams {
+            style,
+            config,
+            with_crown: true,
+            with_top,
+        };
+        if with_top {
+            self.visit_quarters(
+                coord,
+                params,
+                |style, piece, direction, size, heights| {
+                    make_geometry(piece, direction, size, heights, style, config)
+                },
+                visit,
+            );
+        } else {
+            self.visit_quarters(
+                coord,
+                params,
+                |style, piece, direction, size, heights| {
+                    make_faces(piece, direction, size, heights, style, config)
+                },
+                visit,
+            );
+        }
+    }
+    pub fn make_geometry(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+    ) -> Vec<RelativeBuffers> {
+        self.make_geometry_with_cache(coord, style, config, None)
+    }
+    /// Like `make_geometry`, but where quarters in `cache` are copied from
+    /// it, rather than made again. The result is the same as that of
+    /// `make_geometry`.
+    pub fn make_geometry_cached(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+        cache: &PieceCache,
+    ) -> Vec<RelativeBuffers> {
+        self.make_geometry_with_cache(coord, style, config, Some(cache))
+    }
+    fn make_geometry_with_cache(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+        cache: Option<&PieceCache>,
+    ) -> Vec<RelativeBuffers> {
+        let translate = move_to_cell_centre(coord, config);
+        let mut parts = Vec::new();
+        self.visit_geometry_quarters(coord, style, config, |o, key, make| {
+            let cached = match (cache, key) {
+                (Some(cache), Some(key)) => cache.quarters.get(&key),
+                _ => None,
+            };
+            let quarter = match cached {
+                Some(quarter) => quarter.clone(),
+                None => make(),
+            };
+            parts.push(quarter.transform(translate * quarter_rotation(o)));
+        });
+        parts.extend(self.make_junction_top(coord, style, config));
+        parts
+    }
+    /// Like `make_geometry`, but only the horizontal tops of the quarters
+    pub fn make_tops(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+    ) -> Vec<RelativeBuffers> {
+        if let Some(top) = self.make_junction_top(coord, style, config) {
+            return vec![top];
+        }
+        self.make_quarters(
+            coord,
+            style,
+            config,
+            false,
+            |style, piece, _, size, heights| {
+                make_top(piece, size, heights.top_px, heights.bevel_px, style, config)
+            },
+        )
+    }
+    /// Like `make_geometry`, but only the vertical faces of the quarters,
+    /// including any bevels
+    pub fn make_faces(
+        &self,
+        coord: Coord,
+        style: &Style,
+        config: &Config,
+    ) -> Vec<RelativeBuffers> {
+        self.make_quarters(
+            coord,
+            style,
+            config,
+            true,
+            |style, piece, direction, size, heights| {
+                make_faces(piece, direction, size, heights, style, config)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    const TERRAIN: &str = "\
+#########
+#...#...#
+#.#####.#
+#...#...#
+##+##=###
+#.......#
+#########
+";
+
+    #[test]
+    fn cached_geometry_equals_uncached() {
+        let grid = parse_terrain(TERRAIN).unwrap();
+        let config = ConfigBuilder::new().corner_segments(3).build();
+        let detail_grid = Grid::new_from_fn(grid.size(), |coord| {
+            CellDetails::from_grid(&grid, coord, &config)
+        });
+        // junctions only share a single top when the top isn't bevelled
+        for &bevel_px in [0., 1.].iter() {
+            let style = StyleBuilder::new()
+                .width_px(4.)
+                .top_bevel_px(bevel_px)
+                .build();
+            let cache = PieceCache::new(&detail_grid, &style, &config);
+            for (coord, cell) in detail_grid.enumerate() {
+                if let Some(ref cell) = *cell {
+                    assert_eq!(
+                        cell.make_geometry_cached(coord, &style, &config, &cache),
+                        cell.make_geometry(coord, &style, &config)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn opposing_neighbours_are_an_error() {
+        let neigh_a = (CellType::Wall, CardinalDirection::North);
+        let neigh_b = (CellType::Floor, CardinalDirection::South);
+        assert_eq!(
+            Piece::choose(neigh_a, neigh_b),
+            Err(PieceError { neigh_a, neigh_b })
+        );
+        let neigh_b = (CellType::Floor, CardinalDirection::North);
+        assert!(Piece::choose(neigh_a, neigh_b).is_err());
+    }
+
+    #[test]
+    fn border_determines_pieces_at_the_edge() {
+        let grid = parse_terrain("#\n").unwrap();
+        let piece = |border| {
+            let config = ConfigBuilder::new().border(border).build();
+            Quarter::from_grid(
+                &grid,
+                Coord::new(0, 0),
+                OrdinalDirection::NorthWest,
+                &config,
+            )
+            .piece
+        };
+        assert_eq!(piece(CellType::Floor), Piece::Outer);
+        assert_eq!(piece(CellType::Wall), Piece::Inner);
+    }
+
+    #[test]
+    fn wrapping_connects_opposite_edges() {
+        let grid = parse_terrain("#.#\n").unwrap();
+        let piece = |wrap| {
+            let config = ConfigBuilder::new().wrap(wrap).build();
+            Quarter::from_grid(
+                &grid,
+                Coord::new(0, 0),
+                OrdinalDirection::NorthWest,
+                &config,
+            )
+            .piece
+        };
+        assert_eq!(piece(false), Piece::Outer);
+        // the cell to the west is the wall at the other end of the grid, and
+        // the cell to the north is the cell itself
+        assert_eq!(piece(true), Piece::Inner);
+    }
+
+    #[test]
+    fn isolated_walls_are_pillars() {
+        let grid = parse_terrain("...\n.#.\n...\n").unwrap();
+        let coord = Coord::new(1, 1);
+        let style = StyleBuilder::new().width_px(4.).build();
+        let cell = |pillar_segments| {
+            let config = ConfigBuilder::new()
+                .pillar_segments(pillar_segments)
+                .build();
+            let cell = CellDetails::from_grid(&grid, coord, &config).unwrap();
+            let vertex_count = cell
+                .make_geometry(coord, &style, &config)
+                .iter()
+                .map(|buffers| buffers.attributes.len())
+                .sum::<usize>();
+            (cell, vertex_count)
+        };
+        let (post, post_vertex_count) = cell(0);
+        let (pillar, pillar_vertex_count) = cell(4);
+        for (post_quarter, pillar_quarter) in
+            post.quarters.iter().zip(pillar.quarters.iter())
+        {
+            assert_eq!(post_quarter.piece, Piece::Outer);
+            assert_eq!(pillar_quarter.piece, Piece::Pillar);
+        }
+        // the round outline has more corners than the square one
+        assert!(pillar_vertex_count > post_vertex_count);
+        // walls with a solid neighbour aren't pillars
+        let grid = parse_terrain("...\n.##\n...\n").unwrap();
+        let config = ConfigBuilder::new().pillar_segments(4).build();
+        let cell = CellDetails::from_grid(&grid, coord, &config).unwrap();
+        assert!(cell
+            .quarters
+            .iter()
+            .all(|quarter| quarter.piece != Piece::Pillar));
+    }
+
+    #[test]
+    fn default_chooser_matches_piece_choose() {
+        let mut cell_types = vec![
+            CellType::Wall,
+            CellType::Floor,
+            CellType::Door,
+            CellType::Window,
+            CellType::Railing,
+            CellType::Diagonal(Diagonal::NorthWestToSouthEast),
+            CellType::Diagonal(Diagonal::NorthEastToSouthWest),
+        ];
+        for direction in CardinalDirections {
+            cell_types.push(CellType::Stairs(direction));
+            cell_types.push(CellType::Ramp(direction));
+        }
+        let chooser: &dyn PieceChooser = &DefaultPieceChooser;
+        for &type_a in cell_types.iter() {
+            for &type_b in cell_types.iter() {
+                for dir_a in CardinalDirections {
+                    for dir_b in CardinalDirections {
+                        let (neigh_a, neigh_b) = ((type_a, dir_a), (type_b, dir_b));
+                        assert_eq!(
+                            chooser.choose(neigh_a, neigh_b),
+                            Piece::choose(neigh_a, neigh_b)
+                        );
+                    }
+                }
+            }
+        }
+        let grid = parse_terrain(TERRAIN).unwrap();
+        let config = ConfigBuilder::new().build();
+        for (coord, _) in grid.enumerate() {
+            assert_eq!(
+                CellDetails::from_grid_with_chooser(&grid, coord, chooser, &config),
+                Ok(CellDetails::from_grid(&grid, coord, &config))
+            );
+        }
+    }
+
+    /// Refuses to choose a piece next to doors
+    struct NoDoorChooser;
+
+    impl PieceChooser for NoDoorChooser {
+        fn choose(
+            &self,
+            neigh_a: (CellType, CardinalDirection),
+            neigh_b: (CellType, CardinalDirection),
+        ) -> Result<Piece, PieceError> {
+            if neigh_a.0 == CellType::Door || neigh_b.0 == CellType::Door {
+                return Err(PieceError { neigh_a, neigh_b });
+            }
+            Piece::choose(neigh_a, neigh_b)
+        }
+    }
+
+    #[test]
+    fn chooser_errors_are_returned() {
+        let grid = parse_terrain("#+#\n").unwrap();
+        let heights = Grid::new_clone(grid.size(), 16.);
+        let config = ConfigBuilder::new().build();
+        let results = |coord| {
+            [
+                CellDetails::from_grid_with_chooser(
+                    &grid,
+                    coord,
+                    &NoDoorChooser,
+                    &config,
+                ),
+                CellDetails::from_grid_with_heights_and_chooser(
+                    &grid,
+                    &heights,
+                    coord,
+                    &NoDoorChooser,
+                    &config,
+                ),
+            ]
+        };
+        for result in results(Coord::new(0, 0)).iter() {
+            let error = result.as_ref().unwrap_err();
+            assert!([error.neigh_a, error.neigh_b]
+                .contains(&(CellType::Door, CardinalDirection::East)));
+        }
+        // the door itself has no doors next to it
+        for result in results(Coord::new(1, 0)).iter() {
+            assert!(result.as_ref().unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn cross_junction_top_has_no_gap_or_overlap() {
+        let grid = parse_terrain(".#.\n###\n.#.\n").unwrap();
+        let coord = Coord::new(1, 1);
+        let style = StyleBuilder::new().width_px(4.).top_bevel_px(0.).build();
+        let config = ConfigBuilder::new().cell_size_px(vec2(32., 32.)).build();
+        let cell = CellDetails::from_grid(&grid, coord, &config).unwrap();
+        assert_eq!(cell.junction, Some(Junction::Cross));
+        let tops = RelativeBuffers::concat_all(cell.make_tops(coord, &style, &config));
+        let w = style.width_px;
+        // the top lies within the two runs of wall crossing the cell
+        for a in tops.attributes.iter() {
+            let p = a.space_coord_px;
+            assert_eq!(p.y, style.height_px);
+            assert!((p.x - 48.).abs() <= w || (p.z - 48.).abs() <= w, "{:?}", p);
+        }
+        // and covers them exactly once, so its area is that of the two runs
+        // less the square where they cross
+        let area = tops
+            .triangles()
+            .map(|t| {
+                let (a, b, c) = (
+                    t[0].space_coord_px,
+                    t[1].space_coord_px,
+                    t[2].space_coord_px,
+                );
+                ((b - a).cross(c - a)).y.abs() / 2.
+            })
+            .sum::<f32>();
+        assert_eq!(area, 2. * (2. * w * 32.) - (2. * w) * (2. * w));
+    }
+}
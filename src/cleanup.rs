@@ -0,0 +1,193 @@
+use buffers::{face_normal, RelativeBuffers};
+use cgmath::{vec3, InnerSpace};
+use std::collections::HashMap;
+
+// Normals are compared at a fixed precision regardless of the position epsilon.
+pub const NORMAL_EPSILON: f32 = 1. / 1024.;
+
+pub fn quantize(x: f32, epsilon: f32) -> i64 {
+    (x / epsilon).round() as i64
+}
+
+impl RelativeBuffers {
+    /// Snaps each component of the position of each vertex to the nearest
+    /// multiple of `grid_px`, so vertices which should coincide but differ
+    /// by floating point error after transformation end up with identical
+    /// positions, and are merged by `weld`.
+    pub fn quantize_positions(&mut self, grid_px: f32) {
+        // grids such as 0.001 aren't exactly representable, so when the grid
+        // divides a pixel a whole number of times, snap to fractions of a
+        // pixel instead, keeping whole positions exact
+        let mut steps_per_px = 1. / grid_px as f64;
+        if (steps_per_px - steps_per_px.round()).abs() < steps_per_px * 1e-6 {
+            steps_per_px = steps_per_px.round();
+        }
+        // adding zero turns negative zero into zero
+        let snap =
+            |x: f32| ((x as f64 * steps_per_px).round() / steps_per_px) as f32 + 0.;
+        for a in self.attributes.iter_mut() {
+            let p = a.space_coord_px;
+            a.space_coord_px = vec3(snap(p.x), snap(p.y), snap(p.z));
+        }
+    }
+
+    /// Merges vertices whose positions and texture coordinates are equal
+    /// after snapping to multiples of `position_epsilon`, rewriting the
+    /// indices to refer to the merged vertices. Vertices with different
+    /// normals (such as either side of a corner), materials, texture layers
+    /// or ambient occlusion are kept separate.
+    pub fn weld(&mut self, position_epsilon: f32) {
+        let mut index_by_key = HashMap::new();
+        let mut attributes = Vec::new();
+        let remap = self
+            .attributes
+            .iter()
+            .map(|a| {
+                let p = a.space_coord_px;
+                let t = a.tex_coord_px;
+                let n = a.normal;
+                let key = (
+                    [
+                        quantize(p.x, position_epsilon),
+                        quantize(p.y, position_epsilon),
+                        quantize(p.z, position_epsilon),
+                    ],
+                    [
+                        quantize(t.x, position_epsilon),
+                        quantize(t.y, position_epsilon),
+                    ],
+                    [
+                        quantize(n.x, NORMAL_EPSILON),
+                        quantize(n.y, NORMAL_EPSILON),
+                        quantize(n.z, NORMAL_EPSILON),
+                    ],
+                    a.material,
+                    a.tex_layer,
+                    quantize(a.ao, NORMAL_EPSILON),
+                );
+                *index_by_key.entry(key).or_insert_with(|| {
+                    attributes.push(*a);
+                    attributes.len() as u32 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        for i in self.indices.iter_mut() {
+            *i = remap[*i as usize];
+        }
+        self.attributes = attributes;
+    }
+
+    /// Removes triangles whose area is less than `area_epsilon`, such as
+    /// triangles with two vertices in the same place. Vertices aren't
+    /// removed, even if they are no longer referred to by any triangle (see
+    /// `compact`).
+    pub fn remove_degenerate(&mut self, area_epsilon: f32) {
+        let attributes = &self.attributes;
+        let indices = self
+            .indices
+            .chunks(3)
+            .filter(|triangle| {
+                let position = |i: usize| attributes[triangle[i] as usize].space_coord_px;
+                let area =
+                    face_normal(position(0), position(1), position(2)).magnitude() / 2.;
+                area >= area_epsilon
+            })
+            .flat_map(|triangle| triangle.iter().cloned())
+            .collect();
+        self.indices = indices;
+    }
+
+    /// Removes vertices which aren't referred to by any triangle, rewriting
+    /// the indices to refer to the remaining vertices. The remaining vertices
+    /// keep their relative order.
+    pub fn compact(&mut self) {
+        let mut referenced = vec![false; self.attributes.len()];
+        for &i in self.indices.iter() {
+            referenced[i as usize] = true;
+        }
+        let mut remap = vec![0; self.attributes.len()];
+        let mut attributes = Vec::new();
+        for (i, a) in self.attributes.iter().enumerate() {
+            if referenced[i] {
+                remap[i] = attributes.len() as u32;
+                attributes.push(*a);
+            }
+        }
+        for i in self.indices.iter_mut() {
+            *i = remap[*i as usize];
+        }
+        self.attributes = attributes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cgmath::vec2;
+
+    fn vertex(x: f32, z: f32) -> Attribute {
+        Attribute {
+            space_coord_px: vec3(x, 0., z),
+            tex_coord_px: vec2(x, z),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        }
+    }
+
+    fn triangle() -> Vec<Attribute> {
+        vec![vertex(0., 0.), vertex(1., 0.), vertex(1., 1.)]
+    }
+
+    #[test]
+    fn weld_identical_triangles() {
+        let mut buffers = RelativeBuffers {
+            attributes: triangle().into_iter().chain(triangle()).collect(),
+            indices: vec![0, 1, 2, 3, 4, 5],
+        };
+        buffers.weld(1. / 1024.);
+        assert_eq!(buffers.attributes, triangle());
+        assert_eq!(buffers.indices, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn collapsed_triangles_are_removed() {
+        let mut buffers = RelativeBuffers {
+            attributes: triangle(),
+            indices: vec![0, 1, 2, 0, 1, 1],
+        };
+        buffers.remove_degenerate(1. / 1024.);
+        assert_eq!(buffers.indices, vec![0, 1, 2]);
+        assert_eq!(buffers.attributes, triangle());
+    }
+
+    #[test]
+    fn compact_removes_unreferenced_vertices() {
+        let mut buffers = RelativeBuffers {
+            attributes: vec![
+                vertex(0., 0.),
+                vertex(5., 5.),
+                vertex(1., 0.),
+                vertex(1., 1.),
+            ],
+            indices: vec![0, 2, 3],
+        };
+        buffers.compact();
+        assert_eq!(buffers.attributes, triangle());
+        assert_eq!(buffers.indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn quantize_positions_to_grid() {
+        let mut buffers = RelativeBuffers {
+            attributes: vec![vertex(16.000002, -0.0004)],
+            indices: Vec::new(),
+        };
+        buffers.quantize_positions(0.001);
+        let p = buffers.attributes[0].space_coord_px;
+        assert_eq!(p.x, 16.);
+        assert_eq!(p.z.to_bits(), 0f32.to_bits());
+    }
+}
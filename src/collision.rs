@@ -0,0 +1,159 @@
+use buffers::{face_normal, Attribute, RelativeBuffers};
+use cell::CellType;
+use cgmath::{vec2, vec3, vec4, InnerSpace, Vector3};
+use geometry::move_to_cell_centre;
+use grid_2d::{Coord, Grid};
+use style::{Config, Style};
+
+fn cell_aabb(
+    coord: Coord,
+    height_px: f32,
+    config: &Config,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let transform = move_to_cell_centre(coord, config);
+    let (hx, hz) = (config.cell_size_px.x / 2., config.cell_size_px.y / 2.);
+    let corner = |x: f32, y: f32, z: f32| (transform * vec4(x, y, z, 1.)).truncate();
+    (corner(-hx, 0., -hz), corner(hx, height_px, hz))
+}
+
+/// Returns the coordinate and the minimum and maximum corners of the box
+/// occupied by each solid cell in `grid`, with the height of the walls and
+/// railings taken from `style`. Diagonal walls aren't included, as boxes fit
+/// them poorly.
+pub fn wall_cell_aabbs(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> Vec<(Coord, Vector3<f32>, Vector3<f32>)> {
+    grid.enumerate()
+        .filter(|&(_, &cell_type)| cell_type.is_solid())
+        .map(|(coord, &cell_type)| {
+            let height_px = match cell_type {
+                CellType::Railing => style.railing_height_px,
+                _ => style.height_px,
+            };
+            let (min, max) = cell_aabb(coord, height_px, config);
+            (coord, min, max)
+        })
+        .collect()
+}
+
+/// Like `wall_cell_aabbs`, but where the height of each wall is taken from
+/// `heights`, which must be the same size as `grid`.
+pub fn wall_cell_aabbs_with_heights(
+    grid: &Grid<CellType>,
+    heights: &Grid<f32>,
+    config: &Config,
+) -> Vec<(Coord, Vector3<f32>, Vector3<f32>)> {
+    grid.enumerate()
+        .filter(|&(_, &cell_type)| cell_type.is_solid())
+        .map(|(coord, _)| {
+            let height_px = heights.get(coord).cloned().unwrap_or(0.);
+            let (min, max) = cell_aabb(coord, height_px, config);
+            (coord, min, max)
+        })
+        .collect()
+}
+
+/// Makes a closed box with the minimum and maximum corners `min` and `max`,
+/// with each side facing outwards. Texture coordinates are all 0.
+pub fn aabb_mesh(min: Vector3<f32>, max: Vector3<f32>) -> RelativeBuffers {
+    let corner = |i: usize| {
+        vec3(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    };
+    // corners of each side, in order around its perimeter, where bit 0 of the
+    // corner index selects x, bit 1 selects y and bit 2 selects z
+    const SIDES: [[usize; 4]; 6] = [
+        [0, 2, 6, 4],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 5, 7, 6],
+    ];
+    let centre = (min + max) / 2.;
+    let mut attributes = Vec::new();
+    let mut indices = Vec::new();
+    for side in SIDES.iter() {
+        let corners = [
+            corner(side[0]),
+            corner(side[1]),
+            corner(side[2]),
+            corner(side[3]),
+        ];
+        let side_centre = (corners[0] + corners[2]) / 2.;
+        let outwards = side_centre - centre;
+        let reversed = face_normal(corners[0], corners[1], corners[2]).dot(outwards) < 0.;
+        let order: &[u32] = if reversed {
+            &[0, 2, 1, 0, 3, 2]
+        } else {
+            &[0, 1, 2, 0, 2, 3]
+        };
+        indices.extend(order.iter().map(|i| i + attributes.len() as u32));
+        let normal = face_normal(corners[0], corners[1], corners[2]).normalize()
+            * if reversed { -1. } else { 1. };
+        attributes.extend(corners.iter().map(|&space_coord_px| Attribute {
+            space_coord_px,
+            tex_coord_px: vec2(0., 0.),
+            normal,
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        }));
+    }
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+}
+
+/// Makes a closed box for each of the boxes returned by `wall_cell_aabbs`,
+/// for use as a collision mesh.
+pub fn wall_collision_mesh(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    RelativeBuffers::concat_all(
+        wall_cell_aabbs(grid, style, config)
+            .into_iter()
+            .map(|(_, min, max)| aabb_mesh(min, max)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    #[test]
+    fn cell_aabbs_span_a_cell() {
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new().cell_size_px(vec2(32., 24.)).build();
+        let grid = parse_terrain(".#\n").unwrap();
+        let aabbs = wall_cell_aabbs(&grid, &style, &config);
+        assert_eq!(aabbs.len(), 1);
+        let (coord, min, max) = aabbs[0];
+        assert_eq!(coord, Coord::new(1, 0));
+        assert_eq!(min, vec3(32., 0., 0.));
+        assert_eq!(max, vec3(64., style.height_px, 24.));
+    }
+
+    #[test]
+    fn collision_box_volume() {
+        assert_eq!(
+            aabb_mesh(vec3(1., 2., 3.), vec3(3., 5., 7.)).signed_volume(),
+            2. * 3. * 4.
+        );
+        let style = StyleBuilder::new().height_px(10.).build();
+        let config = ConfigBuilder::new().cell_size_px(vec2(32., 24.)).build();
+        let grid = parse_terrain("##\n").unwrap();
+        let mesh = wall_collision_mesh(&grid, &style, &config);
+        assert_eq!(mesh.signed_volume(), 2. * 32. * 24. * 10.);
+    }
+}
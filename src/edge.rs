@@ -0,0 +1,150 @@
+use buffers::RelativeBuffers;
+use cgmath::vec2;
+use direction::CardinalDirection;
+use geometry::make_slab;
+use grid_2d::{Coord, Grid, Size};
+use style::{Config, Style};
+
+/// Walls which lie along the boundaries between cells, rather than filling
+/// cells. Each wall spans one side of one cell.
+#[derive(Debug, Clone)]
+pub struct EdgeWalls {
+    size: Size,
+    /// Walls along the north side of each cell, with an extra row for the
+    /// south side of the last row of cells
+    horizontal: Grid<bool>,
+    /// Walls along the west side of each cell, with an extra column for the
+    /// east side of the last column of cells
+    vertical: Grid<bool>,
+}
+
+impl EdgeWalls {
+    /// Creates walls for a grid of cells of size `size`, with no walls
+    /// present.
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            horizontal: Grid::new_clone(Size::new(size.x(), size.y() + 1), false),
+            vertical: Grid::new_clone(Size::new(size.x() + 1, size.y()), false),
+        }
+    }
+    pub fn size(&self) -> Size {
+        self.size
+    }
+    /// Returns true if there is a wall along the north side of the cell at
+    /// `coord`. Use a `y` coordinate equal to the height of the grid for the
+    /// south side of the last row.
+    pub fn horizontal(&self, coord: Coord) -> bool {
+        self.horizontal.get(coord).cloned().unwrap_or(false)
+    }
+    /// Returns true if there is a wall along the west side of the cell at
+    /// `coord`. Use an `x` coordinate equal to the width of the grid for the
+    /// east side of the last column.
+    pub fn vertical(&self, coord: Coord) -> bool {
+        self.vertical.get(coord).cloned().unwrap_or(false)
+    }
+    /// Panics if there is no such side (see `horizontal`).
+    pub fn set_horizontal(&mut self, coord: Coord, present: bool) {
+        *self
+            .horizontal
+            .get_mut(coord)
+            .expect("horizontal edge out of bounds") = present;
+    }
+    /// Panics if there is no such side (see `vertical`).
+    pub fn set_vertical(&mut self, coord: Coord, present: bool) {
+        *self
+            .vertical
+            .get_mut(coord)
+            .expect("vertical edge out of bounds") = present;
+    }
+}
+
+/// Generates the geometry of walls along the boundaries between cells. Each
+/// wall extends `style.width_px` either side of its boundary, and has faces
+/// on both sides. Where walls meet at the corners of cells, they are joined
+/// by a post, which also closes off the ends of walls.
+pub fn generate_edge_walls(
+    edge_walls: &EdgeWalls,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let w = style.width_px;
+    let cell = config.cell_size_px;
+    let mut parts = Vec::new();
+    for (coord, &present) in edge_walls.horizontal.enumerate() {
+        let half_length = cell.x / 2. - w;
+        if present && half_length > 0. {
+            let centre = vec2((coord.x as f32 + 0.5) * cell.x, coord.y as f32 * cell.y);
+            parts.push(make_slab(
+                centre,
+                vec2(half_length, w),
+                |d| d == CardinalDirection::North || d == CardinalDirection::South,
+                style,
+                config,
+            ));
+        }
+    }
+    for (coord, &present) in edge_walls.vertical.enumerate() {
+        let half_length = cell.y / 2. - w;
+        if present && half_length > 0. {
+            let centre = vec2(coord.x as f32 * cell.x, (coord.y as f32 + 0.5) * cell.y);
+            parts.push(make_slab(
+                centre,
+                vec2(w, half_length),
+                |d| d == CardinalDirection::East || d == CardinalDirection::West,
+                style,
+                config,
+            ));
+        }
+    }
+    // posts at the corners of cells, with faces on the sides where no wall
+    // meets them
+    for y in 0..=edge_walls.size.y() as i32 {
+        for x in 0..=edge_walls.size.x() as i32 {
+            let has_wall = |d: CardinalDirection| match d {
+                CardinalDirection::North => edge_walls.vertical(Coord::new(x, y - 1)),
+                CardinalDirection::South => edge_walls.vertical(Coord::new(x, y)),
+                CardinalDirection::West => edge_walls.horizontal(Coord::new(x - 1, y)),
+                CardinalDirection::East => edge_walls.horizontal(Coord::new(x, y)),
+            };
+            let directions = [
+                CardinalDirection::North,
+                CardinalDirection::East,
+                CardinalDirection::South,
+                CardinalDirection::West,
+            ];
+            if directions.iter().any(|&d| has_wall(d)) {
+                let centre = vec2(x as f32 * cell.x, y as f32 * cell.y);
+                parts.push(make_slab(
+                    centre,
+                    vec2(w, w),
+                    |d| !has_wall(d),
+                    style,
+                    config,
+                ));
+            }
+        }
+    }
+    RelativeBuffers::concat_all(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use style::{ConfigBuilder, StyleBuilder};
+
+    #[test]
+    fn single_edge_wall_thickness() {
+        let style = StyleBuilder::new().width_px(2.).build();
+        let config = ConfigBuilder::new().cell_size_px(vec2(32., 32.)).build();
+        let mut edge_walls = EdgeWalls::new(Size::new(1, 1));
+        edge_walls.set_horizontal(Coord::new(0, 0), true);
+        let buffers = generate_edge_walls(&edge_walls, &style, &config);
+        let (min, max) = buffers.bounds().unwrap();
+        // the wall extends the width of the style either side of the north side
+        // of the cell, and past its ends by the posts at the corners
+        assert_eq!((min.z, max.z), (-2., 2.));
+        assert_eq!((min.x, max.x), (-2., 34.));
+        assert_eq!((min.y, max.y), (0., style.height_px));
+    }
+}
@@ -0,0 +1,573 @@
+use buffers::{face_normal, RelativeBuffers};
+use cgmath::{vec3, InnerSpace};
+use std::io::{self, Write};
+
+impl RelativeBuffers {
+    /// Writes the buffers as a Wavefront OBJ. Texture coordinates are written
+    /// as raw pixels, exactly as they appear in `tex_coord_px`. Faces are
+    /// written counter-clockwise (viewed from outside), which is the reverse
+    /// of the winding used in the buffers. Any indices after the last whole
+    /// triangle are ignored.
+    pub fn write_obj<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for a in self.attributes.iter() {
+            let p = a.space_coord_px;
+            writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for a in self.attributes.iter() {
+            let t = a.tex_coord_px;
+            writeln!(writer, "vt {} {}", t.x, t.y)?;
+        }
+        for a in self.attributes.iter() {
+            let n = a.normal;
+            writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for t in self.indices.chunks_exact(3) {
+            // obj indices start at 1
+            let (a, b, c) = (t[0] + 1, t[2] + 1, t[1] + 1);
+            writeln!(
+                writer,
+                "f {}/{}/{} {}/{}/{} {}/{}/{}",
+                a, a, a, b, b, b, c, c, c
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffers as an ascii PLY file, with texture coordinates (in
+    /// pixels) stored in the `s` and `t` vertex properties. Faces are written
+    /// counter-clockwise (viewed from outside), which is the reverse of the
+    /// winding used in the buffers.
+    pub fn write_ply<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format ascii 1.0")?;
+        writeln!(writer, "element vertex {}", self.attributes.len())?;
+        for property in ["x", "y", "z", "s", "t", "nx", "ny", "nz"].iter() {
+            writeln!(writer, "property float {}", property)?;
+        }
+        writeln!(writer, "element face {}", self.indices.len() / 3)?;
+        writeln!(writer, "property list uchar uint vertex_indices")?;
+        writeln!(writer, "end_header")?;
+        for a in self.attributes.iter() {
+            let p = a.space_coord_px;
+            let t = a.tex_coord_px;
+            let n = a.normal;
+            writeln!(
+                writer,
+                "{} {} {} {} {} {} {} {}",
+                p.x, p.y, p.z, t.x, t.y, n.x, n.y, n.z
+            )?;
+        }
+        for t in self.indices.chunks_exact(3) {
+            writeln!(writer, "3 {} {} {}", t[0], t[2], t[1])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffers as a binary STL file. Texture coordinates are
+    /// discarded. Triangles are written counter-clockwise (viewed from
+    /// outside) as STL expects, which is the reverse of the winding used in
+    /// the buffers. Degenerate triangles are given a zero normal, and any
+    /// indices after the last whole triangle are ignored.
+    pub fn write_stl_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[0; 80])?;
+        writer.write_all(&((self.indices.len() / 3) as u32).to_le_bytes())?;
+        for t in self.indices.chunks_exact(3) {
+            let a = self.attributes[t[0] as usize].space_coord_px;
+            let b = self.attributes[t[1] as usize].space_coord_px;
+            let c = self.attributes[t[2] as usize].space_coord_px;
+            let normal = face_normal(a, b, c);
+            let normal = if normal.magnitude2() > 0. {
+                normal.normalize()
+            } else {
+                vec3(0., 0., 0.)
+            };
+            for v in [normal, a, c, b].iter() {
+                writer.write_all(&v.x.to_bits().to_le_bytes())?;
+                writer.write_all(&v.y.to_bits().to_le_bytes())?;
+                writer.write_all(&v.z.to_bits().to_le_bytes())?;
+            }
+            // attribute byte count
+            writer.write_all(&[0; 2])?;
+        }
+        Ok(())
+    }
+}
+
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_SHORT: u32 = 5123;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const GLB_CHUNK_JSON: u32 = 0x4e4f_534a;
+const GLB_CHUNK_BIN: u32 = 0x004e_4942;
+
+fn pad_to_4(bytes: &mut Vec<u8>, pad: u8) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(pad);
+    }
+}
+
+/// Returns `s` as a json string literal, including the quotes.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl RelativeBuffers {
+    /// Returns the json document and the binary buffer of a glTF 2.0 asset
+    /// containing a single mesh. If `bin_uri` is `None`, the buffer is
+    /// assumed to be embedded in the same glb file. Since glTF doesn't allow
+    /// empty buffers or accessors, buffers with no triangles produce a scene
+    /// with a single node and no mesh, and an empty binary buffer.
+    fn gltf_parts(&self, bin_uri: Option<&str>) -> (String, Vec<u8>) {
+        if self.indices.is_empty() {
+            let json = concat!(
+                "{\"asset\":{\"version\":\"2.0\"},",
+                "\"scene\":0,",
+                "\"scenes\":[{\"nodes\":[0]}],",
+                "\"nodes\":[{}]}",
+            );
+            return (json.to_string(), Vec::new());
+        }
+        let num_vertices = self.attributes.len();
+        // the maximum value of an index type is reserved for primitive restart
+        let index_component_type = if num_vertices <= u16::MAX as usize {
+            GLTF_UNSIGNED_SHORT
+        } else {
+            GLTF_UNSIGNED_INT
+        };
+
+        let (min, max) = self
+            .bounds()
+            .unwrap_or((vec3(0., 0., 0.), vec3(0., 0., 0.)));
+        let mut bin = Vec::new();
+        for a in self.attributes.iter() {
+            let p: [f32; 3] = a.space_coord_px.into();
+            for c in p.iter() {
+                bin.extend_from_slice(&c.to_bits().to_le_bytes());
+            }
+        }
+        let positions_len = bin.len();
+        for a in self.attributes.iter() {
+            bin.extend_from_slice(&a.tex_coord_px.x.to_bits().to_le_bytes());
+            bin.extend_from_slice(&a.tex_coord_px.y.to_bits().to_le_bytes());
+        }
+        let tex_coords_len = bin.len() - positions_len;
+        for a in self.attributes.iter() {
+            let n: [f32; 3] = a.normal.into();
+            for c in n.iter() {
+                bin.extend_from_slice(&c.to_bits().to_le_bytes());
+            }
+        }
+        let normals_len = bin.len() - positions_len - tex_coords_len;
+        let mut push_index = |i: u32| {
+            if index_component_type == GLTF_UNSIGNED_SHORT {
+                bin.extend_from_slice(&(i as u16).to_le_bytes());
+            } else {
+                bin.extend_from_slice(&i.to_le_bytes());
+            }
+        };
+        // glTF expects triangles to be counter-clockwise (viewed from
+        // outside), which is the reverse of the winding used in the buffers
+        let triangles = self.indices.chunks_exact(3);
+        let partial = triangles.remainder();
+        for t in triangles {
+            for &i in [t[0], t[2], t[1]].iter() {
+                push_index(i);
+            }
+        }
+        for &i in partial.iter() {
+            push_index(i);
+        }
+        let indices_offset = positions_len + tex_coords_len + normals_len;
+        let indices_len = bin.len() - indices_offset;
+        pad_to_4(&mut bin, 0);
+
+        let uri = bin_uri
+            .map(|uri| format!(",\"uri\":{}", json_string(uri)))
+            .unwrap_or_default();
+        let json = format!(
+            concat!(
+                "{{\"asset\":{{\"version\":\"2.0\"}},",
+                "\"scene\":0,",
+                "\"scenes\":[{{\"nodes\":[0]}}],",
+                "\"nodes\":[{{\"mesh\":0}}],",
+                "\"meshes\":[{{\"primitives\":[{{",
+                "\"attributes\":{{\"POSITION\":0,\"TEXCOORD_0\":1,\"NORMAL\":2}},",
+                "\"indices\":3}}]}}],",
+                "\"buffers\":[{{\"byteLength\":{buffer_len}{uri}}}],",
+                "\"bufferViews\":[",
+                "{{\"buffer\":0,\"byteOffset\":0,\"byteLength\":{positions_len},",
+                "\"target\":{array_buffer}}},",
+                "{{\"buffer\":0,\"byteOffset\":{positions_len},",
+                "\"byteLength\":{tex_coords_len},\"target\":{array_buffer}}},",
+                "{{\"buffer\":0,\"byteOffset\":{normals_offset},",
+                "\"byteLength\":{normals_len},\"target\":{array_buffer}}},",
+                "{{\"buffer\":0,\"byteOffset\":{indices_offset},",
+                "\"byteLength\":{indices_len},\"target\":{element_array_buffer}}}],",
+                "\"accessors\":[",
+                "{{\"bufferView\":0,\"componentType\":{float},",
+                "\"count\":{num_vertices},\"type\":\"VEC3\",",
+                "\"min\":[{min_x},{min_y},{min_z}],",
+                "\"max\":[{max_x},{max_y},{max_z}]}},",
+                "{{\"bufferView\":1,\"componentType\":{float},",
+                "\"count\":{num_vertices},\"type\":\"VEC2\"}},",
+                "{{\"bufferView\":2,\"componentType\":{float},",
+                "\"count\":{num_vertices},\"type\":\"VEC3\"}},",
+                "{{\"bufferView\":3,\"componentType\":{index_component_type},",
+                "\"count\":{num_indices},\"type\":\"SCALAR\"}}]}}",
+            ),
+            buffer_len = bin.len(),
+            uri = uri,
+            positions_len = positions_len,
+            tex_coords_len = tex_coords_len,
+            normals_offset = positions_len + tex_coords_len,
+            normals_len = normals_len,
+            indices_offset = indices_offset,
+            indices_len = indices_len,
+            array_buffer = GLTF_ARRAY_BUFFER,
+            element_array_buffer = GLTF_ELEMENT_ARRAY_BUFFER,
+            float = GLTF_FLOAT,
+            num_vertices = num_vertices,
+            min_x = min.x,
+            min_y = min.y,
+            min_z = min.z,
+            max_x = max.x,
+            max_y = max.y,
+            max_z = max.z,
+            index_component_type = index_component_type,
+            num_indices = self.indices.len(),
+        );
+        (json, bin)
+    }
+
+    /// Writes the buffers as a glTF 2.0 json document to `json_writer`, and the
+    /// binary buffer it refers to (by `bin_uri`) to `bin_writer`. Texture
+    /// coordinates are written as raw pixels. If there are no triangles, the
+    /// document has no mesh and nothing is written to `bin_writer`.
+    pub fn write_gltf<W: Write, B: Write>(
+        &self,
+        json_writer: &mut W,
+        bin_writer: &mut B,
+        bin_uri: &str,
+    ) -> io::Result<()> {
+        let (json, bin) = self.gltf_parts(Some(bin_uri));
+        json_writer.write_all(json.as_bytes())?;
+        bin_writer.write_all(&bin)
+    }
+
+    /// Writes the buffers as a single binary glTF 2.0 (glb) file. Texture
+    /// coordinates are written as raw pixels. If there are no triangles, the
+    /// file has no mesh and no binary chunk.
+    pub fn write_glb<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (json, bin) = self.gltf_parts(None);
+        let mut json = json.into_bytes();
+        pad_to_4(&mut json, b' ');
+        // 12 byte header, followed by the json chunk and the optional binary
+        // chunk, each with an 8 byte header
+        let mut total_len = 12 + 8 + json.len();
+        if !bin.is_empty() {
+            total_len += 8 + bin.len();
+        }
+        writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(total_len as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+        writer.write_all(&json)?;
+        if bin.is_empty() {
+            return Ok(());
+        }
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+        writer.write_all(&bin)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct JsonBuffers<'a> {
+    positions: Vec<f32>,
+    texcoords: Vec<f32>,
+    indices: &'a [u32],
+}
+
+#[cfg(feature = "serde")]
+impl RelativeBuffers {
+    /// Writes the buffers as a json object with flat arrays of `positions`
+    /// (3 numbers per vertex), `texcoords` (2 numbers per vertex, in pixels)
+    /// and `indices`, which can be loaded directly into typed arrays.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut positions = Vec::with_capacity(self.attributes.len() * 3);
+        let mut texcoords = Vec::with_capacity(self.attributes.len() * 2);
+        for a in self.attributes.iter() {
+            let p = a.space_coord_px;
+            positions.extend_from_slice(&[p.x, p.y, p.z]);
+            texcoords.extend_from_slice(&[a.tex_coord_px.x, a.tex_coord_px.y]);
+        }
+        let json = JsonBuffers {
+            positions,
+            texcoords,
+            indices: &self.indices,
+        };
+        ::serde_json::to_writer(writer, &json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cell::Piece;
+    use cgmath::vec2;
+    use direction::OrdinalDirection;
+    use geometry::{make_geometry, PieceHeights};
+    use style::{ConfigBuilder, StyleBuilder};
+
+    /// A single outer corner quarter, small enough to check by hand
+    fn outer_quarter() -> RelativeBuffers {
+        let style = StyleBuilder::new().width_px(2.).height_px(4.).build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(8., 8.))
+            .tex_top_piece_size(4.)
+            .build();
+        make_geometry(
+            Piece::Outer,
+            OrdinalDirection::SouthEast,
+            vec2(4., 4.),
+            PieceHeights {
+                bottom_px: 0.,
+                top_px: 4.,
+                bevel_px: 0.,
+            },
+            &style,
+            &config,
+        )
+    }
+
+    const OUTER_QUARTER_OBJ: &str = concat!(
+        "v 0 4 0\n",
+        "v 2 4 0\n",
+        "v 2 4 2\n",
+        "v 0 4 2\n",
+        "v 0 0 2\n",
+        "v 0 4 2\n",
+        "v 2 0 2\n",
+        "v 2 4 2\n",
+        "v 2 0 2\n",
+        "v 2 4 2\n",
+        "v 2 0 0\n",
+        "v 2 4 0\n",
+        "vt 24 16\n",
+        "vt 26 16\n",
+        "vt 26 18\n",
+        "vt 24 18\n",
+        "vt 68 20\n",
+        "vt 68 16\n",
+        "vt 66 20\n",
+        "vt 66 16\n",
+        "vt 66 20\n",
+        "vt 66 16\n",
+        "vt 64 20\n",
+        "vt 64 16\n",
+        "vn 0 1 0\n",
+        "vn 0 1 0\n",
+        "vn 0 1 0\n",
+        "vn 0 1 0\n",
+        "vn -0 0 1\n",
+        "vn -0 0 1\n",
+        "vn -0 0 1\n",
+        "vn -0 0 1\n",
+        "vn 1 0 0\n",
+        "vn 1 0 0\n",
+        "vn 1 0 0\n",
+        "vn 1 0 0\n",
+        "f 1/1/1 3/3/3 2/2/2\n",
+        "f 1/1/1 4/4/4 3/3/3\n",
+        "f 5/5/5 7/7/7 6/6/6\n",
+        "f 6/6/6 7/7/7 8/8/8\n",
+        "f 9/9/9 11/11/11 10/10/10\n",
+        "f 10/10/10 11/11/11 12/12/12\n",
+    );
+
+    #[test]
+    fn obj_golden() {
+        let mut obj = Vec::new();
+        outer_quarter().write_obj(&mut obj).unwrap();
+        assert_eq!(String::from_utf8(obj).unwrap(), OUTER_QUARTER_OBJ);
+    }
+
+    #[test]
+    fn partial_triangles_are_ignored() {
+        let mut buffers = outer_quarter();
+        let (mut obj, mut ply, mut stl) = (Vec::new(), Vec::new(), Vec::new());
+        buffers.write_obj(&mut obj).unwrap();
+        buffers.write_ply(&mut ply).unwrap();
+        buffers.write_stl_binary(&mut stl).unwrap();
+        buffers.indices.push(0);
+        let (mut partial_obj, mut partial_ply, mut partial_stl) =
+            (Vec::new(), Vec::new(), Vec::new());
+        buffers.write_obj(&mut partial_obj).unwrap();
+        buffers.write_ply(&mut partial_ply).unwrap();
+        buffers.write_stl_binary(&mut partial_stl).unwrap();
+        assert_eq!(partial_obj, obj);
+        assert_eq!(partial_ply, ply);
+        assert_eq!(partial_stl, stl);
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    /// Returns the values of every occurrence of `"key":<number>` in `json`
+    fn json_numbers(json: &str, key: &str) -> Vec<usize> {
+        let pattern = format!("\"{}\":", key);
+        json.match_indices(&pattern)
+            .map(|(i, _)| {
+                let rest = &json[i + pattern.len()..];
+                let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap();
+                rest[..end].parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let buffers = outer_quarter();
+        let mut glb = Vec::new();
+        buffers.write_glb(&mut glb).unwrap();
+        assert_eq!(u32_at(&glb, 0), GLB_MAGIC);
+        assert_eq!(u32_at(&glb, 8) as usize, glb.len());
+        let json_len = u32_at(&glb, 12) as usize;
+        assert_eq!(u32_at(&glb, 16), GLB_CHUNK_JSON);
+        let json = ::std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        let bin_len = u32_at(&glb, 20 + json_len) as usize;
+        assert_eq!(u32_at(&glb, 24 + json_len), GLB_CHUNK_BIN);
+        assert_eq!(28 + json_len + bin_len, glb.len());
+        assert_eq!(json_numbers(json, "byteLength")[0], bin_len);
+        // positions, texture coordinates and normals, then indices
+        assert_eq!(json_numbers(json, "count"), vec![12, 12, 12, 18]);
+        // the first position is the first vertex
+        let position = (0..3)
+            .map(|i| f32::from_bits(u32_at(&glb, 28 + json_len + i * 4)))
+            .collect::<Vec<_>>();
+        let p = buffers.attributes[0].space_coord_px;
+        assert_eq!(position, vec![p.x, p.y, p.z]);
+    }
+
+    #[test]
+    fn glb_winding_matches_normals() {
+        let buffers = outer_quarter();
+        let mut glb = Vec::new();
+        buffers.write_glb(&mut glb).unwrap();
+        let bin_start = 28 + u32_at(&glb, 12) as usize;
+        let n = buffers.attributes.len();
+        let vec3_at = |offset: usize| {
+            let f = |i: usize| f32::from_bits(u32_at(&glb, bin_start + offset + i * 4));
+            vec3(f(0), f(1), f(2))
+        };
+        // positions, then texture coordinates, then normals, then 16 bit
+        // indices
+        let normals_offset = n * 12 + n * 8;
+        let indices_offset = normals_offset + n * 12;
+        let index = |k: usize| {
+            let o = bin_start + indices_offset + k * 2;
+            u16::from_le_bytes([glb[o], glb[o + 1]]) as usize
+        };
+        for k in (0..buffers.indices.len()).step_by(3) {
+            let (a, b, c) = (index(k), index(k + 1), index(k + 2));
+            let (pa, pb, pc) = (vec3_at(a * 12), vec3_at(b * 12), vec3_at(c * 12));
+            let counter_clockwise_normal = (pb - pa).cross(pc - pa);
+            let normal = vec3_at(normals_offset + a * 12);
+            assert!(counter_clockwise_normal.dot(normal) > 0.);
+        }
+    }
+
+    #[test]
+    fn gltf_uri_is_escaped() {
+        let (mut json, mut bin) = (Vec::new(), Vec::new());
+        outer_quarter()
+            .write_gltf(&mut json, &mut bin, "a \"b\"\\c.bin")
+            .unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""uri":"a \"b\"\\c.bin""#));
+        assert_eq!(json_numbers(&json, "byteLength")[0], bin.len());
+    }
+
+    #[test]
+    fn empty_gltf_has_no_mesh() {
+        let empty = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: Vec::new(),
+        };
+        let (mut json, mut bin) = (Vec::new(), Vec::new());
+        empty.write_gltf(&mut json, &mut bin, "empty.bin").unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(!json.contains("meshes") && !json.contains("accessors"));
+        assert!(bin.is_empty());
+        let mut glb = Vec::new();
+        empty.write_glb(&mut glb).unwrap();
+        assert_eq!(u32_at(&glb, 8) as usize, glb.len());
+        assert_eq!(20 + u32_at(&glb, 12) as usize, glb.len());
+    }
+
+    #[test]
+    fn ply_face_count() {
+        let buffers = outer_quarter();
+        let mut ply = Vec::new();
+        buffers.write_ply(&mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        let face_line = format!("element face {}", buffers.indices.len() / 3);
+        assert!(ply.lines().any(|line| line == face_line));
+        let faces = ply.lines().filter(|line| line.starts_with("3 ")).count();
+        assert_eq!(faces, buffers.indices.len() / 3);
+        // faces are wound counter-clockwise, the reverse of the buffers
+        let t = &buffers.indices[..3];
+        let first_face = format!("3 {} {} {}", t[0], t[2], t[1]);
+        assert_eq!(
+            ply.lines().find(|line| line.starts_with("3 ")),
+            Some(&first_face[..])
+        );
+    }
+
+    #[test]
+    fn stl_triangle_count() {
+        let buffers = outer_quarter();
+        let mut stl = Vec::new();
+        buffers.write_stl_binary(&mut stl).unwrap();
+        let triangle_count = u32_at(&stl, 80) as usize;
+        assert_eq!(triangle_count, buffers.indices.len() / 3);
+        // 80 byte header, triangle count, then 50 bytes per triangle
+        assert_eq!(stl.len(), 84 + triangle_count * 50);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_arrays_are_flat() {
+        let buffers = outer_quarter();
+        let mut json = Vec::new();
+        buffers.write_json(&mut json).unwrap();
+        let value: ::serde_json::Value = ::serde_json::from_slice(&json).unwrap();
+        let len = |key: &str| value[key].as_array().unwrap().len();
+        assert_eq!(len("positions"), buffers.attributes.len() * 3);
+        assert_eq!(len("texcoords"), buffers.attributes.len() * 2);
+        assert_eq!(len("indices"), buffers.indices.len());
+        assert!(value["positions"][0].is_number());
+    }
+}
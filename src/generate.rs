@@ -0,0 +1,1093 @@
+use buffers::RelativeBuffers;
+use cell::{
+    cell_type_at, CellDetails, CellType, DefaultPieceChooser, Diagonal, Piece,
+    PieceCache, PieceChooser, PieceError,
+};
+use cgmath::{vec3, Matrix4, SquareMatrix, Vector3};
+use direction::{CardinalDirections, OrdinalDirection, OrdinalDirections};
+use geometry::{
+    make_ceiling, make_crown, make_diagonal, make_diagonal_tops_and_faces, make_floor,
+    make_geometry, make_ramp, make_ramp_slope_and_faces, make_stairs,
+    make_stairs_treads_and_faces, move_by_cells, move_to_cell_centre, quarter_rotation,
+    quarter_size_px, PieceHeights,
+};
+use grid_2d::{Coord, Grid, Size};
+use std::collections::HashMap;
+use std::iter;
+use style::{Ceilings, Config, Style};
+
+/// Returns a grid which is true at floor cells that can't reach the edge of
+/// the grid without passing through a wall. Diagonal walls count as enclosed
+/// if they are next to enclosed floor. When the border beyond the grid is
+/// solid, or the grid wraps around, all floor is enclosed.
+fn enclosed_floor(grid: &Grid<CellType>, config: &Config) -> Grid<bool> {
+    let mut enclosed = Grid::new_from_fn(grid.size(), |coord| {
+        grid.get(coord) == Some(&CellType::Floor)
+    });
+    // flood fill outwards from floor on the edge of the grid
+    let mut to_visit = grid
+        .enumerate()
+        .filter(|&(coord, &cell_type)| {
+            let on_edge = coord.x == 0
+                || coord.y == 0
+                || coord.x as u32 == grid.size().x() - 1
+                || coord.y as u32 == grid.size().y() - 1;
+            on_edge
+                && cell_type == CellType::Floor
+                && !config.border.is_solid()
+                && !config.wrap
+        })
+        .map(|(coord, _)| coord)
+        .collect::<Vec<_>>();
+    while let Some(coord) = to_visit.pop() {
+        match enclosed.get_mut(coord) {
+            Some(cell) if *cell => *cell = false,
+            _ => continue,
+        }
+        for direction in CardinalDirections {
+            to_visit.push(coord + direction.coord());
+        }
+    }
+    Grid::new_from_fn(grid.size(), |coord| match grid.get(coord) {
+        Some(&CellType::Diagonal(_)) => CardinalDirections
+            .into_iter()
+            .any(|direction| enclosed.get(coord + direction.coord()) == Some(&true)),
+        _ => enclosed.get(coord) == Some(&true),
+    })
+}
+
+fn diagonal_end_caps(
+    grid: &Grid<CellType>,
+    coord: Coord,
+    diagonal: Diagonal,
+    config: &Config,
+) -> (bool, bool) {
+    // ends which continue into another diagonal wall are left open
+    let (end_a, end_b) = diagonal.ends();
+    let is_capped = |end: OrdinalDirection| {
+        cell_type_at(grid, coord + end.coord(), config) != CellType::Diagonal(diagonal)
+    };
+    (is_capped(end_a), is_capped(end_b))
+}
+
+fn make_diagonal_in_grid(
+    grid: &Grid<CellType>,
+    coord: Coord,
+    diagonal: Diagonal,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let end_caps = diagonal_end_caps(grid, coord, diagonal, config);
+    make_diagonal(coord, diagonal, end_caps, style, config)
+}
+
+fn make_floors(grid: &Grid<CellType>, style: &Style, config: &Config) -> RelativeBuffers {
+    let floor_iter = grid
+        .enumerate()
+        .filter(|&(_, &cell_type)| cell_type.has_floor())
+        .map(|(coord, _)| make_floor(coord, style, config));
+    RelativeBuffers::concat_all(floor_iter)
+}
+
+/// Returns a grid which is true at cells which have a ceiling, or `None` if
+/// there are no ceilings.
+fn ceiling_grid(grid: &Grid<CellType>, config: &Config) -> Option<Grid<bool>> {
+    match config.ceilings {
+        Ceilings::None => None,
+        Ceilings::Enclosed => Some(enclosed_floor(grid, config)),
+        Ceilings::All => Some(Grid::new_from_fn(grid.size(), |coord| {
+            grid.get(coord)
+                .is_some_and(|cell_type| cell_type.has_floor())
+        })),
+    }
+}
+
+fn make_ceilings(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let ceiling_grid = ceiling_grid(grid, config);
+    let ceiling_iter = ceiling_grid
+        .iter()
+        .flat_map(|ceiling_grid| ceiling_grid.enumerate())
+        .filter(|&(_, &ceiling)| ceiling)
+        .map(|(coord, _)| make_ceiling(coord, style, config));
+    RelativeBuffers::concat_all(ceiling_iter)
+}
+
+/// Returns the heights of the walls in `grid`, taken from `heights` if
+/// given, and otherwise from `style`, where railings are no taller than
+/// `style.railing_height_px`. Returns `None` if every wall has the height in
+/// `style`.
+pub fn wall_heights(
+    grid: &Grid<CellType>,
+    heights: Option<&Grid<f32>>,
+    style: &Style,
+) -> Option<Grid<f32>> {
+    let has_railings = grid
+        .enumerate()
+        .any(|(_, &cell_type)| cell_type == CellType::Railing);
+    if heights.is_none() && !has_railings {
+        return None;
+    }
+    Some(Grid::new_from_fn(grid.size(), |coord| {
+        let height_px = heights
+            .and_then(|heights| heights.get(coord).cloned())
+            .unwrap_or(style.height_px);
+        match grid.get(coord) {
+            Some(&CellType::Railing) => height_px.min(style.railing_height_px),
+            _ => height_px,
+        }
+    }))
+}
+
+/// Returns the details of the cell at `coord` if it's solid, where walls
+/// have the heights in `height_grid` (see `wall_heights`).
+pub fn cell_details(
+    grid: &Grid<CellType>,
+    height_grid: Option<&Grid<f32>>,
+    coord: Coord,
+    config: &Config,
+) -> Option<CellDetails> {
+    cell_details_with_chooser(grid, height_grid, coord, &DefaultPieceChooser, config)
+        .expect("cardinals of an ordinal direction are adjacent")
+}
+
+fn cell_details_with_chooser<C: PieceChooser + ?Sized>(
+    grid: &Grid<CellType>,
+    height_grid: Option<&Grid<f32>>,
+    coord: Coord,
+    chooser: &C,
+    config: &Config,
+) -> Result<Option<CellDetails>, PieceError> {
+    match height_grid {
+        None => CellDetails::from_grid_with_chooser(grid, coord, chooser, config),
+        Some(height_grid) => CellDetails::from_grid_with_heights_and_chooser(
+            grid,
+            height_grid,
+            coord,
+            chooser,
+            config,
+        ),
+    }
+}
+
+/// Returns the details of each solid cell in `grid`, with the heights of walls
+/// taken from `heights` if given, and otherwise from `style`. Railings are no
+/// taller than `style.railing_height_px`.
+fn cell_details_grid(
+    grid: &Grid<CellType>,
+    heights: Option<&Grid<f32>>,
+    style: &Style,
+    config: &Config,
+) -> Grid<Option<CellDetails>> {
+    cell_details_grid_with_chooser(grid, heights, &DefaultPieceChooser, style, config)
+        .expect("cardinals of an ordinal direction are adjacent")
+}
+
+/// Like `cell_details_grid`, but where pieces are chosen by `chooser`.
+/// Returns the first error from `chooser`.
+fn cell_details_grid_with_chooser<C: PieceChooser + ?Sized>(
+    grid: &Grid<CellType>,
+    heights: Option<&Grid<f32>>,
+    chooser: &C,
+    style: &Style,
+    config: &Config,
+) -> Result<Grid<Option<CellDetails>>, PieceError> {
+    let height_grid = wall_heights(grid, heights, style);
+    let mut detail_grid = Grid::new_default(grid.size());
+    for (coord, cell) in detail_grid.enumerate_mut() {
+        *cell = cell_details_with_chooser(
+            grid,
+            height_grid.as_ref(),
+            coord,
+            chooser,
+            config,
+        )?;
+    }
+    Ok(detail_grid)
+}
+
+#[cfg(not(feature = "rayon"))]
+fn make_walls(
+    detail_grid: &Grid<Option<CellDetails>>,
+    style: &Style,
+    config: &Config,
+) -> Vec<RelativeBuffers> {
+    let cache = PieceCache::new(detail_grid, style, config);
+    detail_grid
+        .enumerate()
+        .filter_map(|(coord, cell)| cell.as_ref().map(|cell| (coord, cell)))
+        .flat_map(|(coord, cell)| cell.make_geometry_cached(coord, style, config, &cache))
+        .collect()
+}
+
+/// Like the serial version, but each cell is made in parallel. The results
+/// are collected in the order of the cells, so the output is the same.
+#[cfg(feature = "rayon")]
+fn make_walls(
+    detail_grid: &Grid<Option<CellDetails>>,
+    style: &Style,
+    config: &Config,
+) -> Vec<RelativeBuffers> {
+    use rayon::prelude::*;
+    let cache = PieceCache::new(detail_grid, style, config);
+    let cells = detail_grid
+        .enumerate()
+        .filter_map(|(coord, cell)| cell.as_ref().map(|cell| (coord, cell)))
+        .collect::<Vec<_>>();
+    cells
+        .par_iter()
+        .map(|&(coord, cell)| cell.make_geometry_cached(coord, style, config, &cache))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn generate_from_details(
+    grid: &Grid<CellType>,
+    detail_grid: &Grid<Option<CellDetails>>,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let wall_iter = make_walls(detail_grid, style, config).into_iter();
+    let diagonal_iter =
+        grid.enumerate()
+            .filter_map(|(coord, &cell_type)| match cell_type {
+                CellType::Diagonal(diagonal) => {
+                    Some(make_diagonal_in_grid(grid, coord, diagonal, style, config))
+                }
+                CellType::Stairs(direction) => {
+                    Some(make_stairs(coord, direction, style, config))
+                }
+                CellType::Ramp(direction) => {
+                    Some(make_ramp(coord, direction, style, config))
+                }
+                _ => None,
+            });
+    let mut walls = RelativeBuffers::concat_all(wall_iter.chain(diagonal_iter));
+    if config.merge_straight_faces && style.face_tex_wraps {
+        walls.merge_straight_faces(config.units_per_px / 1024.);
+    }
+    RelativeBuffers::concat_all(vec![
+        walls,
+        make_floors(grid, style, config),
+        make_ceilings(grid, style, config),
+    ])
+}
+
+/// Generates the geometry for every wall and floor cell in `grid`. An empty
+/// grid produces empty buffers.
+pub fn generate_mesh(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let detail_grid = cell_details_grid(grid, None, style, config);
+    generate_from_details(grid, &detail_grid, style, config)
+}
+
+/// Like `generate_mesh`, but with less detail for higher values of `lod`,
+/// for drawing distant geometry more cheaply. Level 0 is the same as
+/// `generate_mesh`. From level 1, tops aren't bevelled, walls have no
+/// baseboards or crowns, and corners and pillars are square rather than
+/// rounded. From level 2, straight faces are also merged along runs of wall
+/// if the face texture wraps (see `RelativeBuffers::merge_straight_faces`
+/// and `Style::face_tex_wraps`), and upward facing surfaces
+/// in the same plane, such as the tops of walls and floors, are merged into
+/// larger polygons (see `RelativeBuffers::merge_coplanar_tops`).
+pub fn generate_lod(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+    lod: u32,
+) -> RelativeBuffers {
+    if lod == 0 {
+        return generate_mesh(grid, style, config);
+    }
+    let mut style = style.clone();
+    style.top_bevel_px = 0.;
+    style.baseboard_px = 0.;
+    style.crown_px = 0.;
+    let mut config = config.clone();
+    config.corner_segments = 0;
+    config.pillar_segments = 0;
+    if lod == 1 {
+        return generate_mesh(grid, &style, &config);
+    }
+    config.merge_straight_faces = true;
+    let mut buffers = generate_mesh(grid, &style, &config);
+    buffers.merge_coplanar_tops(config.units_per_px / 1024.);
+    buffers
+}
+
+/// Like `generate_mesh`, but where the piece of each quarter of each wall
+/// cell is chosen by `chooser` rather than `Piece::choose`. Returns the
+/// first error from `chooser` if it fails to choose a piece.
+pub fn generate_mesh_with_chooser<C: PieceChooser + ?Sized>(
+    grid: &Grid<CellType>,
+    chooser: &C,
+    style: &Style,
+    config: &Config,
+) -> Result<RelativeBuffers, PieceError> {
+    let detail_grid = cell_details_grid_with_chooser(grid, None, chooser, style, config)?;
+    Ok(generate_from_details(grid, &detail_grid, style, config))
+}
+
+/// Returns the walls of the cell at `coord`, which has the details `cell` if
+/// it's solid.
+pub fn make_cell_walls(
+    grid: &Grid<CellType>,
+    cell: Option<&CellDetails>,
+    coord: Coord,
+    style: &Style,
+    config: &Config,
+) -> Vec<RelativeBuffers> {
+    match (grid.get(coord), cell) {
+        (Some(&CellType::Diagonal(diagonal)), _) => {
+            vec![make_diagonal_in_grid(grid, coord, diagonal, style, config)]
+        }
+        (Some(&CellType::Stairs(direction)), _) => {
+            vec![make_stairs(coord, direction, style, config)]
+        }
+        (Some(&CellType::Ramp(direction)), _) => {
+            vec![make_ramp(coord, direction, style, config)]
+        }
+        (_, Some(cell)) => cell.make_geometry(coord, style, config),
+        (_, None) => Vec::new(),
+    }
+}
+
+/// Generates the geometry of the cells in the region of `grid` covered by
+/// the chunk at `chunk_coord`, where chunks are `chunk` cells in size.
+fn generate_chunk(
+    grid: &Grid<CellType>,
+    detail_grid: &Grid<Option<CellDetails>>,
+    ceiling_grid: Option<&Grid<bool>>,
+    chunk_coord: Coord,
+    chunk: Size,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let top_left = Coord::new(
+        chunk_coord.x * chunk.x() as i32,
+        chunk_coord.y * chunk.y() as i32,
+    );
+    let coords = (0..chunk.y() as i32)
+        .flat_map(|y| (0..chunk.x() as i32).map(move |x| Coord::new(x, y)))
+        .map(|offset| top_left + offset)
+        .filter(|&coord| grid.get(coord).is_some())
+        .collect::<Vec<_>>();
+    let mut walls = Vec::new();
+    let mut floors = Vec::new();
+    let mut ceilings = Vec::new();
+    for &coord in coords.iter() {
+        let cell_type = *grid.get(coord).unwrap();
+        let cell = detail_grid.get(coord).and_then(Option::as_ref);
+        walls.extend(make_cell_walls(grid, cell, coord, style, config));
+        if cell_type.has_floor() {
+            floors.push(make_floor(coord, style, config));
+        }
+        if ceiling_grid.and_then(|ceiling_grid| ceiling_grid.get(coord)) == Some(&true) {
+            ceilings.push(make_ceiling(coord, style, config));
+        }
+    }
+    let mut walls = RelativeBuffers::concat_all(walls);
+    if config.merge_straight_faces && style.face_tex_wraps {
+        walls.merge_straight_faces(config.units_per_px / 1024.);
+    }
+    RelativeBuffers::concat_all(vec![
+        walls,
+        RelativeBuffers::concat_all(floors),
+        RelativeBuffers::concat_all(ceilings),
+    ])
+}
+
+/// The geometry of a rectangular region of a grid (see `generate_chunks`)
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Coordinate of the chunk, in chunks
+    pub coord: Coord,
+    pub buffers: RelativeBuffers,
+    /// Minimum and maximum vertex positions of `buffers`, for culling, or
+    /// `None` if the chunk has no geometry
+    pub bounds: Option<(Vector3<f32>, Vector3<f32>)>,
+}
+
+/// Like `generate_mesh`, but divides `grid` into chunks of `chunk` cells,
+/// and generates the geometry of each chunk in turn, in row-major order, so
+/// the geometry of a large grid doesn't need to be held at once. Chunks on
+/// the bottom and right edges of the grid may be partial. Cells on the edges
+/// of chunks are shaped by their neighbours in adjacent chunks, so together,
+/// the chunks contain the same triangles as `generate_mesh` produces, except
+/// that straight faces aren't merged across chunks. `chunk` must not be
+/// empty.
+pub fn generate_chunks<'a>(
+    grid: &'a Grid<CellType>,
+    style: &'a Style,
+    config: &'a Config,
+    chunk: Size,
+) -> impl Iterator<Item = Chunk> + 'a {
+    let detail_grid = cell_details_grid(grid, None, style, config);
+    let ceiling_grid = ceiling_grid(grid, config);
+    let width_in_chunks = grid.size().x().div_ceil(chunk.x());
+    let height_in_chunks = grid.size().y().div_ceil(chunk.y());
+    (0..height_in_chunks as i32)
+        .flat_map(move |y| (0..width_in_chunks as i32).map(move |x| Coord::new(x, y)))
+        .map(move |chunk_coord| {
+            let buffers = generate_chunk(
+                grid,
+                &detail_grid,
+                ceiling_grid.as_ref(),
+                chunk_coord,
+                chunk,
+                style,
+                config,
+            );
+            Chunk {
+                coord: chunk_coord,
+                bounds: buffers.bounds(),
+                buffers,
+            }
+        })
+}
+
+/// Geometry of a grid where each triangle can be traced back to the cell it
+/// belongs to, such as for picking (see `generate_picking_mesh`)
+#[derive(Debug, Clone)]
+pub struct PickingMesh {
+    pub buffers: RelativeBuffers,
+    /// Coordinate of the cell of each triangle of `buffers`, in order
+    pub triangle_cells: Vec<Coord>,
+}
+
+/// Like `generate_mesh`, but also records which cell each triangle belongs
+/// to. The geometry of each cell is kept together, so the buffers contain the
+/// same triangles as `generate_mesh` produces, except that straight faces
+/// aren't merged across cells.
+pub fn generate_picking_mesh(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> PickingMesh {
+    let detail_grid = cell_details_grid(grid, None, style, config);
+    let ceiling_grid = ceiling_grid(grid, config);
+    let mut triangle_cells = Vec::new();
+    let buffers =
+        RelativeBuffers::concat_all(grid.enumerate().map(|(coord, &cell_type)| {
+            let cell = detail_grid.get(coord).and_then(Option::as_ref);
+            let mut parts = make_cell_walls(grid, cell, coord, style, config);
+            if cell_type.has_floor() {
+                parts.push(make_floor(coord, style, config));
+            }
+            if ceiling_grid
+                .as_ref()
+                .and_then(|ceiling_grid| ceiling_grid.get(coord))
+                == Some(&true)
+            {
+                parts.push(make_ceiling(coord, style, config));
+            }
+            let buffers = RelativeBuffers::concat_all(parts);
+            triangle_cells.extend(iter::repeat_n(coord, buffers.indices.len() / 3));
+            buffers
+        }));
+    PickingMesh {
+        buffers,
+        triangle_cells,
+    }
+}
+
+/// Geometry of a grid, divided by the kind of surface so each can be drawn
+/// separately.
+#[derive(Debug, Clone)]
+pub struct MeshSplit {
+    /// Horizontal tops of walls, treads of stairs, and slopes of ramps
+    pub tops: RelativeBuffers,
+    /// Vertical faces of walls, stairs and ramps, including the insides of
+    /// openings
+    pub faces: RelativeBuffers,
+    pub floors: RelativeBuffers,
+    pub ceilings: RelativeBuffers,
+}
+
+/// Like `generate_mesh`, but keeps each kind of surface in separate buffers.
+/// Together, the buffers contain the same triangles as `generate_mesh`
+/// produces.
+pub fn generate_mesh_split(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> MeshSplit {
+    let detail_grid = cell_details_grid(grid, None, style, config);
+    let mut tops = Vec::new();
+    let mut faces = Vec::new();
+    for (coord, &cell_type) in grid.enumerate() {
+        if let CellType::Diagonal(diagonal) = cell_type {
+            let end_caps = diagonal_end_caps(grid, coord, diagonal, config);
+            let (top, face) =
+                make_diagonal_tops_and_faces(coord, diagonal, end_caps, style, config);
+            tops.push(top);
+            faces.push(face);
+        } else if let CellType::Stairs(direction) = cell_type {
+            let (treads, face) =
+                make_stairs_treads_and_faces(coord, direction, style, config);
+            tops.push(treads);
+            faces.push(face);
+        } else if let CellType::Ramp(direction) = cell_type {
+            let (slope, face) =
+                make_ramp_slope_and_faces(coord, direction, style, config);
+            tops.push(slope);
+            faces.push(face);
+        } else if let Some(Some(cell)) = detail_grid.get(coord) {
+            tops.extend(cell.make_tops(coord, style, config));
+            faces.extend(cell.make_faces(coord, style, config));
+        }
+    }
+    let mut faces = RelativeBuffers::concat_all(faces);
+    if config.merge_straight_faces && style.face_tex_wraps {
+        faces.merge_straight_faces(config.units_per_px / 1024.);
+    }
+    MeshSplit {
+        tops: RelativeBuffers::concat_all(tops),
+        faces,
+        floors: make_floors(grid, style, config),
+        ceilings: make_ceilings(grid, style, config),
+    }
+}
+
+/// Like `generate_mesh`, but where the height of each wall is taken from
+/// `heights` rather than `style`. `heights` must be the same size as `grid`.
+/// Where walls of different heights meet, the taller wall has faces above the
+/// height of the shorter wall. Railings are no taller than
+/// `style.railing_height_px`.
+pub fn generate_mesh_with_heights(
+    grid: &Grid<CellType>,
+    heights: &Grid<f32>,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let detail_grid = cell_details_grid(grid, Some(heights), style, config);
+    generate_from_details(grid, &detail_grid, style, config)
+}
+
+/// Generates the geometry for several grids stacked on top of one another.
+/// Each grid is paired with the height of its floor in pixels.
+pub fn generate_levels(
+    layers: &[(Grid<CellType>, f32)],
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let level_iter = layers.iter().map(|&(ref grid, offset_px)| {
+        let offset = offset_px * config.units_per_px;
+        generate_mesh(grid, style, config)
+            .transform(Matrix4::from_translation(vec3(0., offset, 0.)))
+    });
+    RelativeBuffers::concat_all(level_iter)
+}
+
+/// Generates the geometry for several grids laid out side by side. Each grid
+/// is paired with the coordinate of its top-left cell, and is meshed on its
+/// own, so walls in different grids don't connect to one another. Parts which
+/// overlap produce overlapping geometry.
+pub fn generate_composite(
+    parts: &[(Grid<CellType>, Coord)],
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let part_iter = parts.iter().map(|&(ref grid, offset)| {
+        generate_mesh(grid, style, config).transform(move_by_cells(offset, config))
+    });
+    RelativeBuffers::concat_all(part_iter)
+}
+
+/// A quarter of a wall cell, drawn by placing the shared mesh of its piece.
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceData {
+    pub piece: Piece,
+    /// Whether the quarter is rotated a quarter turn relative to the grid.
+    /// When cells aren't square, such quarters use a separate mesh with its
+    /// dimensions swapped.
+    pub rotated: bool,
+    /// Transformation from the space of the piece's mesh to world space
+    pub transform: Matrix4<f32>,
+}
+
+/// Generates the walls in `grid` as a single mesh for each kind of piece, and
+/// an instance for each quarter of each wall cell which places one of those
+/// meshes. Meshes are keyed by the `piece` and `rotated` fields of the
+/// instances which use them. Floors, ceilings, diagonal walls, stairs and
+/// ramps aren't included. Railings are drawn with the same meshes as walls,
+/// scaled vertically to the height of railings, and walls next to railings
+/// don't have faces above them. Faces are textured as if every piece were in
+/// a south-east quarter, so per-facing face textures and face texture
+/// variants aren't supported.
+pub fn generate_instanced(
+    grid: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> (HashMap<(Piece, bool), RelativeBuffers>, Vec<InstanceData>) {
+    let mut pieces = HashMap::new();
+    let mut instances = Vec::new();
+    for (coord, &cell_type) in grid.enumerate() {
+        if let Some(details) = CellDetails::from_grid(grid, coord, config) {
+            let translate = move_to_cell_centre(coord, config);
+            let scale = match cell_type {
+                CellType::Railing => Matrix4::from_nonuniform_scale(
+                    1.,
+                    style.railing_height_px / style.height_px,
+                    1.,
+                ),
+                _ => Matrix4::identity(),
+            };
+            for (o, q) in OrdinalDirections.into_iter().zip(details.quarters.iter()) {
+                let rotated = match o {
+                    OrdinalDirection::NorthEast | OrdinalDirection::SouthWest => true,
+                    OrdinalDirection::SouthEast | OrdinalDirection::NorthWest => false,
+                };
+                pieces.entry((q.piece, rotated)).or_insert_with(|| {
+                    let size = quarter_size_px(o, config);
+                    let bevel_px = style.top_bevel_px.min(style.height_px);
+                    make_geometry(
+                        q.piece,
+                        OrdinalDirection::SouthEast,
+                        size,
+                        PieceHeights {
+                            bottom_px: 0.,
+                            top_px: style.height_px,
+                            bevel_px,
+                        },
+                        style,
+                        config,
+                    )
+                    .concat_into(make_crown(
+                        q.piece,
+                        size,
+                        0.,
+                        style.height_px - bevel_px,
+                        style,
+                        config,
+                    ))
+                });
+                instances.push(InstanceData {
+                    piece: q.piece,
+                    rotated,
+                    transform: translate * quarter_rotation(o) * scale,
+                });
+            }
+        }
+    }
+    (pieces, instances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec2, InnerSpace};
+    use direction::CardinalDirection;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    fn style() -> Style {
+        StyleBuilder::new().build()
+    }
+
+    /// Cells of the size the default style is made for
+    fn config() -> Config {
+        ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build()
+    }
+
+    /// The triangles of `buffers`, in an order which doesn't depend on the
+    /// order they were generated in
+    fn sorted_triangles(buffers: &RelativeBuffers) -> Vec<String> {
+        let mut triangles = buffers
+            .triangles()
+            .map(|t| format!("{:?}", t))
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn per_cell_heights_differ() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("#.#\n").unwrap();
+        let heights =
+            Grid::new_from_fn(grid.size(), |coord| if coord.x == 0 { 24. } else { 40. });
+        let buffers = generate_mesh_with_heights(&grid, &heights, &style, &config);
+        let top_heights = |min_x: f32, max_x: f32| {
+            let mut ys = buffers
+                .attributes
+                .iter()
+                .filter(|a| a.normal == vec3(0., 1., 0.) && a.space_coord_px.y > 0.)
+                .filter(|a| a.space_coord_px.x >= min_x && a.space_coord_px.x <= max_x)
+                .map(|a| a.space_coord_px.y)
+                .collect::<Vec<_>>();
+            ys.dedup();
+            ys
+        };
+        assert_eq!(top_heights(0., 32.), vec![24.]);
+        assert_eq!(top_heights(64., 96.), vec![40.]);
+    }
+
+    #[test]
+    fn single_floor_cell() {
+        let grid = parse_terrain(".\n").unwrap();
+        let buffers = generate_mesh(&grid, &style(), &config());
+        assert_eq!(buffers.attributes.len(), 4);
+        assert_eq!(buffers.indices.len(), 6);
+        assert!(buffers.attributes.iter().all(|a| a.space_coord_px.y == 0.));
+    }
+
+    #[test]
+    fn ceilings_face_down_at_wall_height() {
+        let style = style();
+        let mut config = config();
+        config.ceilings = Ceilings::All;
+        let grid = parse_terrain(".\n").unwrap();
+        let ceilings = generate_mesh(&grid, &style, &config)
+            .attributes
+            .into_iter()
+            .filter(|a| a.material == style.ceiling_material)
+            .collect::<Vec<_>>();
+        assert_eq!(ceilings.len(), 4);
+        for a in ceilings {
+            assert_eq!(a.space_coord_px.y, style.height_px);
+            assert_eq!(a.normal, vec3(0., -1., 0.));
+        }
+    }
+
+    #[test]
+    fn levels_are_offset() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("#.\n").unwrap();
+        let single = generate_mesh(&grid, &style, &config);
+        let levels =
+            generate_levels(&[(grid.clone(), 0.), (grid, 100.)], &style, &config);
+        let n = single.attributes.len();
+        assert_eq!(levels.attributes.len(), 2 * n);
+        assert_eq!(levels.indices.len(), 2 * single.indices.len());
+        for (i, a) in single.attributes.iter().enumerate() {
+            let lower = levels.attributes[i].space_coord_px;
+            let upper = levels.attributes[n + i].space_coord_px;
+            assert_eq!(lower, a.space_coord_px);
+            assert_eq!(upper, a.space_coord_px + vec3(0., 100., 0.));
+        }
+    }
+
+    #[test]
+    fn units_per_px_scales_positions() {
+        let style = style();
+        let grid = parse_terrain("#.\n").unwrap();
+        let pixels = generate_mesh(&grid, &style, &config());
+        let mut config = config();
+        config.units_per_px = 0.01;
+        let units = generate_mesh(&grid, &style, &config);
+        assert_eq!(pixels.attributes.len(), units.attributes.len());
+        assert!(pixels.attributes.iter().any(|a| a.space_coord_px.x != 0.));
+        for (p, u) in pixels.attributes.iter().zip(units.attributes.iter()) {
+            assert!((p.space_coord_px.x - u.space_coord_px.x * 100.).abs() < 1e-3);
+            assert_eq!(p.tex_coord_px, u.tex_coord_px);
+        }
+    }
+
+    #[test]
+    fn an_instance_per_wall_quarter() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("#+#\n#.=\n###\n").unwrap();
+        let (pieces, instances) = generate_instanced(&grid, &style, &config);
+        let walls = grid
+            .iter()
+            .filter(|&&cell_type| cell_type != CellType::Floor)
+            .count();
+        assert_eq!(instances.len(), 4 * walls);
+        for instance in instances.iter() {
+            assert!(pieces.contains_key(&(instance.piece, instance.rotated)));
+        }
+    }
+
+    #[test]
+    fn composite_parts_are_offset() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("#\n").unwrap();
+        let single = generate_mesh(&grid, &style, &config);
+        let composite = generate_composite(
+            &[(grid.clone(), Coord::new(0, 0)), (grid, Coord::new(10, 0))],
+            &style,
+            &config,
+        );
+        let n = single.attributes.len();
+        assert_eq!(composite.attributes.len(), 2 * n);
+        for (i, a) in single.attributes.iter().enumerate() {
+            assert_eq!(composite.attributes[i].space_coord_px, a.space_coord_px);
+            assert_eq!(
+                composite.attributes[n + i].space_coord_px,
+                a.space_coord_px + vec3(10. * 32., 0., 0.)
+            );
+        }
+    }
+
+    #[test]
+    fn split_mesh_matches_merged_mesh() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("##\n#.\n").unwrap();
+        let split = generate_mesh_split(&grid, &style, &config);
+        assert!(!split.tops.indices.is_empty());
+        assert!(!split.faces.indices.is_empty());
+        let merged = generate_mesh(&grid, &style, &config);
+        let num_indices = [split.tops, split.faces, split.floors, split.ceilings]
+            .iter()
+            .map(|buffers| buffers.indices.len())
+            .sum::<usize>();
+        assert_eq!(num_indices, merged.indices.len());
+    }
+
+    #[test]
+    fn railings_are_lower_than_walls() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain("#.-\n").unwrap();
+        let buffers = generate_mesh(&grid, &style, &config);
+        let top_height = |min_x: f32, max_x: f32| {
+            buffers
+                .attributes
+                .iter()
+                .filter(|a| a.space_coord_px.x >= min_x && a.space_coord_px.x <= max_x)
+                .map(|a| a.space_coord_px.y)
+                .fold(0., f32::max)
+        };
+        assert_eq!(top_height(0., 32.), style.height_px);
+        assert_eq!(top_height(64., 96.), style.railing_height_px);
+        assert!(style.railing_height_px < style.height_px);
+    }
+
+    #[test]
+    fn face_textures_by_facing() {
+        let config = config();
+        let by_facing = [vec2(0., 0.), vec2(64., 0.), vec2(128., 0.), vec2(192., 0.)];
+        let style = StyleBuilder::new()
+            .face_tex_top_left_px_by_facing(Some(by_facing))
+            .build();
+        let grid = parse_terrain("#\n").unwrap();
+        let buffers = generate_mesh(&grid, &style, &config);
+        // the horizontal texture coordinate of a face decreases along it, and
+        // ends at the left of its texture
+        let tex_base = |normal_z: f32| {
+            buffers
+                .attributes
+                .iter()
+                .filter(|a| (a.normal.z - normal_z).abs() < 1e-4)
+                .map(|a| a.tex_coord_px.x)
+                .fold(f32::INFINITY, f32::min)
+        };
+        let north = tex_base(-1.);
+        let south = tex_base(1.);
+        assert_eq!(north, by_facing[CardinalDirection::North as usize].x);
+        assert_eq!(south, by_facing[CardinalDirection::South as usize].x);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_walls_equal_serial_walls() {
+        let (style, config) = (style(), config());
+        let grid = Grid::new_from_fn(Size::new(24, 16), |coord| {
+            if (coord.x * 7 + coord.y * 3) % 5 == 0 {
+                CellType::Wall
+            } else if (coord.x + coord.y) % 11 == 0 {
+                CellType::Door
+            } else {
+                CellType::Floor
+            }
+        });
+        let detail_grid = cell_details_grid(&grid, None, &style, &config);
+        let serial = detail_grid
+            .enumerate()
+            .filter_map(|(coord, cell)| cell.as_ref().map(|cell| (coord, cell)))
+            .flat_map(|(coord, cell)| cell.make_geometry(coord, &style, &config))
+            .collect::<Vec<_>>();
+        assert!(!serial.is_empty());
+        assert_eq!(make_walls(&detail_grid, &style, &config), serial);
+    }
+
+    #[test]
+    fn crowns_compose_with_baseboards() {
+        let config = config();
+        let grid = parse_terrain("##\n#.\n").unwrap();
+        let vertices = |baseboard_px, crown_px| {
+            let style = StyleBuilder::new()
+                .baseboard_px(baseboard_px)
+                .crown_px(crown_px)
+                .build();
+            generate_mesh(&grid, &style, &config).attributes
+        };
+        let plain = vertices(0., 0.);
+        let crown = vertices(0., 4.);
+        let baseboard = vertices(4., 0.);
+        let both = vertices(4., 4.);
+        assert!(crown.len() > plain.len());
+        // the added vertices are near the top of the walls
+        let height_px = style().height_px;
+        let added = crown
+            .iter()
+            .filter(|a| !plain.contains(a))
+            .collect::<Vec<_>>();
+        assert!(!added.is_empty());
+        assert!(added
+            .iter()
+            .all(|a| a.space_coord_px.y >= height_px - 4.
+                && a.space_coord_px.y <= height_px));
+        assert_eq!(
+            both.len(),
+            plain.len() + (crown.len() - plain.len()) + (baseboard.len() - plain.len())
+        );
+    }
+
+    #[test]
+    fn chunks_equal_whole() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain(concat!("####\n", "#..#\n", "#.##\n")).unwrap();
+        let chunks =
+            generate_chunks(&grid, &style, &config, Size::new(2, 3)).collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 2);
+        let joined =
+            RelativeBuffers::concat_all(chunks.into_iter().map(|chunk| chunk.buffers));
+        assert_eq!(
+            sorted_triangles(&joined),
+            sorted_triangles(&generate_mesh(&grid, &style, &config))
+        );
+    }
+
+    #[test]
+    fn chunk_bounds_contain_vertices() {
+        let (style, config) = (style(), config());
+        let grid = parse_terrain(concat!("####\n", "#..#\n", "#.##\n")).unwrap();
+        for chunk in generate_chunks(&grid, &style, &config, Size::new(3, 2)) {
+            let (min, max) = chunk.bounds.unwrap();
+            for a in chunk.buffers.attributes.iter() {
+                let p = a.space_coord_px;
+                assert!(min.x <= p.x && min.y <= p.y && min.z <= p.z);
+                assert!(p.x <= max.x && p.y <= max.y && p.z <= max.z);
+            }
+        }
+    }
+
+    #[test]
+    fn picking_triangles_lie_in_their_cells() {
+        let (style, config) = (style(), config());
+        let picking =
+            generate_picking_mesh(&parse_terrain("#\n").unwrap(), &style, &config);
+        assert!(!picking.triangle_cells.is_empty());
+        assert_eq!(
+            picking.triangle_cells.len(),
+            picking.buffers.triangle_count()
+        );
+        assert!(picking
+            .triangle_cells
+            .iter()
+            .all(|&c| c == Coord::new(0, 0)));
+        let grid = parse_terrain("###\n#..\n#+#\n").unwrap();
+        let picking = generate_picking_mesh(&grid, &style, &config);
+        assert_eq!(
+            picking.triangle_cells.len(),
+            picking.buffers.triangle_count()
+        );
+        for (t, coord) in picking.buffers.triangles().zip(picking.triangle_cells) {
+            let centre =
+                (t[0].space_coord_px + t[1].space_coord_px + t[2].space_coord_px) / 3.;
+            assert_eq!(
+                Coord::new(
+                    (centre.x / 32.).floor() as i32,
+                    (centre.z / 32.).floor() as i32
+                ),
+                coord
+            );
+        }
+    }
+
+    #[test]
+    fn higher_lods_have_fewer_triangles() {
+        let grid = parse_terrain(concat!(
+            "#########\n",
+            "#...#...#\n",
+            "#.......#\n",
+            "#...#...#\n",
+            "#########\n",
+        ))
+        .unwrap();
+        // faces are only merged at level 2 if their texture wraps
+        let style = StyleBuilder::new()
+            .top_bevel_px(1.)
+            .face_tex_wraps(true)
+            .build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .corner_segments(4)
+            .build();
+        let triangle_count =
+            |lod| generate_lod(&grid, &style, &config, lod).triangle_count();
+        assert_eq!(
+            generate_lod(&grid, &style, &config, 0),
+            generate_mesh(&grid, &style, &config)
+        );
+        assert!(triangle_count(1) < triangle_count(0));
+        assert!(triangle_count(2) < triangle_count(1));
+    }
+
+    #[test]
+    fn walls_as_wide_as_a_quarter_have_finite_normals() {
+        let grid = parse_terrain("#..\n#..\n###\n").unwrap();
+        let config = Config::default();
+        for style in [
+            StyleBuilder::new().build(),
+            StyleBuilder::new()
+                .top_bevel_px(1.)
+                .baseboard_px(2.)
+                .crown_px(2.)
+                .build(),
+        ]
+        .iter()
+        {
+            assert_eq!(style.width_px * 2., config.cell_size_px.x);
+            let buffers = generate_mesh(&grid, style, &config);
+            assert!(!buffers.attributes.is_empty());
+            assert!(buffers
+                .attributes
+                .iter()
+                .all(|a| a.normal.magnitude().is_finite()));
+        }
+    }
+
+    #[test]
+    fn generate_mesh_returns_chooser_errors() {
+        struct FailingChooser;
+        impl PieceChooser for FailingChooser {
+            fn choose(
+                &self,
+                neigh_a: (CellType, CardinalDirection),
+                neigh_b: (CellType, CardinalDirection),
+            ) -> Result<Piece, PieceError> {
+                Err(PieceError { neigh_a, neigh_b })
+            }
+        }
+        let config = Config::default();
+        // grids without walls never consult the chooser
+        let grid = parse_terrain("..\n..\n").unwrap();
+        assert_eq!(
+            generate_mesh_with_chooser(&grid, &FailingChooser, &style(), &config),
+            Ok(generate_mesh(&grid, &style(), &config))
+        );
+        let grid = parse_terrain(".#\n..\n").unwrap();
+        assert!(
+            generate_mesh_with_chooser(&grid, &FailingChooser, &style(), &config)
+                .is_err()
+        );
+        assert_eq!(
+            generate_mesh_with_chooser(&grid, &DefaultPieceChooser, &style(), &config),
+            Ok(generate_mesh(&grid, &style(), &config))
+        );
+    }
+}
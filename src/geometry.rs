@@ -0,0 +1,2218 @@
+use buffers::{face_normal, Attribute, RelativeBuffers};
+use cell::{Diagonal, Junction, Piece};
+use cgmath::{self, vec2, vec3, InnerSpace, Matrix4, SquareMatrix, Vector2, Vector3};
+use direction::{
+    CardinalDirection, CardinalDirections, OrdinalDirection, OrdinalDirections,
+};
+use grid_2d::Coord;
+use std::sync::OnceLock;
+use style::{Config, Style};
+
+// The intermediate buffers used while making each piece only hold a handful
+// of elements, so with the `smallvec` feature they are kept on the stack.
+#[cfg(feature = "smallvec")]
+type SmallBuffer<T> = ::smallvec::SmallVec<[T; 8]>;
+#[cfg(not(feature = "smallvec"))]
+type SmallBuffer<T> = Vec<T>;
+
+struct BaseAttribute {
+    face_tex_offset_px_x: f32,
+    space_coord_px: Vector2<f32>,
+    /// Normal of faces at this point, or `None` to use the normal of each
+    /// segment of the edge base either side of the point
+    normal: Option<Vector2<f32>>,
+}
+
+struct TopAttribute {
+    piece_tex_offset_px: Vector2<f32>,
+    space_coord_px: Vector2<f32>,
+}
+
+impl TopAttribute {
+    fn new(piece_tex_offset_px: Vector2<f32>, space_coord_px: Vector2<f32>) -> Self {
+        Self {
+            piece_tex_offset_px,
+            space_coord_px,
+        }
+    }
+    fn tex_offset_px(&self, style: &Style, config: &Config) -> Vector2<f32> {
+        self.piece_tex_offset_px
+            + rotate_top_tex_offset_px(
+                self.space_coord_px,
+                style.top_tex_quarter_turns,
+                config.tex_top_piece_size,
+            )
+    }
+}
+
+/// Rotates a point in a piece of the top texture of size `piece_size_px` by
+/// `quarter_turns` clockwise quarter turns about the centre of the piece.
+fn rotate_top_tex_offset_px(
+    offset_px: Vector2<f32>,
+    quarter_turns: u32,
+    piece_size_px: f32,
+) -> Vector2<f32> {
+    let s = piece_size_px;
+    match quarter_turns % 4 {
+        0 => offset_px,
+        1 => vec2(s - offset_px.y, offset_px.x),
+        2 => vec2(s - offset_px.x, s - offset_px.y),
+        _ => vec2(offset_px.y, s - offset_px.x),
+    }
+}
+
+// Each segment of the edge base becomes a quad whose vertices alternate
+// between the base and the top of the wall.
+const BASE_TOP_ALTERNATING_INDICES: &[u32] = &[0, 1, 2, 1, 3, 2];
+
+/// Returns the normal of faces along the segment of an edge base from `a` to
+/// `b`, or `None` if the segment has zero length. Such segments occur where
+/// walls are as wide as a quarter, or openings are closed or as wide as the
+/// quarter. They are kept so edge bases can be split at known points, but
+/// nothing is made along them.
+fn segment_normal(a: &BaseAttribute, b: &BaseAttribute) -> Option<Vector2<f32>> {
+    let d = b.space_coord_px - a.space_coord_px;
+    if d.magnitude2() > 0. {
+        Some(vec2(-d.y, d.x).normalize())
+    } else {
+        None
+    }
+}
+
+/// Assigns face texture offsets to the points of an edge base according to
+/// the distance along the edge, such that the final point has an offset of 0.
+/// The total length of texture is rounded to a whole number of
+/// `tex_period_px`, so each end of the edge lies on a multiple of the period
+/// and lines up with the faces of neighbouring pieces. Edges whose length is
+/// not a multiple of the period have their texture stretched slightly to fit.
+fn edge_base_from_points(
+    points: SmallBuffer<Vector2<f32>>,
+    tex_period_px: f32,
+) -> SmallBuffer<BaseAttribute> {
+    let segment_lengths = points
+        .windows(2)
+        .map(|p| (p[1] - p[0]).magnitude())
+        .collect::<SmallBuffer<_>>();
+    let edge_length: f32 = segment_lengths.iter().sum();
+    let tex_scale = if edge_length > 0. {
+        let tex_length = (edge_length / tex_period_px).round().max(1.) * tex_period_px;
+        tex_length / edge_length
+    } else {
+        0.
+    };
+    let mut distance_to_end = edge_length;
+    points
+        .into_iter()
+        .enumerate()
+        .map(|(i, space_coord_px)| {
+            let face_tex_offset_px_x = distance_to_end * tex_scale;
+            if let Some(length) = segment_lengths.get(i) {
+                distance_to_end = (distance_to_end - length).max(0.);
+            }
+            BaseAttribute {
+                face_tex_offset_px_x,
+                space_coord_px,
+                normal: None,
+            }
+        })
+        .collect()
+}
+
+/// Returns the period of face textures for quarters of the given size. This
+/// is half the smaller dimension of a cell, so faces running along either
+/// axis of a non-square cell have the same texture density, and the ends of
+/// pieces line up with whole periods in both directions where possible.
+fn face_tex_period_px(quarter_size_px: Vector2<f32>) -> f32 {
+    quarter_size_px.x.min(quarter_size_px.y)
+}
+
+/// Returns the top-left of the face texture for faces with the normal
+/// `normal` in the local space of a quarter in the direction `direction`.
+/// Faces take the texture of the cardinal direction closest to the direction
+/// they face in the grid.
+fn face_tex_top_left_px(
+    normal: Vector3<f32>,
+    direction: OrdinalDirection,
+    style: &Style,
+) -> Vector2<f32> {
+    let n = (quarter_rotation(direction) * normal.extend(0.)).truncate();
+    let facing = if n.x.abs() > n.z.abs() {
+        if n.x > 0. {
+            CardinalDirection::East
+        } else {
+            CardinalDirection::West
+        }
+    } else if n.z > 0. {
+        CardinalDirection::South
+    } else {
+        CardinalDirection::North
+    };
+    style.face_tex_top_left_px_facing(facing)
+}
+
+/// Returns `segments + 1` points along an arc around `centre`, from `from` to
+/// `to`, which must be the same distance from `centre`. The arc turns through
+/// the smaller angle between them.
+fn arc_points(
+    centre: Vector2<f32>,
+    from: Vector2<f32>,
+    to: Vector2<f32>,
+    segments: u32,
+) -> SmallBuffer<Vector2<f32>> {
+    use std::f32::consts::PI;
+    let radius = (from - centre).magnitude();
+    let from_angle = (from.y - centre.y).atan2(from.x - centre.x);
+    let to_angle = (to.y - centre.y).atan2(to.x - centre.x);
+    let mut delta = to_angle - from_angle;
+    if delta > PI {
+        delta -= 2. * PI;
+    } else if delta < -PI {
+        delta += 2. * PI;
+    }
+    (0..=segments)
+        .map(|i| {
+            let angle = from_angle + delta * (i as f32 / segments as f32);
+            centre + vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+/// Radius of the rounded corner of an inner piece, which is limited so the
+/// arc fits within the quarter.
+fn inner_corner_radius_px(quarter_size_px: Vector2<f32>, style: &Style) -> f32 {
+    let w = style.width_px;
+    w.min(quarter_size_px.x - w).min(quarter_size_px.y - w)
+}
+
+/// Returns the corner arc of a corner or pillar piece, from the end nearer
+/// the left of the quarter to the end nearer the right, and the centre of the
+/// arc. Other pieces and configurations without rounded corners have no arc.
+fn corner_arc(
+    piece: Piece,
+    quarter_size_px: Vector2<f32>,
+    style: &Style,
+    config: &Config,
+) -> Option<(SmallBuffer<Vector2<f32>>, Vector2<f32>)> {
+    let w = style.width_px;
+    let subdivisions = config.subdivisions.max(1);
+    if piece == Piece::Pillar && config.pillar_segments > 0 {
+        let centre = vec2(0., 0.);
+        let segments = config.pillar_segments * subdivisions;
+        let points = arc_points(centre, vec2(0., w), vec2(w, 0.), segments);
+        return Some((points, centre));
+    }
+    if config.corner_segments <= 1 {
+        return None;
+    }
+    let segments = config.corner_segments * subdivisions;
+    match piece {
+        Piece::Outer => {
+            let centre = vec2(0., 0.);
+            let points = arc_points(centre, vec2(0., w), vec2(w, 0.), segments);
+            Some((points, centre))
+        }
+        Piece::Inner => {
+            let r = inner_corner_radius_px(quarter_size_px, style);
+            if r <= 0. {
+                return None;
+            }
+            let centre = vec2(w + r, w + r);
+            let points = arc_points(centre, vec2(w, w + r), vec2(w + r, w), segments);
+            Some((points, centre))
+        }
+        _ => None,
+    }
+}
+
+fn make_edge_base(
+    piece: Piece,
+    quarter_size_px: Vector2<f32>,
+    style: &Style,
+    config: &Config,
+) -> SmallBuffer<BaseAttribute> {
+    let (sx, sz) = (quarter_size_px.x, quarter_size_px.y);
+    let w = style.width_px;
+    if let Some((arc, centre)) = corner_arc(piece, quarter_size_px, style, config) {
+        // faces on the arc point away from its centre on outer corners, and
+        // towards it on inner corners
+        let (points, sign) = match piece {
+            Piece::Inner => {
+                let mut points = SmallBuffer::from(&[vec2(w, sz)][..]);
+                points.extend(arc.iter().cloned());
+                points.push(vec2(sx, w));
+                // the arc meets the ends of the edge when the quarter is small
+                points.dedup();
+                (points, -1.)
+            }
+            _ => (arc.clone(), 1.),
+        };
+        let mut edge_base =
+            edge_base_from_points(points, face_tex_period_px(quarter_size_px));
+        for a in edge_base.iter_mut() {
+            if arc.contains(&a.space_coord_px) {
+                a.normal = Some((a.space_coord_px - centre).normalize() * sign);
+            }
+        }
+        return edge_base;
+    }
+    let points = match piece {
+        Piece::Inner => SmallBuffer::from(&[vec2(w, sz), vec2(w, w), vec2(sx, w)][..]),
+        Piece::Outer | Piece::Pillar => {
+            SmallBuffer::from(&[vec2(0., w), vec2(w, w), vec2(w, 0.)][..])
+        }
+        Piece::Left => SmallBuffer::from(&[vec2(w, sz), vec2(w, 0.)][..]),
+        Piece::Right => SmallBuffer::from(&[vec2(0., w), vec2(sx, w)][..]),
+        Piece::DoorwayLeft | Piece::WindowLeft => {
+            let g = opening_half_width_px(piece, quarter_size_px, style);
+            SmallBuffer::from(&[vec2(w, sz), vec2(w, g), vec2(w, 0.)][..])
+        }
+        Piece::DoorwayRight | Piece::WindowRight => {
+            let g = opening_half_width_px(piece, quarter_size_px, style);
+            SmallBuffer::from(&[vec2(0., w), vec2(g, w), vec2(sx, w)][..])
+        }
+    };
+    edge_base_from_points(points, face_tex_period_px(quarter_size_px))
+}
+
+/// A horizontal band of a face, within which the face texture doesn't
+/// repeat
+struct FaceTexRow {
+    bottom_px: f32,
+    top_px: f32,
+    /// Amount subtracted from the vertical face texture coordinate,
+    /// `style.height_px - y`, within the row
+    tex_offset_px: f32,
+}
+
+/// Splits the heights `bottom_px` to `top_px` into rows at the boundaries
+/// between repetitions of the face texture, counting down from
+/// `style.height_px` (see `Style::face_tex_tile_height_px`). When the face
+/// texture doesn't tile, there is a single row.
+fn face_tex_rows(bottom_px: f32, top_px: f32, style: &Style) -> SmallBuffer<FaceTexRow> {
+    let mut rows = SmallBuffer::new();
+    let tile_height_px = match style.face_tex_tile_height_px {
+        Some(tile_height_px) if tile_height_px > 0. => tile_height_px,
+        _ => {
+            rows.push(FaceTexRow {
+                bottom_px,
+                top_px,
+                tex_offset_px: 0.,
+            });
+            return rows;
+        }
+    };
+    let mut tile = ((style.height_px - top_px) / tile_height_px).floor();
+    let mut row_top_px = top_px;
+    while row_top_px > bottom_px {
+        let tex_offset_px = tile * tile_height_px;
+        let row_bottom_px =
+            (style.height_px - tex_offset_px - tile_height_px).max(bottom_px);
+        rows.push(FaceTexRow {
+            bottom_px: row_bottom_px,
+            top_px: row_top_px,
+            tex_offset_px,
+        });
+        row_top_px = row_bottom_px;
+        tile += 1.;
+    }
+    rows
+}
+
+/// Makes a face for each segment of `edge_base`, extending from `bottom_px`
+/// up to `top_px`, and split into rows where the face texture repeats. The
+/// edge base is in the local space of a quarter in the direction
+/// `quarter_direction`.
+fn make_face_segments(
+    edge_base: &[BaseAttribute],
+    bottom_px: f32,
+    top_px: f32,
+    quarter_direction: OrdinalDirection,
+    style: &Style,
+) -> RelativeBuffers {
+    let mut attributes = Vec::new();
+    let mut indices = Vec::new();
+    let rows = face_tex_rows(bottom_px, top_px, style);
+    for (row, segment) in rows
+        .iter()
+        .flat_map(|row| edge_base.windows(2).map(move |segment| (row, segment)))
+    {
+        // edge bases run such that the floor side of the face is a quarter
+        // turn from the direction of the edge
+        let segment_normal = match segment_normal(&segment[0], &segment[1]) {
+            Some(n) => vec3(n.x, 0., n.y),
+            None => continue,
+        };
+        let tex_top_left_px =
+            face_tex_top_left_px(segment_normal, quarter_direction, style);
+        indices.extend(
+            BASE_TOP_ALTERNATING_INDICES
+                .iter()
+                .map(|i| i + attributes.len() as u32),
+        );
+        for a in segment {
+            let normal = a.normal.map_or(segment_normal, |n| vec3(n.x, 0., n.y));
+            for &y in [row.bottom_px, row.top_px].iter() {
+                attributes.push(Attribute {
+                    tex_coord_px: vec2(
+                        a.face_tex_offset_px_x,
+                        style.height_px - y - row.tex_offset_px,
+                    ) + tex_top_left_px,
+                    space_coord_px: vec3(a.space_coord_px.x, y, a.space_coord_px.y),
+                    normal,
+                    material: style.face_material,
+                    tex_layer: style.face_tex_layer,
+                    ao: 1.,
+                });
+            }
+        }
+    }
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+}
+
+/// Makes a quad from corners listed in order around its perimeter, wound such
+/// that it faces along `normal`.
+fn make_quad(
+    corners: [Vector3<f32>; 4],
+    tex_coords_px: [Vector2<f32>; 4],
+    normal: Vector3<f32>,
+    material: u32,
+    tex_layer: u32,
+) -> RelativeBuffers {
+    const INDICES: &[u32] = &[0, 1, 2, 0, 2, 3];
+    const REVERSED_INDICES: &[u32] = &[0, 2, 1, 0, 3, 2];
+    let indices = if face_normal(corners[0], corners[1], corners[2]).dot(normal) < 0. {
+        REVERSED_INDICES
+    } else {
+        INDICES
+    };
+    let attributes = corners
+        .iter()
+        .zip(tex_coords_px.iter())
+        .map(|(&space_coord_px, &tex_coord_px)| Attribute {
+            space_coord_px,
+            tex_coord_px,
+            normal,
+            material,
+            tex_layer,
+            ao: 1.,
+        })
+        .collect();
+    RelativeBuffers {
+        attributes,
+        indices: indices.to_vec(),
+    }
+}
+
+/// An opening through a straight piece of wall, which spans the heights
+/// `bottom_px` to `top_px`.
+struct Opening {
+    bottom_px: f32,
+    top_px: f32,
+}
+
+/// Makes a triangle wound such that it faces along `normal`.
+fn make_triangle(
+    corners: [Vector3<f32>; 3],
+    tex_coords_px: [Vector2<f32>; 3],
+    normal: Vector3<f32>,
+    material: u32,
+    tex_layer: u32,
+) -> RelativeBuffers {
+    let indices = if face_normal(corners[0], corners[1], corners[2]).dot(normal) < 0. {
+        vec![0, 2, 1]
+    } else {
+        vec![0, 1, 2]
+    };
+    let attributes = corners
+        .iter()
+        .zip(tex_coords_px.iter())
+        .map(|(&space_coord_px, &tex_coord_px)| Attribute {
+            space_coord_px,
+            tex_coord_px,
+            normal,
+            material,
+            tex_layer,
+            ao: 1.,
+        })
+        .collect();
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+}
+
+/// Returns the opening in a piece whose faces span the heights `bottom_px`
+/// to `top_px`, clamped to that range.
+fn opening(piece: Piece, bottom_px: f32, top_px: f32, style: &Style) -> Option<Opening> {
+    let clamp_height = |y: f32| y.max(bottom_px).min(top_px);
+    match piece {
+        Piece::DoorwayLeft | Piece::DoorwayRight => Some(Opening {
+            bottom_px,
+            top_px: clamp_height(style.door_height_px),
+        }),
+        Piece::WindowLeft | Piece::WindowRight => {
+            let bottom_px = clamp_height(style.window_sill_px);
+            Some(Opening {
+                bottom_px,
+                top_px: clamp_height(bottom_px + style.window_height_px),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Half the width of the opening in a piece, measured from the centre of the
+/// cell.
+fn opening_half_width_px(
+    piece: Piece,
+    quarter_size_px: Vector2<f32>,
+    style: &Style,
+) -> f32 {
+    let (fraction, s) = match piece {
+        Piece::WindowLeft => (style.window_width_fraction, quarter_size_px.y),
+        Piece::WindowRight => (style.window_width_fraction, quarter_size_px.x),
+        Piece::DoorwayRight => (style.door_width_fraction, quarter_size_px.x),
+        _ => (style.door_width_fraction, quarter_size_px.y),
+    };
+    (fraction * s).max(0.).min(s)
+}
+
+/// Makes the jamb, and the surfaces above and below an opening.
+fn make_opening(
+    piece: Piece,
+    direction: OrdinalDirection,
+    quarter_size_px: Vector2<f32>,
+    bottom_px: f32,
+    opening: &Opening,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let w = style.width_px;
+    let g = opening_half_width_px(piece, quarter_size_px, style);
+    let (b, t) = (opening.bottom_px, opening.top_px);
+    let s = config.tex_top_piece_size;
+    // coordinates are specified across and along the wall
+    let along_x = matches!(piece, Piece::DoorwayRight | Piece::WindowRight);
+    let space_coord = |across: f32, y: f32, along: f32| {
+        if along_x {
+            vec3(along, y, across)
+        } else {
+            vec3(across, y, along)
+        }
+    };
+    let piece_tex_offset_px = if along_x { vec2(s, 0.) } else { vec2(0., s) };
+    let jamb_normal = space_coord(0., 0., -1.);
+    let jamb_tex_top_left_px = face_tex_top_left_px(jamb_normal, direction, style);
+    let face_tex_coord = |across: f32, y: f32, row: &FaceTexRow| {
+        vec2(w - across, style.height_px - y - row.tex_offset_px) + jamb_tex_top_left_px
+    };
+    let top_tex_coord = |across: f32, along: f32| {
+        let space_coord = space_coord(across, 0., along);
+        TopAttribute::new(piece_tex_offset_px, vec2(space_coord.x, space_coord.z))
+            .tex_offset_px(style, config)
+            + style.top_tex_top_left_px
+    };
+    let horizontal = |y: f32, normal_y: f32| {
+        make_quad(
+            [
+                space_coord(0., y, 0.),
+                space_coord(w, y, 0.),
+                space_coord(w, y, g),
+                space_coord(0., y, g),
+            ],
+            [
+                top_tex_coord(0., 0.),
+                top_tex_coord(w, 0.),
+                top_tex_coord(w, g),
+                top_tex_coord(0., g),
+            ],
+            vec3(0., normal_y, 0.),
+            style.top_material,
+            style.top_tex_layer,
+        )
+    };
+    let jamb =
+        RelativeBuffers::concat_all(face_tex_rows(b, t, style).iter().map(|row| {
+            let (b, t) = (row.bottom_px, row.top_px);
+            make_quad(
+                [
+                    space_coord(0., b, g),
+                    space_coord(w, b, g),
+                    space_coord(w, t, g),
+                    space_coord(0., t, g),
+                ],
+                [
+                    face_tex_coord(0., b, row),
+                    face_tex_coord(w, b, row),
+                    face_tex_coord(w, t, row),
+                    face_tex_coord(0., t, row),
+                ],
+                jamb_normal,
+                style.face_material,
+                style.face_tex_layer,
+            )
+        }));
+    let mut buffers = jamb.concat_into(horizontal(t, -1.));
+    if b > bottom_px {
+        buffers = buffers.concat_into(horizontal(b, 1.));
+    }
+    buffers
+}
+
+/// Returns the direction in which each point of `edge_base` moves when the
+/// top of the wall is inset by a bevel, scaled such that the inset edge is 1
+/// pixel from the original edge.
+fn bevel_insets(edge_base: &[BaseAttribute]) -> SmallBuffer<Vector2<f32>> {
+    let normals = edge_base
+        .windows(2)
+        .map(|segment| segment_normal(&segment[0], &segment[1]))
+        .collect::<SmallBuffer<_>>();
+    (0..edge_base.len())
+        .map(|i| {
+            if let Some(normal) = edge_base[i].normal {
+                return -normal;
+            }
+            // segments of zero length are skipped, so the points either side
+            // of one are inset alike
+            let before = normals[..i].iter().rev().find_map(|&n| n);
+            let after = normals[i..].iter().find_map(|&n| n);
+            match (before, after) {
+                // mitre the corner so both sides are inset by the same amount
+                (Some(a), Some(b)) => -(a + b) / (1. + a.dot(b)),
+                (Some(n), None) | (None, Some(n)) => -n,
+                (None, None) => vec2(0., 0.),
+            }
+        })
+        .collect()
+}
+
+/// Makes the angled strip between the top of the faces along `edge_base`, at
+/// `top_px - bevel_px`, and the edge of the inset top at `top_px`. The strip
+/// is textured with the top of the face texture.
+fn make_bevel(
+    edge_base: &[BaseAttribute],
+    top_px: f32,
+    bevel_px: f32,
+    quarter_direction: OrdinalDirection,
+    style: &Style,
+) -> RelativeBuffers {
+    let insets = bevel_insets(edge_base);
+    // the bevel isn't split, and takes the texture of the row at the top
+    let tex_offset_px = face_tex_rows(top_px - bevel_px, top_px, style)[0].tex_offset_px;
+    let mut attributes = Vec::new();
+    let mut indices = Vec::new();
+    for (i, segment) in edge_base.windows(2).enumerate() {
+        let segment_normal = match segment_normal(&segment[0], &segment[1]) {
+            Some(n) => n,
+            None => continue,
+        };
+        let tex_top_left_px = face_tex_top_left_px(
+            vec3(segment_normal.x, 0., segment_normal.y),
+            quarter_direction,
+            style,
+        );
+        indices.extend(
+            BASE_TOP_ALTERNATING_INDICES
+                .iter()
+                .map(|i| i + attributes.len() as u32),
+        );
+        for (a, inset) in segment.iter().zip(insets[i..].iter()) {
+            let n = a.normal.unwrap_or(segment_normal);
+            let normal = vec3(n.x, 1., n.y).normalize();
+            let inset_coord_px = a.space_coord_px + inset * bevel_px;
+            for &(space_coord_px, y) in [
+                (a.space_coord_px, top_px - bevel_px),
+                (inset_coord_px, top_px),
+            ]
+            .iter()
+            {
+                attributes.push(Attribute {
+                    tex_coord_px: vec2(
+                        a.face_tex_offset_px_x,
+                        style.height_px - y - tex_offset_px,
+                    ) + tex_top_left_px,
+                    space_coord_px: vec3(space_coord_px.x, y, space_coord_px.y),
+                    normal,
+                    material: style.face_material,
+                    tex_layer: style.face_tex_layer,
+                    ao: 1.,
+                });
+            }
+        }
+    }
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+}
+
+/// Makes a strip of trim along `edge_base`, which protrudes `depth_px` out
+/// from the faces between the heights `bottom_px` and `top_px`, with its
+/// corners mitred. The front of the trim is textured with the strip of
+/// texture at `tex_top_left_px` as high as the trim, and its horizontal
+/// surfaces with the strip below that as deep as the trim. The underside is
+/// omitted at floor level. Each entry of `caps` determines whether the start
+/// or end of the trim is closed off.
+fn make_trim(
+    edge_base: &[BaseAttribute],
+    bottom_px: f32,
+    top_px: f32,
+    depth_px: f32,
+    tex_top_left_px: Vector2<f32>,
+    caps: (bool, bool),
+    style: &Style,
+) -> RelativeBuffers {
+    let height_px = top_px - bottom_px;
+    let outsets = bevel_insets(edge_base)
+        .iter()
+        .map(|&inset| -inset * depth_px)
+        .collect::<SmallBuffer<_>>();
+    let mut attributes = Vec::new();
+    let mut indices = Vec::new();
+    let mut surfaces = Vec::new();
+    for (i, segment) in edge_base.windows(2).enumerate() {
+        let segment_normal = match segment_normal(&segment[0], &segment[1]) {
+            Some(n) => n,
+            None => continue,
+        };
+        indices.extend(
+            BASE_TOP_ALTERNATING_INDICES
+                .iter()
+                .map(|i| i + attributes.len() as u32),
+        );
+        for (a, outset) in segment.iter().zip(outsets[i..].iter()) {
+            let n = a.normal.unwrap_or(segment_normal);
+            let p = a.space_coord_px + outset;
+            for &y in [bottom_px, top_px].iter() {
+                attributes.push(Attribute {
+                    tex_coord_px: vec2(a.face_tex_offset_px_x, top_px - y)
+                        + tex_top_left_px,
+                    space_coord_px: vec3(p.x, y, p.y),
+                    normal: vec3(n.x, 0., n.y),
+                    material: style.face_material,
+                    tex_layer: style.face_tex_layer,
+                    ao: 1.,
+                });
+            }
+        }
+        let corner = |j: usize, outset: f32, y: f32| {
+            let p = segment[j].space_coord_px + outsets[i + j] * outset;
+            vec3(p.x, y, p.y)
+        };
+        let tex_coord = |j: usize, outset: f32| {
+            vec2(
+                segment[j].face_tex_offset_px_x,
+                height_px + outset * depth_px,
+            ) + tex_top_left_px
+        };
+        for &(y, normal_y) in [(top_px, 1.), (bottom_px, -1.)].iter() {
+            if y <= 0. {
+                continue;
+            }
+            surfaces.push(make_quad(
+                [
+                    corner(0, 0., y),
+                    corner(1, 0., y),
+                    corner(1, 1., y),
+                    corner(0, 1., y),
+                ],
+                [
+                    tex_coord(0, 0.),
+                    tex_coord(1, 0.),
+                    tex_coord(1, 1.),
+                    tex_coord(0, 1.),
+                ],
+                vec3(0., normal_y, 0.),
+                style.face_material,
+                style.face_tex_layer,
+            ));
+        }
+    }
+    let last = edge_base.len() - 1;
+    for &(cap, i, from) in [(caps.0, 0, 1), (caps.1, last, last - 1)].iter() {
+        if !cap {
+            continue;
+        }
+        // the cap faces away from the rest of the trim, and there's nothing
+        // to close off at the end of a segment of zero length
+        let p = edge_base[i].space_coord_px;
+        let normal = match segment_normal(&edge_base[from], &edge_base[i]) {
+            Some(n) => vec2(n.y, -n.x),
+            None => continue,
+        };
+        let corner = |outset: f32, y: f32| {
+            let c = p + outsets[i] * outset;
+            vec3(c.x, y, c.y)
+        };
+        let tex_coord =
+            |outset: f32, y: f32| vec2(outset * depth_px, top_px - y) + tex_top_left_px;
+        surfaces.push(make_quad(
+            [
+                corner(0., bottom_px),
+                corner(1., bottom_px),
+                corner(1., top_px),
+                corner(0., top_px),
+            ],
+            [
+                tex_coord(0., bottom_px),
+                tex_coord(1., bottom_px),
+                tex_coord(1., top_px),
+                tex_coord(0., top_px),
+            ],
+            vec3(normal.x, 0., normal.y),
+            style.face_material,
+            style.face_tex_layer,
+        ));
+    }
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+    .concat_into(RelativeBuffers::concat_all(surfaces))
+}
+
+/// The heights spanned by a piece of wall, and the depth of the bevel at its
+/// top
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PieceHeights {
+    pub bottom_px: f32,
+    pub top_px: f32,
+    pub bevel_px: f32,
+}
+
+/// Makes the vertical faces of a piece, spanning the heights
+/// `heights.bottom_px` to `heights.top_px`. If `heights.bevel_px` is
+/// positive, the faces stop short of the top by that amount, and are joined
+/// to the edge of the top (see `make_top`) by an angled strip. Faces which
+/// reach the floor have a baseboard if `style.baseboard_px` is positive,
+/// except across doorways.
+pub fn make_faces(
+    piece: Piece,
+    direction: OrdinalDirection,
+    quarter_size_px: Vector2<f32>,
+    heights: PieceHeights,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let PieceHeights {
+        bottom_px,
+        top_px,
+        bevel_px,
+    } = heights;
+    let edge_base = make_edge_base(piece, quarter_size_px, style, config);
+    let bevel_px = bevel_px.max(0.).min(style.width_px);
+    if bevel_px > 0. {
+        let faces = make_faces(
+            piece,
+            direction,
+            quarter_size_px,
+            PieceHeights {
+                bottom_px,
+                top_px: top_px - bevel_px,
+                bevel_px: 0.,
+            },
+            style,
+            config,
+        );
+        return faces
+            .concat_into(make_bevel(&edge_base, top_px, bevel_px, direction, style));
+    }
+    let faces = match opening(piece, bottom_px, top_px, style) {
+        Some(opening) => {
+            // The segment of the edge base in front of the opening only has
+            // faces above and below the opening.
+            let (solid, open) = match piece {
+                Piece::DoorwayRight | Piece::WindowRight => {
+                    (&edge_base[1..], &edge_base[..2])
+                }
+                _ => (&edge_base[..2], &edge_base[1..]),
+            };
+            let mut faces =
+                make_face_segments(solid, bottom_px, top_px, direction, style)
+                    .concat_into(make_opening(
+                        piece,
+                        direction,
+                        quarter_size_px,
+                        bottom_px,
+                        &opening,
+                        style,
+                        config,
+                    ));
+            if opening.top_px < top_px {
+                faces = faces.concat_into(make_face_segments(
+                    open,
+                    opening.top_px,
+                    top_px,
+                    direction,
+                    style,
+                ));
+            }
+            if opening.bottom_px > bottom_px {
+                faces = faces.concat_into(make_face_segments(
+                    open,
+                    bottom_px,
+                    opening.bottom_px,
+                    direction,
+                    style,
+                ));
+            }
+            faces
+        }
+        None => make_face_segments(&edge_base, bottom_px, top_px, direction, style),
+    };
+    if bottom_px > 0. || style.baseboard_px <= 0. {
+        return faces;
+    }
+    // the baseboard stops at the jamb of doorways
+    let (edge_base, caps) = match piece {
+        Piece::DoorwayRight => (&edge_base[1..], (true, false)),
+        Piece::DoorwayLeft => (&edge_base[..2], (false, true)),
+        _ => (&edge_base[..], (false, false)),
+    };
+    faces.concat_into(make_trim(
+        edge_base,
+        bottom_px,
+        style.baseboard_px.min(top_px),
+        style.baseboard_depth_px,
+        style.baseboard_tex_top_left_px,
+        caps,
+        style,
+    ))
+}
+
+/// Makes the crown molding along the faces of a piece, whose top is at
+/// `top_px` and which extends `style.crown_px` down from there, but not
+/// below `bottom_px`. Pieces have no crown if `style.crown_px` isn't
+/// positive.
+pub fn make_crown(
+    piece: Piece,
+    quarter_size_px: Vector2<f32>,
+    bottom_px: f32,
+    top_px: f32,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let crown_bottom_px = (top_px - style.crown_px).max(bottom_px);
+    if style.crown_px <= 0. || crown_bottom_px >= top_px {
+        return RelativeBuffers {
+            attributes: Vec::new(),
+            indices: Vec::new(),
+        };
+    }
+    let edge_base = make_edge_base(piece, quarter_size_px, style, config);
+    make_trim(
+        &edge_base,
+        crown_bottom_px,
+        top_px,
+        style.crown_depth_px,
+        style.crown_tex_top_left_px,
+        (false, false),
+        style,
+    )
+}
+
+fn make_rect_top(
+    size: Vector2<f32>,
+    piece_tex_offset_px: Vector2<f32>,
+) -> (Vec<TopAttribute>, Vec<u32>) {
+    const INDICES: &[u32] = &[0, 1, 2, 0, 2, 3];
+    let attributes = vec![
+        TopAttribute::new(piece_tex_offset_px, vec2(0., 0.)),
+        TopAttribute::new(piece_tex_offset_px, vec2(size.x, 0.)),
+        TopAttribute::new(piece_tex_offset_px, size),
+        TopAttribute::new(piece_tex_offset_px, vec2(0., size.y)),
+    ];
+    (attributes, INDICES.to_vec())
+}
+
+/// Makes the horizontal top of a piece at the height `height_px`. If
+/// `bevel_px` is positive, the edges of the top above the faces of the piece
+/// are inset by that amount.
+pub fn make_top(
+    piece: Piece,
+    quarter_size_px: Vector2<f32>,
+    height_px: f32,
+    bevel_px: f32,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let s = config.tex_top_piece_size;
+    let (sx, sz) = (quarter_size_px.x, quarter_size_px.y);
+    let w = style.width_px;
+    let (attributes, indices) = match piece {
+        Piece::Inner | Piece::Outer | Piece::Pillar
+            if corner_arc(piece, quarter_size_px, style, config).is_some() =>
+        {
+            let (arc, _) = corner_arc(piece, quarter_size_px, style, config)
+                .expect("corner pieces have an arc");
+            // the top is a fan around a point from which the whole outline is
+            // visible, listed in the same order as the other pieces
+            let (points, piece_tex_offset_px) = match piece {
+                Piece::Inner => {
+                    let mut points =
+                        SmallBuffer::from(&[vec2(0., 0.), vec2(sx, 0.), vec2(sx, w)][..]);
+                    points.extend(arc.iter().rev().cloned());
+                    points.extend_from_slice(&[vec2(w, sz), vec2(0., sz)]);
+                    points.dedup();
+                    (points, vec2(0., 0.))
+                }
+                _ => {
+                    let mut points = SmallBuffer::from(&[vec2(0., 0.)][..]);
+                    points.extend(arc.iter().rev().cloned());
+                    (points, vec2(2. * s, 0.))
+                }
+            };
+            let indices = (1..points.len() as u32 - 1)
+                .flat_map(|i| SmallBuffer::from(&[0, i, i + 1][..]))
+                .collect::<Vec<_>>();
+            let attributes = points
+                .into_iter()
+                .map(|p| TopAttribute::new(piece_tex_offset_px, p))
+                .collect::<Vec<_>>();
+            (attributes, indices)
+        }
+        Piece::Inner => {
+            const INDICES: &[u32] = &[0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5];
+            let piece_tex_offset_px = vec2(0., 0.);
+            (
+                vec![
+                    TopAttribute::new(piece_tex_offset_px, vec2(w, w)),
+                    TopAttribute::new(piece_tex_offset_px, vec2(w, sz)),
+                    TopAttribute::new(piece_tex_offset_px, vec2(0., sz)),
+                    TopAttribute::new(piece_tex_offset_px, vec2(0., 0.)),
+                    TopAttribute::new(piece_tex_offset_px, vec2(sx, 0.)),
+                    TopAttribute::new(piece_tex_offset_px, vec2(sx, w)),
+                ],
+                INDICES.to_vec(),
+            )
+        }
+        Piece::Outer | Piece::Pillar => make_rect_top(vec2(w, w), vec2(2. * s, 0.)),
+        Piece::Left | Piece::DoorwayLeft | Piece::WindowLeft => {
+            make_rect_top(vec2(w, sz), vec2(0., s))
+        }
+        Piece::Right | Piece::DoorwayRight | Piece::WindowRight => {
+            make_rect_top(vec2(sx, w), vec2(s, 0.))
+        }
+    };
+    let bevel_px = bevel_px.max(0.).min(w);
+    let attributes = if bevel_px > 0. {
+        let edge_base = make_edge_base(piece, quarter_size_px, style, config);
+        let insets = bevel_insets(&edge_base);
+        attributes
+            .into_iter()
+            .map(|a| {
+                match edge_base
+                    .iter()
+                    .position(|b| b.space_coord_px == a.space_coord_px)
+                {
+                    Some(i) => TopAttribute::new(
+                        a.piece_tex_offset_px,
+                        a.space_coord_px + insets[i] * bevel_px,
+                    ),
+                    None => a,
+                }
+            })
+            .collect()
+    } else {
+        attributes
+    };
+    let attributes = attributes
+        .iter()
+        .map(|a| {
+            let space_coord_px = vec3(a.space_coord_px.x, height_px, a.space_coord_px.y);
+            let tex_coord_px = a.tex_offset_px(style, config) + style.top_tex_top_left_px;
+            Attribute {
+                space_coord_px,
+                tex_coord_px,
+                normal: vec3(0., 1., 0.),
+                material: style.top_material,
+                tex_layer: style.top_tex_layer,
+                ao: 1.,
+            }
+        })
+        .collect::<Vec<_>>();
+    RelativeBuffers {
+        attributes,
+        indices,
+    }
+}
+
+/// Returns true if the quarters of junctions share a single top (see
+/// `make_junction_top`), which is the case unless the tops of quarters would
+/// be bevelled or have rounded corners.
+pub fn junctions_share_tops(style: &Style, config: &Config) -> bool {
+    let half = config.cell_size_px / 2.;
+    style.top_bevel_px <= 0. && corner_arc(Piece::Inner, half, style, config).is_none()
+}
+
+/// Makes a single top covering the whole of a junction at the height
+/// `height_px`, centred on the origin, in place of the tops of its quarters.
+/// The top is a fan around the centre of the cell, split along the axes of
+/// the cell so the inner piece of the top texture can be mirrored into each
+/// quarter without seams. Returns `None` if the tops of the quarters would be
+/// bevelled or have rounded corners, as only flat outlines are supported (see
+/// `junctions_share_tops`).
+pub fn make_junction_top(
+    junction: Junction,
+    height_px: f32,
+    style: &Style,
+    config: &Config,
+) -> Option<RelativeBuffers> {
+    if !junctions_share_tops(style, config) {
+        return None;
+    }
+    let half = config.cell_size_px / 2.;
+    let w = style.width_px;
+    let unit = |direction: CardinalDirection| {
+        let coord = direction.coord();
+        vec2(coord.x as f32, coord.y as f32)
+    };
+    let end = |direction: CardinalDirection| {
+        let u = unit(direction);
+        vec2(u.x * half.x, u.y * half.y)
+    };
+    // the outline of each quarter runs from the axis of one cardinal
+    // direction to the next, such that each triangle of the fan lies within
+    // a single quarter
+    let mut points = SmallBuffer::new();
+    for &(a, b) in [
+        (CardinalDirection::East, CardinalDirection::South),
+        (CardinalDirection::South, CardinalDirection::West),
+        (CardinalDirection::West, CardinalDirection::North),
+        (CardinalDirection::North, CardinalDirection::East),
+    ]
+    .iter()
+    {
+        let (arm_a, arm_b) = (junction.has_arm(a), junction.has_arm(b));
+        if arm_a {
+            points.extend_from_slice(&[end(a), end(a) + unit(b) * w]);
+        } else {
+            points.push(unit(a) * w);
+        }
+        if arm_a && arm_b {
+            points.push((unit(a) + unit(b)) * w);
+        }
+        if arm_b {
+            points.push(unit(a) * w + end(b));
+        }
+    }
+    let mut fan = vec![vec2(0., 0.)];
+    fan.extend(points.iter().cloned());
+    let n = points.len() as u32;
+    let mut indices = (1..n + 1)
+        .flat_map(|i| SmallBuffer::from(&[0, i, i % n + 1][..]))
+        .collect::<Vec<_>>();
+    let up = vec3(0., 1., 0.);
+    let corner = |i: u32| {
+        let p = fan[i as usize];
+        vec3(p.x, height_px, p.y)
+    };
+    if face_normal(corner(indices[0]), corner(indices[1]), corner(indices[2])).dot(up)
+        < 0.
+    {
+        for t in indices.chunks_mut(3) {
+            t.swap(1, 2);
+        }
+    }
+    let attributes = fan
+        .iter()
+        .map(|&p| {
+            let mirrored = vec2(p.x.abs(), p.y.abs());
+            let tex_offset_px =
+                TopAttribute::new(vec2(0., 0.), mirrored).tex_offset_px(style, config);
+            Attribute {
+                space_coord_px: vec3(p.x, height_px, p.y),
+                tex_coord_px: tex_offset_px + style.top_tex_top_left_px,
+                normal: up,
+                material: style.top_material,
+                tex_layer: style.top_tex_layer,
+                ao: 1.,
+            }
+        })
+        .collect();
+    Some(RelativeBuffers {
+        attributes,
+        indices,
+    })
+}
+
+/// Makes the top and faces of a piece of wall spanning `heights`, with the
+/// top bevelled by `heights.bevel_px`.
+pub fn make_geometry(
+    piece: Piece,
+    direction: OrdinalDirection,
+    quarter_size_px: Vector2<f32>,
+    heights: PieceHeights,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let heights = PieceHeights {
+        bevel_px: heights.bevel_px.min(heights.top_px - heights.bottom_px),
+        ..heights
+    };
+    let top = make_top(
+        piece,
+        quarter_size_px,
+        heights.top_px,
+        heights.bevel_px,
+        style,
+        config,
+    );
+    let faces = make_faces(piece, direction, quarter_size_px, heights, style, config);
+    top.concat_into(faces)
+}
+
+/// Returns the size of a quarter of a cell in the direction `direction` from
+/// the centre, in the local space of the quarter. Quarters which are rotated
+/// a quarter turn relative to the grid have their dimensions swapped.
+pub fn quarter_size_px(direction: OrdinalDirection, config: &Config) -> Vector2<f32> {
+    let half = config.cell_size_px / 2.;
+    match direction {
+        OrdinalDirection::SouthEast | OrdinalDirection::NorthWest => half,
+        OrdinalDirection::NorthEast | OrdinalDirection::SouthWest => vec2(half.y, half.x),
+    }
+}
+
+/// Makes a wall running between opposite corners of the cell at `coord`. The
+/// wall extends `style.width_px` either side of the diagonal, and each entry
+/// of `end_caps` determines whether the corresponding end of the wall (in the
+/// order returned by `Diagonal::ends`) is closed off with a face.
+pub fn make_diagonal(
+    coord: Coord,
+    diagonal: Diagonal,
+    end_caps: (bool, bool),
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let (tops, faces) =
+        make_diagonal_tops_and_faces(coord, diagonal, end_caps, style, config);
+    faces.concat_into(tops)
+}
+
+/// Like `make_diagonal`, but returns the top of the wall separately from its
+/// vertical faces.
+pub fn make_diagonal_tops_and_faces(
+    coord: Coord,
+    diagonal: Diagonal,
+    end_caps: (bool, bool),
+    style: &Style,
+    config: &Config,
+) -> (RelativeBuffers, RelativeBuffers) {
+    let style = &*style.for_cell(coord, config);
+    let w = style.width_px;
+    let s = config.tex_top_piece_size;
+    // the wall is made running from north west to south east, then rotated
+    let direction = match diagonal {
+        Diagonal::NorthWestToSouthEast => OrdinalDirection::SouthEast,
+        Diagonal::NorthEastToSouthWest => OrdinalDirection::SouthWest,
+    };
+    let half = quarter_size_px(direction, config);
+    let start = -half;
+    let end = half;
+    let along = (end - start).normalize();
+    let across = vec2(along.y, -along.x) * w;
+    let length_px = (end - start).magnitude();
+    let faces = [(start, end, across), (end, start, -across)]
+        .iter()
+        .map(|&(from, to, offset)| {
+            let edge_base = edge_base_from_points(
+                SmallBuffer::from(&[to + offset, from + offset][..]),
+                face_tex_period_px(half),
+            );
+            make_face_segments(&edge_base, 0., style.height_px, direction, style)
+        })
+        .collect::<Vec<_>>();
+    // the top is split into pieces along its length, each of which is
+    // textured like the top of a straight piece
+    let num_top_pieces = (length_px / s).round().max(1.) as usize;
+    let piece_length_px = length_px / num_top_pieces as f32;
+    let tops = (0..num_top_pieces)
+        .flat_map(|i| {
+            [across, -across]
+                .iter()
+                .map(move |&offset| {
+                    let corner = |a: f32, b: f32| {
+                        let c =
+                            start + along * (i as f32 + b) * piece_length_px + offset * a;
+                        vec3(c.x, style.height_px, c.y)
+                    };
+                    let tex_coord = |a: f32, b: f32| {
+                        rotate_top_tex_offset_px(
+                            vec2(a * w, b * s),
+                            style.top_tex_quarter_turns,
+                            s,
+                        ) + vec2(0., s)
+                            + style.top_tex_top_left_px
+                    };
+                    make_quad(
+                        [
+                            corner(0., 0.),
+                            corner(1., 0.),
+                            corner(1., 1.),
+                            corner(0., 1.),
+                        ],
+                        [
+                            tex_coord(0., 0.),
+                            tex_coord(1., 0.),
+                            tex_coord(1., 1.),
+                            tex_coord(0., 1.),
+                        ],
+                        vec3(0., 1., 0.),
+                        style.top_material,
+                        style.top_tex_layer,
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let caps = [(end_caps.0, start, -along), (end_caps.1, end, along)]
+        .iter()
+        .filter(|&&(cap, _, _)| cap)
+        .flat_map(|&(_, centre, normal)| {
+            let normal = vec3(normal.x, 0., normal.y);
+            let corner = move |a: f32, y: f32| {
+                let c = centre + across * a;
+                vec3(c.x, y, c.y)
+            };
+            let tex_top_left_px = face_tex_top_left_px(normal, direction, style);
+            face_tex_rows(0., style.height_px, style)
+                .into_iter()
+                .map(move |row| {
+                    let tex_coord = |a: f32, y: f32| {
+                        vec2(w - a * w, style.height_px - y - row.tex_offset_px)
+                            + tex_top_left_px
+                    };
+                    let (b, t) = (row.bottom_px, row.top_px);
+                    make_quad(
+                        [corner(-1., b), corner(1., b), corner(1., t), corner(-1., t)],
+                        [
+                            tex_coord(-1., b),
+                            tex_coord(1., b),
+                            tex_coord(1., t),
+                            tex_coord(-1., t),
+                        ],
+                        normal,
+                        style.face_material,
+                        style.face_tex_layer,
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+    let transform = move_to_cell_centre(coord, config) * quarter_rotation(direction);
+    (
+        RelativeBuffers::concat_all(tops).transform(transform),
+        RelativeBuffers::concat_all(faces.into_iter().chain(caps)).transform(transform),
+    )
+}
+
+/// Makes stairs filling the cell at `coord`, which climb from the floor to
+/// `style.height_px` in the direction `direction`, in
+/// `config.stair_step_count` steps. Each step has a tread on top and a riser
+/// at the front, and the sides and back of the stairs are closed off with
+/// faces.
+pub fn make_stairs(
+    coord: Coord,
+    direction: CardinalDirection,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let (treads, faces) = make_stairs_treads_and_faces(coord, direction, style, config);
+    faces.concat_into(treads)
+}
+
+/// Like `make_stairs`, but returns the horizontal treads separately from the
+/// vertical faces.
+pub fn make_stairs_treads_and_faces(
+    coord: Coord,
+    direction: CardinalDirection,
+    style: &Style,
+    config: &Config,
+) -> (RelativeBuffers, RelativeBuffers) {
+    let style = &*style.for_cell(coord, config);
+    let step_count = config.stair_step_count.max(1);
+    let half = config.cell_size_px / 2.;
+    let d = direction.coord();
+    let along = vec2(d.x as f32, d.y as f32);
+    let across = vec2(-along.y, along.x);
+    let (half_length, half_width) = if d.x != 0 {
+        (half.x, half.y)
+    } else {
+        (half.y, half.x)
+    };
+    let step_length_px = 2. * half_length / step_count as f32;
+    let rise_px = style.height_px / step_count as f32;
+    // positions are measured along and across the direction of the stairs
+    let point = |a: f32, c: f32| along * a + across * c;
+    let step_start = |i: u32| -half_length + i as f32 * step_length_px;
+    let treads = (0..step_count)
+        .map(|i| {
+            let y = (i + 1) as f32 * rise_px;
+            let corners = [
+                point(step_start(i), -half_width),
+                point(step_start(i + 1), -half_width),
+                point(step_start(i + 1), half_width),
+                point(step_start(i), half_width),
+            ];
+            make_quad(
+                [
+                    vec3(corners[0].x, y, corners[0].y),
+                    vec3(corners[1].x, y, corners[1].y),
+                    vec3(corners[2].x, y, corners[2].y),
+                    vec3(corners[3].x, y, corners[3].y),
+                ],
+                [
+                    corners[0] + half + style.top_tex_top_left_px,
+                    corners[1] + half + style.top_tex_top_left_px,
+                    corners[2] + half + style.top_tex_top_left_px,
+                    corners[3] + half + style.top_tex_top_left_px,
+                ],
+                vec3(0., 1., 0.),
+                style.top_material,
+                style.top_tex_layer,
+            )
+        })
+        .collect::<Vec<_>>();
+    // edge bases run such that the floor side of the face is a quarter turn
+    // from the direction of the edge, and the cell is in the space of
+    // south-east quarters
+    let tex_period_px = face_tex_period_px(half);
+    let face_segments = |points: &[Vector2<f32>], bottom_px: f32, top_px: f32| {
+        let edge_base = edge_base_from_points(SmallBuffer::from(points), tex_period_px);
+        make_face_segments(
+            &edge_base,
+            bottom_px,
+            top_px,
+            OrdinalDirection::SouthEast,
+            style,
+        )
+    };
+    let risers = (0..step_count).map(|i| {
+        let a = step_start(i);
+        face_segments(
+            &[point(a, -half_width), point(a, half_width)],
+            i as f32 * rise_px,
+            (i + 1) as f32 * rise_px,
+        )
+    });
+    // each side is a single edge base, so its texture is continuous, and the
+    // segment beside each step rises to the tread of the step
+    let sides = [1., -1.]
+        .iter()
+        .flat_map(|&side| {
+            let mut steps = (0..=step_count).collect::<Vec<_>>();
+            if side < 0. {
+                steps.reverse();
+            }
+            let points = steps
+                .iter()
+                .map(|&i| point(step_start(i), side * half_width))
+                .collect::<SmallBuffer<_>>();
+            let edge_base = edge_base_from_points(points, tex_period_px);
+            (0..step_count as usize)
+                .map(|j| {
+                    let i = if side < 0. {
+                        step_count as usize - 1 - j
+                    } else {
+                        j
+                    };
+                    make_face_segments(
+                        &edge_base[j..j + 2],
+                        0.,
+                        (i + 1) as f32 * rise_px,
+                        OrdinalDirection::SouthEast,
+                        style,
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let back = face_segments(
+        &[
+            point(half_length, half_width),
+            point(half_length, -half_width),
+        ],
+        0.,
+        style.height_px,
+    );
+    let transform = move_to_cell_centre(coord, config);
+    (
+        RelativeBuffers::concat_all(treads).transform(transform),
+        RelativeBuffers::concat_all(risers.chain(sides).chain(Some(back)))
+            .transform(transform),
+    )
+}
+
+/// Makes a ramp filling the cell at `coord`, which slopes up from the floor
+/// to `style.height_px` in the direction `direction`. The ramp has a sloped
+/// top, triangular sides, and a face at the back.
+pub fn make_ramp(
+    coord: Coord,
+    direction: CardinalDirection,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers {
+    let (slope, faces) = make_ramp_slope_and_faces(coord, direction, style, config);
+    faces.concat_into(slope)
+}
+
+/// Like `make_ramp`, but returns the sloped top separately from the vertical
+/// faces.
+pub fn make_ramp_slope_and_faces(
+    coord: Coord,
+    direction: CardinalDirection,
+    style: &Style,
+    config: &Config,
+) -> (RelativeBuffers, RelativeBuffers) {
+    let style = &*style.for_cell(coord, config);
+    let half = config.cell_size_px / 2.;
+    let d = direction.coord();
+    let along = vec2(d.x as f32, d.y as f32);
+    let across = vec2(-along.y, along.x);
+    let (half_length, half_width) = if d.x != 0 {
+        (half.x, half.y)
+    } else {
+        (half.y, half.x)
+    };
+    let h = style.height_px;
+    // positions are measured along and across the direction of the ramp
+    let point = |a: f32, c: f32| along * a + across * c;
+    let corners = [
+        point(-half_length, -half_width),
+        point(half_length, -half_width),
+        point(half_length, half_width),
+        point(-half_length, half_width),
+    ];
+    let height_at = |i: usize| if i == 1 || i == 2 { h } else { 0. };
+    // the slope faces up, and back towards its low edge
+    let slope_normal = vec3(-along.x * h, 2. * half_length, -along.y * h).normalize();
+    let slope = make_quad(
+        [
+            vec3(corners[0].x, height_at(0), corners[0].y),
+            vec3(corners[1].x, height_at(1), corners[1].y),
+            vec3(corners[2].x, height_at(2), corners[2].y),
+            vec3(corners[3].x, height_at(3), corners[3].y),
+        ],
+        [
+            corners[0] + half + style.top_tex_top_left_px,
+            corners[1] + half + style.top_tex_top_left_px,
+            corners[2] + half + style.top_tex_top_left_px,
+            corners[3] + half + style.top_tex_top_left_px,
+        ],
+        slope_normal,
+        style.top_material,
+        style.top_tex_layer,
+    );
+    // edge bases run such that the floor side of the face is a quarter turn
+    // from the direction of the edge, and the cell is in the space of
+    // south-east quarters
+    let tex_period_px = face_tex_period_px(half);
+    let sides = [
+        (1., direction.right90(), -half_length, half_length),
+        (-1., direction.left90(), half_length, -half_length),
+    ]
+    .iter()
+    .map(|&(side, facing, from, to)| {
+        let edge_base = edge_base_from_points(
+            SmallBuffer::from(
+                &[point(from, side * half_width), point(to, side * half_width)][..],
+            ),
+            tex_period_px,
+        );
+        let tex_top_left_px = style.face_tex_top_left_px_facing(facing);
+        let vertex = |a: &BaseAttribute, y: f32| {
+            (
+                vec3(a.space_coord_px.x, y, a.space_coord_px.y),
+                vec2(a.face_tex_offset_px_x, h - y) + tex_top_left_px,
+            )
+        };
+        // the side rises towards the end of the ramp at the top of the slope
+        let (high, low) = if from < to {
+            (&edge_base[1], &edge_base[0])
+        } else {
+            (&edge_base[0], &edge_base[1])
+        };
+        let corners = [vertex(low, 0.), vertex(high, 0.), vertex(high, h)];
+        let normal = across * side;
+        make_triangle(
+            [corners[0].0, corners[1].0, corners[2].0],
+            [corners[0].1, corners[1].1, corners[2].1],
+            vec3(normal.x, 0., normal.y),
+            style.face_material,
+            style.face_tex_layer,
+        )
+    })
+    .collect::<Vec<_>>();
+    let back_edge_base = edge_base_from_points(
+        SmallBuffer::from(
+            &[
+                point(half_length, half_width),
+                point(half_length, -half_width),
+            ][..],
+        ),
+        tex_period_px,
+    );
+    let back =
+        make_face_segments(&back_edge_base, 0., h, OrdinalDirection::SouthEast, style);
+    let transform = move_to_cell_centre(coord, config);
+    (
+        slope.transform(transform),
+        RelativeBuffers::concat_all(sides.into_iter().chain(Some(back)))
+            .transform(transform),
+    )
+}
+
+/// Makes a wall whose base is the rectangle centred at `centre_px` with the
+/// half-dimensions `half_size_px`, measured from the top-left corner of the
+/// grid. The wall has a top, and a face on each side in the cardinal
+/// directions for which `has_face` returns true.
+pub fn make_slab<F>(
+    centre_px: Vector2<f32>,
+    half_size_px: Vector2<f32>,
+    has_face: F,
+    style: &Style,
+    config: &Config,
+) -> RelativeBuffers
+where
+    F: Fn(CardinalDirection) -> bool,
+{
+    let faces = CardinalDirections
+        .into_iter()
+        .filter(|&d| has_face(d))
+        .map(|d| {
+            let coord = d.coord();
+            let n = vec2(coord.x as f32, coord.y as f32);
+            // the side runs such that the floor is a quarter turn from the
+            // direction of the edge, as with the edge bases of pieces
+            let perp = vec2(-n.y, n.x);
+            let offset = vec2(n.x * half_size_px.x, n.y * half_size_px.y);
+            let half_length = vec2(perp.x * half_size_px.x, perp.y * half_size_px.y);
+            let edge_base = edge_base_from_points(
+                SmallBuffer::from(
+                    &[
+                        centre_px + offset + half_length,
+                        centre_px + offset - half_length,
+                    ][..],
+                ),
+                face_tex_period_px(config.cell_size_px / 2.),
+            );
+            // slabs are made in the space of the grid, which is also the
+            // space of south-east quarters
+            make_face_segments(
+                &edge_base,
+                0.,
+                style.height_px,
+                OrdinalDirection::SouthEast,
+                style,
+            )
+        })
+        .collect::<Vec<_>>();
+    let corner = |x: f32, z: f32| {
+        let c = centre_px + vec2(x * half_size_px.x, z * half_size_px.y);
+        vec3(c.x, style.height_px, c.y)
+    };
+    let tex_coord = |x: f32, z: f32| {
+        vec2((x + 1.) * half_size_px.x, (z + 1.) * half_size_px.y)
+            + style.top_tex_top_left_px
+    };
+    let top = make_quad(
+        [
+            corner(-1., -1.),
+            corner(1., -1.),
+            corner(1., 1.),
+            corner(-1., 1.),
+        ],
+        [
+            tex_coord(-1., -1.),
+            tex_coord(1., -1.),
+            tex_coord(1., 1.),
+            tex_coord(-1., 1.),
+        ],
+        vec3(0., 1., 0.),
+        style.top_material,
+        style.top_tex_layer,
+    );
+    RelativeBuffers::concat_all(faces.into_iter().chain(Some(top)))
+        .transform(Matrix4::from_scale(config.units_per_px))
+}
+
+/// Makes a quad covering the cell at `coord` at the height `height_px`.
+fn make_cell_quad(
+    coord: Coord,
+    height_px: f32,
+    tex_top_left_px: Vector2<f32>,
+    normal: Vector3<f32>,
+    material: u32,
+    tex_layer: u32,
+    config: &Config,
+) -> RelativeBuffers {
+    let (hx, hz) = (config.cell_size_px.x / 2., config.cell_size_px.y / 2.);
+    let corner = |x: f32, z: f32| vec3(x, height_px, z);
+    let tex_coord = |x: f32, z: f32| vec2(x + hx, z + hz) + tex_top_left_px;
+    make_quad(
+        [
+            corner(-hx, -hz),
+            corner(hx, -hz),
+            corner(hx, hz),
+            corner(-hx, hz),
+        ],
+        [
+            tex_coord(-hx, -hz),
+            tex_coord(hx, -hz),
+            tex_coord(hx, hz),
+            tex_coord(-hx, hz),
+        ],
+        normal,
+        material,
+        tex_layer,
+    )
+    .transform(move_to_cell_centre(coord, config))
+}
+
+/// Makes a quad covering the floor of the cell at `coord`, facing upwards like
+/// the tops of walls.
+pub fn make_floor(coord: Coord, style: &Style, config: &Config) -> RelativeBuffers {
+    make_cell_quad(
+        coord,
+        0.,
+        style.floor_tex_top_left_px,
+        vec3(0., 1., 0.),
+        style.floor_material,
+        style.floor_tex_layer,
+        config,
+    )
+}
+
+/// Makes a quad covering the cell at `coord` at the height of the walls,
+/// facing downwards.
+pub fn make_ceiling(coord: Coord, style: &Style, config: &Config) -> RelativeBuffers {
+    make_cell_quad(
+        coord,
+        style.height_px,
+        style.ceiling_tex_top_left_px,
+        vec3(0., -1., 0.),
+        style.ceiling_material,
+        style.ceiling_tex_layer,
+        config,
+    )
+}
+
+/// Returns the transformation from the local space of a cell to world space,
+/// which also scales from pixels to world units.
+pub fn move_to_cell_centre(coord: Coord, config: &Config) -> Matrix4<f32> {
+    let position = vec2(
+        coord.x as f32 * config.cell_size_px.x,
+        coord.y as f32 * config.cell_size_px.y,
+    ) + config.cell_size_px / 2.;
+    Matrix4::from_scale(config.units_per_px)
+        * Matrix4::from_translation(vec3(position.x, 0., position.y))
+}
+
+/// Returns a translation by `offset` whole cells.
+pub fn move_by_cells(offset: Coord, config: &Config) -> Matrix4<f32> {
+    let position = vec2(
+        offset.x as f32 * config.cell_size_px.x,
+        offset.y as f32 * config.cell_size_px.y,
+    );
+    Matrix4::from_scale(config.units_per_px)
+        * Matrix4::from_translation(vec3(position.x, 0., position.y))
+}
+
+pub fn rotate_to_direction(direction: OrdinalDirection) -> Matrix4<f32> {
+    let angle = match direction {
+        OrdinalDirection::NorthEast => ::std::f32::consts::PI / 2.,
+        OrdinalDirection::SouthEast => 0.,
+        OrdinalDirection::SouthWest => -::std::f32::consts::PI / 2.,
+        OrdinalDirection::NorthWest => ::std::f32::consts::PI,
+    };
+    Matrix4::from_angle_y(cgmath::Rad(angle))
+}
+
+// every quarter of every cell is rotated, but there are only four
+// directions, so the rotations are made once and shared by every thread
+static QUARTER_ROTATIONS: OnceLock<[Matrix4<f32>; 4]> = OnceLock::new();
+
+/// Returns the same matrix as `rotate_to_direction`, without recomputing it.
+pub fn quarter_rotation(direction: OrdinalDirection) -> Matrix4<f32> {
+    let rotations = QUARTER_ROTATIONS.get_or_init(|| {
+        let mut rotations = [Matrix4::identity(); 4];
+        for direction in OrdinalDirections {
+            rotations[direction as usize] = rotate_to_direction(direction);
+        }
+        rotations
+    });
+    rotations[direction as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec4;
+    use style::{ConfigBuilder, StyleBuilder};
+
+    fn style() -> Style {
+        StyleBuilder::new().build()
+    }
+
+    /// Cells of the size the default style is made for
+    fn config() -> Config {
+        ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build()
+    }
+
+    fn quarter(piece: Piece, style: &Style, config: &Config) -> RelativeBuffers {
+        make_geometry(
+            piece,
+            OrdinalDirection::SouthEast,
+            quarter_size_px(OrdinalDirection::SouthEast, config),
+            PieceHeights {
+                bottom_px: 0.,
+                top_px: style.height_px,
+                bevel_px: style.top_bevel_px,
+            },
+            style,
+            config,
+        )
+    }
+
+    #[test]
+    fn quarter_rotations_match_rotate_to_direction() {
+        for direction in OrdinalDirections {
+            assert_eq!(quarter_rotation(direction), rotate_to_direction(direction));
+        }
+    }
+
+    #[test]
+    fn outer_top_normals_face_up() {
+        let (style, config) = (style(), config());
+        let top = make_top(
+            Piece::Outer,
+            vec2(16., 16.),
+            style.height_px,
+            0.,
+            &style,
+            &config,
+        );
+        assert!(!top.attributes.is_empty());
+        for a in top.attributes.iter() {
+            assert_eq!(a.normal, vec3(0., 1., 0.));
+        }
+        let quarter = quarter(Piece::Outer, &style, &config);
+        let tops = quarter
+            .attributes
+            .iter()
+            .filter(|a| a.space_coord_px.y == style.height_px && a.normal.y != 0.);
+        assert!(tops.clone().count() > 0);
+        assert!(tops.clone().all(|a| a.normal == vec3(0., 1., 0.)));
+    }
+
+    #[test]
+    fn face_tex_coords_continue_along_edges() {
+        // widths which aren't half of the quarter size
+        for &width_px in [3., 5., 6.5].iter() {
+            let style = StyleBuilder::new().width_px(width_px).build();
+            for &corner_segments in [1, 4].iter() {
+                let mut config = config();
+                config.corner_segments = corner_segments;
+                for &piece in
+                    [Piece::Outer, Piece::Inner, Piece::Left, Piece::Right].iter()
+                {
+                    let faces = make_faces(
+                        piece,
+                        OrdinalDirection::SouthEast,
+                        vec2(16., 16.),
+                        PieceHeights {
+                            bottom_px: 0.,
+                            top_px: style.height_px,
+                            bevel_px: 0.,
+                        },
+                        &style,
+                        &config,
+                    );
+                    // each segment is a bottom and top vertex at its start,
+                    // followed by the same at its end
+                    let segments = faces.attributes.chunks(4).collect::<Vec<_>>();
+                    for segment in segments.iter() {
+                        assert!(segment[0].tex_coord_px.x > segment[2].tex_coord_px.x);
+                        assert_eq!(segment[0].tex_coord_px.x, segment[1].tex_coord_px.x);
+                    }
+                    for pair in segments.windows(2) {
+                        assert_eq!(pair[0][2].space_coord_px, pair[1][0].space_coord_px);
+                        assert_eq!(pair[0][2].tex_coord_px.x, pair[1][0].tex_coord_px.x);
+                    }
+                    // the edge ends at the left of the texture, and starts a
+                    // whole number of periods from it, so it continues into
+                    // the faces of neighbouring pieces
+                    let left_px = style.face_tex_top_left_px.x;
+                    let last = segments.last().unwrap();
+                    assert_eq!(last[2].tex_coord_px.x, left_px);
+                    let start_px = segments[0][0].tex_coord_px.x - left_px;
+                    assert!(start_px > 0. && start_px % 16. == 0.);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn doorways_leave_a_gap() {
+        let (style, config) = (style(), config());
+        let g = opening_half_width_px(Piece::DoorwayLeft, vec2(16., 16.), &style);
+        assert!(g > 0.);
+        let floor_level = |piece| {
+            quarter(piece, &style, &config)
+                .attributes
+                .into_iter()
+                .filter(|a| a.space_coord_px.y == 0.)
+                .map(|a| a.space_coord_px)
+                .collect::<Vec<_>>()
+        };
+        // the wall runs along z for left pieces, and along x for right pieces,
+        // with the opening between the centre of the cell and `g`
+        assert!(floor_level(Piece::Left).iter().any(|p| p.z < g));
+        assert!(floor_level(Piece::Right).iter().any(|p| p.x < g));
+        let left = floor_level(Piece::DoorwayLeft);
+        let right = floor_level(Piece::DoorwayRight);
+        assert!(!left.is_empty() && !right.is_empty());
+        assert!(left.iter().all(|p| p.z >= g));
+        assert!(right.iter().all(|p| p.x >= g));
+    }
+
+    #[test]
+    fn windows_bracket_the_opening() {
+        let (style, config) = (style(), config());
+        let sill_px = style.window_sill_px;
+        let header_px = sill_px + style.window_height_px;
+        assert!(0. < sill_px && header_px < style.height_px);
+        let g = opening_half_width_px(Piece::WindowLeft, vec2(16., 16.), &style);
+        // the wall of a left piece runs along z, with the opening between the
+        // centre of the cell and `g`
+        let window = quarter(Piece::WindowLeft, &style, &config);
+        let in_opening = window
+            .attributes
+            .iter()
+            .map(|a| a.space_coord_px)
+            .filter(|p| p.z < g)
+            .collect::<Vec<_>>();
+        assert!(in_opening.iter().any(|p| p.y == sill_px));
+        assert!(in_opening.iter().any(|p| p.y == header_px));
+        assert!(in_opening
+            .iter()
+            .all(|p| p.y <= sill_px || p.y >= header_px));
+    }
+
+    #[test]
+    fn diagonal_tops_are_rotated_45_degrees() {
+        let (style, config) = (style(), config());
+        let angle =
+            |a: Vector3<f32>, b: Vector3<f32>| (b.z - a.z).atan2(b.x - a.x).to_degrees();
+        let straight = make_top(
+            Piece::Left,
+            vec2(16., 16.),
+            style.height_px,
+            0.,
+            &style,
+            &config,
+        );
+        let p = |i: usize| straight.attributes[i].space_coord_px;
+        assert_eq!(angle(p(0), p(1)).abs() % 90., 0.);
+        assert_eq!(angle(p(0), p(3)).abs() % 90., 0.);
+        let (tops, _) = make_diagonal_tops_and_faces(
+            Coord::new(0, 0),
+            Diagonal::NorthWestToSouthEast,
+            (false, false),
+            &style,
+            &config,
+        );
+        for quad in tops.attributes.chunks(4) {
+            let p = |i: usize| quad[i].space_coord_px;
+            assert!((angle(p(0), p(3)) - 45.).abs() < 1e-4);
+            assert!((p(1) - p(0)).dot(p(3) - p(0)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn cell_centre_of_non_square_cells() {
+        let config = ConfigBuilder::new().cell_size_px(vec2(16., 32.)).build();
+        let centre =
+            move_to_cell_centre(Coord::new(2, 3), &config) * vec4(0., 0., 0., 1.);
+        assert_eq!(centre, vec4(40., 0., 112., 1.));
+        assert_eq!(
+            quarter_size_px(OrdinalDirection::SouthEast, &config),
+            vec2(8., 16.)
+        );
+    }
+
+    #[test]
+    fn corner_segments_add_vertices_to_outer_corners() {
+        let style = style();
+        let square = quarter(Piece::Outer, &style, &config());
+        let mut config = config();
+        config.corner_segments = 4;
+        let rounded = quarter(Piece::Outer, &style, &config);
+        assert!(rounded.attributes.len() > square.attributes.len());
+    }
+
+    #[test]
+    fn bevels_shrink_the_top() {
+        let (style, config) = (style(), config());
+        let top = |bevel_px| {
+            make_top(
+                Piece::Outer,
+                vec2(16., 16.),
+                style.height_px,
+                bevel_px,
+                &style,
+                &config,
+            )
+        };
+        let extent = |top: RelativeBuffers| {
+            let max = |f: fn(&Attribute) -> f32| {
+                top.attributes.iter().map(f).fold(0., f32::max)
+            };
+            vec2(max(|a| a.space_coord_px.x), max(|a| a.space_coord_px.z))
+        };
+        assert_eq!(extent(top(2.)), extent(top(0.)) - vec2(2., 2.));
+        // the bevel joins the top to the faces
+        let bevelled_style = StyleBuilder::new().top_bevel_px(2.).build();
+        assert!(
+            quarter(Piece::Outer, &bevelled_style, &config)
+                .attributes
+                .len()
+                > quarter(Piece::Outer, &style, &config).attributes.len()
+        );
+    }
+
+    #[test]
+    fn top_tex_quarter_turn_swaps_axes() {
+        let config = config();
+        let top = TopAttribute::new(vec2(32., 0.), vec2(3., 5.));
+        let offset = |quarter_turns| {
+            let style = StyleBuilder::new()
+                .top_tex_quarter_turns(quarter_turns)
+                .build();
+            top.tex_offset_px(&style, &config)
+        };
+        assert_eq!(offset(0), vec2(32. + 3., 5.));
+        // the piece offset is unaffected, and the offset within the piece is
+        // rotated clockwise about the centre of the 16 pixel piece
+        assert_eq!(offset(1), vec2(32. + 16. - 5., 3.));
+        assert_eq!(offset(4), offset(0));
+    }
+
+    #[test]
+    fn baseboards_add_geometry_near_the_floor() {
+        let config = config();
+        let faces = |style: &Style| {
+            make_faces(
+                Piece::Inner,
+                OrdinalDirection::SouthEast,
+                vec2(16., 16.),
+                PieceHeights {
+                    bottom_px: 0.,
+                    top_px: style.height_px,
+                    bevel_px: 0.,
+                },
+                style,
+                &config,
+            )
+        };
+        let plain = faces(&style());
+        let with_baseboard = faces(&StyleBuilder::new().baseboard_px(4.).build());
+        assert!(with_baseboard.attributes.len() > plain.attributes.len());
+        // the baseboard is added after the faces
+        let (faces, baseboard) =
+            with_baseboard.attributes.split_at(plain.attributes.len());
+        assert_eq!(faces, &plain.attributes[..]);
+        assert!(baseboard.iter().all(|a| a.space_coord_px.y <= 4.));
+        assert!(baseboard.iter().any(|a| a.space_coord_px.y == 0.));
+    }
+
+    #[test]
+    fn closed_and_full_width_doorways() {
+        let config = config();
+        // the area of the faces along the wall, which face away from it
+        let wall_area = |piece: Piece, door_width_fraction: f32| {
+            let style = StyleBuilder::new()
+                .door_width_fraction(door_width_fraction)
+                .build();
+            let wall_normal = match piece {
+                Piece::DoorwayLeft => vec3(1., 0., 0.),
+                _ => vec3(0., 0., 1.),
+            };
+            let faces = make_faces(
+                piece,
+                OrdinalDirection::SouthEast,
+                vec2(16., 16.),
+                PieceHeights {
+                    bottom_px: 0.,
+                    top_px: style.height_px,
+                    bevel_px: 0.,
+                },
+                &style,
+                &config,
+            );
+            assert!(faces
+                .attributes
+                .iter()
+                .all(|a| a.normal.magnitude().is_finite()));
+            faces
+                .triangles()
+                .filter(|t| t[0].normal.dot(wall_normal) > 0.99)
+                .map(|t| {
+                    let (a, b, c) = (
+                        t[0].space_coord_px,
+                        t[1].space_coord_px,
+                        t[2].space_coord_px,
+                    );
+                    (b - a).cross(c - a).magnitude() / 2.
+                })
+                .sum::<f32>()
+        };
+        for &piece in [Piece::DoorwayLeft, Piece::DoorwayRight].iter() {
+            // a closed doorway is solid wall, and a doorway as wide as the
+            // quarter only has wall above the door
+            assert_eq!(wall_area(piece, 0.), 16. * 32.);
+            assert_eq!(wall_area(piece, 1.), 16. * (32. - 24.));
+        }
+    }
+
+    #[test]
+    fn face_texture_tiles_vertically() {
+        let style = StyleBuilder::new()
+            .height_px(32.)
+            .face_tex_tile_height_px(Some(16.))
+            .build();
+        let faces = make_faces(
+            Piece::Left,
+            OrdinalDirection::SouthEast,
+            vec2(16., 16.),
+            PieceHeights {
+                bottom_px: 0.,
+                top_px: style.height_px,
+                bevel_px: 0.,
+            },
+            &style,
+            &config(),
+        );
+        let mut rows = faces
+            .attributes
+            .iter()
+            .map(|a| (a.space_coord_px.y as i32, a.tex_coord_px.y as i32))
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows.dedup();
+        // each row spans the 16 pixels of texture below the top of the face
+        // texture, and the rows meet half way up the wall
+        let top = style.face_tex_top_left_px.y as i32;
+        assert_eq!(
+            rows,
+            vec![(0, top + 16), (16, top), (16, top + 16), (32, top)]
+        );
+    }
+
+    #[test]
+    fn stairs_treads_rise_in_direction() {
+        let style = style();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .stair_step_count(4)
+            .build();
+        for direction in CardinalDirections {
+            let (treads, _) = make_stairs_treads_and_faces(
+                Coord::new(0, 0),
+                direction,
+                &style,
+                &config,
+            );
+            assert_eq!(treads.attributes.len(), 4 * 4);
+            let d = direction.coord();
+            let mut previous = None;
+            for (i, tread) in treads.attributes.chunks(4).enumerate() {
+                let y = tread[0].space_coord_px.y;
+                assert!(tread.iter().all(|v| v.space_coord_px.y == y));
+                assert_eq!(y, (i + 1) as f32 * style.height_px / 4.);
+                // distance of the centre of the tread along the direction
+                let along = tread
+                    .iter()
+                    .map(|v| {
+                        v.space_coord_px.x * d.x as f32 + v.space_coord_px.z * d.y as f32
+                    })
+                    .sum::<f32>();
+                if let Some(previous) = previous {
+                    assert!(along > previous);
+                }
+                previous = Some(along);
+            }
+        }
+    }
+
+    #[test]
+    fn ramp_rises_from_floor_to_height() {
+        let (style, config) = (style(), config());
+        for direction in CardinalDirections {
+            let (slope, _) =
+                make_ramp_slope_and_faces(Coord::new(0, 0), direction, &style, &config);
+            let d = direction.coord();
+            // distance along the direction from the centre of the cell
+            let along = |v: &Attribute| {
+                (v.space_coord_px.x - 16.) * d.x as f32
+                    + (v.space_coord_px.z - 16.) * d.y as f32
+            };
+            for v in slope.attributes.iter() {
+                if along(v) < 0. {
+                    assert_eq!(v.space_coord_px.y, 0.);
+                } else {
+                    assert_eq!(v.space_coord_px.y, style.height_px);
+                }
+                assert!(v.normal.y > 0.);
+                assert!(v.normal.x * (d.x as f32) + v.normal.z * (d.y as f32) < 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_and_tops_use_their_layers() {
+        let style = StyleBuilder::new()
+            .face_tex_layer(1)
+            .top_tex_layer(2)
+            .build();
+        let config = config();
+        for &piece in [Piece::Inner, Piece::Outer, Piece::Left, Piece::Right].iter() {
+            let buffers = quarter(piece, &style, &config);
+            for a in buffers.attributes.iter() {
+                if a.normal.y > 0.5 {
+                    assert_eq!(a.tex_layer, 2, "{:?}", piece);
+                } else {
+                    assert_eq!(a.normal.y, 0.);
+                    assert_eq!(a.tex_layer, 1, "{:?}", piece);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn subdivisions_add_vertices_to_rounded_corners() {
+        let style = style();
+        let vertex_count = |subdivisions| {
+            let config = ConfigBuilder::new()
+                .cell_size_px(vec2(32., 32.))
+                .tex_top_piece_size(16.)
+                .corner_segments(2)
+                .subdivisions(subdivisions)
+                .build();
+            quarter(Piece::Outer, &style, &config).attributes.len()
+        };
+        assert!(vertex_count(2) > vertex_count(1));
+        assert!(vertex_count(4) > vertex_count(2));
+        // subdivisions have no effect on sharp corners
+        let sharp = |subdivisions| {
+            let config = ConfigBuilder::new().subdivisions(subdivisions).build();
+            quarter(Piece::Outer, &style, &config)
+        };
+        assert_eq!(sharp(4), sharp(1));
+    }
+}
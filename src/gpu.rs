@@ -0,0 +1,116 @@
+#[cfg(feature = "wgpu")]
+use buffers::Attribute;
+use buffers::RelativeBuffers;
+#[cfg(feature = "wgpu")]
+use std::mem;
+
+// Attribute is repr(C) and made up entirely of 4 byte fields, so it has no
+// padding and any bit pattern is valid.
+#[cfg(feature = "wgpu")]
+unsafe impl ::bytemuck::Zeroable for Attribute {}
+#[cfg(feature = "wgpu")]
+unsafe impl ::bytemuck::Pod for Attribute {}
+
+// locations and formats of the fields of Attribute, in the order they are
+// declared
+#[cfg(feature = "wgpu")]
+const VERTEX_ATTRIBUTES: [::wgpu::VertexAttribute; 6] = ::wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x2,
+    2 => Float32x3,
+    3 => Uint32,
+    4 => Uint32,
+    5 => Float32,
+];
+
+/// Returns the layout of a vertex buffer containing `Attribute`s, with the
+/// position, texture coordinates, normal, material, texture layer and ambient
+/// occlusion at shader locations 0 to 5 respectively.
+#[cfg(feature = "wgpu")]
+pub fn vertex_buffer_layout() -> ::wgpu::VertexBufferLayout<'static> {
+    ::wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Attribute>() as ::wgpu::BufferAddress,
+        step_mode: ::wgpu::VertexStepMode::Vertex,
+        attributes: &VERTEX_ATTRIBUTES,
+    }
+}
+
+/// An index which doesn't fit in the requested index type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexOverflow {
+    pub index: u32,
+}
+
+/// The positions, texture coordinates and normals of vertices, as separate
+/// arrays (see `RelativeBuffers::split_attributes`)
+pub type SplitAttributes = (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<[f32; 3]>);
+
+impl RelativeBuffers {
+    /// Returns the indices as 16 bit integers, or the first index which is
+    /// too large to be represented as one.
+    pub fn indices_u16(&self) -> Result<Vec<u16>, IndexOverflow> {
+        self.indices
+            .iter()
+            .map(|&index| {
+                if index > u16::MAX as u32 {
+                    Err(IndexOverflow { index })
+                } else {
+                    Ok(index as u16)
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the positions, texture coordinates and normals of the vertices
+    /// as separate arrays, in the order referred to by the indices.
+    pub fn split_attributes(&self) -> SplitAttributes {
+        let positions = self
+            .attributes
+            .iter()
+            .map(|a| a.space_coord_px.into())
+            .collect();
+        let tex_coords = self
+            .attributes
+            .iter()
+            .map(|a| a.tex_coord_px.into())
+            .collect();
+        let normals = self.attributes.iter().map(|a| a.normal.into()).collect();
+        (positions, tex_coords, normals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cgmath::{vec2, vec3};
+
+    #[test]
+    fn large_indices_overflow_u16() {
+        let buffers = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: vec![0, 1, 70000],
+        };
+        assert_eq!(buffers.indices_u16(), Err(IndexOverflow { index: 70000 }));
+    }
+
+    #[test]
+    fn split_attributes_match_vertices() {
+        let vertex = |x: f32| Attribute {
+            space_coord_px: vec3(x, 2., 3.),
+            tex_coord_px: vec2(x, 5.),
+            normal: vec3(0., 0., 1.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        };
+        let buffers = RelativeBuffers {
+            attributes: vec![vertex(1.), vertex(4.)],
+            indices: vec![0, 1, 0],
+        };
+        let (positions, tex_coords, normals) = buffers.split_attributes();
+        assert_eq!(positions, vec![[1., 2., 3.], [4., 2., 3.]]);
+        assert_eq!(tex_coords, vec![[1., 5.], [4., 5.]]);
+        assert_eq!(normals, vec![[0., 0., 1.], [0., 0., 1.]]);
+    }
+}
@@ -0,0 +1,136 @@
+use cell::CellType;
+use cgmath::{vec2, Vector2};
+use direction::{CardinalDirection, CardinalDirections};
+use grid_2d::{Coord, Grid};
+use style::Config;
+
+/// A wall cell where a run of wall ends, turns or meets another run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallNode {
+    pub coord: Coord,
+    /// Centre of the cell on the horizontal plane, as (x, z)
+    pub position: Vector2<f32>,
+}
+
+/// A straight run of wall between two nodes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallEdge {
+    /// Indices into `WallGraph::nodes` of the north or west end, and the
+    /// south or east end, of the run
+    pub nodes: (usize, usize),
+    /// Distance between the centres of the cells at either end
+    pub length: f32,
+}
+
+/// The centrelines of the walls of a grid (see `wall_graph`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct WallGraph {
+    pub nodes: Vec<WallNode>,
+    pub edges: Vec<WallEdge>,
+}
+
+fn is_wall(grid: &Grid<CellType>, coord: Coord) -> bool {
+    grid.get(coord)
+        .is_some_and(|cell_type| cell_type.is_solid())
+}
+
+/// Returns the graph of the centrelines of the solid cells of `grid`, with a
+/// node at each cell which isn't in the middle of a straight run of wall,
+/// and an edge along each straight run between nodes. Positions and lengths
+/// are in the units of the generated geometry. Only cells within the grid
+/// are considered, so walls don't continue into the border or wrap around.
+/// Diagonal walls aren't included. Nodes are listed in row-major order.
+pub fn wall_graph(grid: &Grid<CellType>, config: &Config) -> WallGraph {
+    let cell_size = config.cell_size_px * config.units_per_px;
+    let is_node = |coord: Coord| {
+        let neighbours = CardinalDirections
+            .into_iter()
+            .filter(|direction| is_wall(grid, coord + direction.coord()))
+            .collect::<Vec<_>>();
+        match neighbours.as_slice() {
+            &[a, b] => a.opposite() != b,
+            _ => true,
+        }
+    };
+    let mut node_indices = Grid::new_from_fn(grid.size(), |_| None);
+    let mut nodes = Vec::new();
+    for (coord, _) in grid.enumerate() {
+        if is_wall(grid, coord) && is_node(coord) {
+            *node_indices.get_mut(coord).unwrap() = Some(nodes.len());
+            nodes.push(WallNode {
+                coord,
+                position: vec2(
+                    (coord.x as f32 + 0.5) * cell_size.x,
+                    (coord.y as f32 + 0.5) * cell_size.y,
+                ),
+            });
+        }
+    }
+    // every run is followed from its north or west end, so each is only
+    // found once
+    let mut edges = Vec::new();
+    for (start, node) in nodes.iter().enumerate() {
+        for &direction in [CardinalDirection::East, CardinalDirection::South].iter() {
+            let mut coord = node.coord + direction.coord();
+            let mut steps = 1;
+            while is_wall(grid, coord) {
+                if let Some(&Some(end)) = node_indices.get(coord) {
+                    let step_length = match direction {
+                        CardinalDirection::East | CardinalDirection::West => cell_size.x,
+                        CardinalDirection::North | CardinalDirection::South => {
+                            cell_size.y
+                        }
+                    };
+                    edges.push(WallEdge {
+                        nodes: (start, end),
+                        length: steps as f32 * step_length,
+                    });
+                    break;
+                }
+                coord += direction.coord();
+                steps += 1;
+            }
+        }
+    }
+    WallGraph { nodes, edges }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use style::ConfigBuilder;
+    use terrain::parse_terrain;
+
+    #[test]
+    fn l_shaped_wall_has_two_edges_at_a_corner() {
+        let grid = parse_terrain("#..\n#..\n###\n").unwrap();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .units_per_px(0.5)
+            .build();
+        let graph = wall_graph(&grid, &config);
+        let coords = graph
+            .nodes
+            .iter()
+            .map(|node| node.coord)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            coords,
+            vec![Coord::new(0, 0), Coord::new(0, 2), Coord::new(2, 2)]
+        );
+        assert_eq!(graph.nodes[1].position, vec2(8., 40.));
+        assert_eq!(
+            graph.edges,
+            vec![
+                WallEdge {
+                    nodes: (0, 1),
+                    length: 32.,
+                },
+                WallEdge {
+                    nodes: (1, 2),
+                    length: 32.,
+                },
+            ]
+        );
+    }
+}
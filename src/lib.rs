@@ -0,0 +1,83 @@
+extern crate cgmath;
+extern crate direction;
+extern crate grid_2d;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "wgpu")]
+extern crate bytemuck;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "tmx")]
+extern crate roxmltree;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "wgpu")]
+extern crate wgpu;
+
+mod analysis;
+mod ao;
+mod atlas;
+mod buffers;
+mod camera;
+mod cell;
+mod cleanup;
+mod collision;
+mod edge;
+mod export;
+mod generate;
+pub mod geometry;
+mod gpu;
+mod graph;
+mod merge;
+mod normals;
+mod placement;
+mod remesh;
+mod rooms;
+mod silhouette;
+mod split;
+mod strip;
+mod style;
+mod terrain;
+#[cfg(feature = "tmx")]
+mod tmx;
+mod uv;
+mod vertex_cache;
+mod wireframe;
+
+pub use atlas::{generate_mesh_with_atlas, Atlas, AtlasError, AtlasRect, TextureNames};
+pub use buffers::{Attribute, RelativeBuffers};
+pub use camera::isometric_view_proj;
+pub use cell::{
+    CellDetails, CellType, DefaultPieceChooser, Diagonal, Junction, Piece, PieceCache,
+    PieceChooser, PieceError, Quarter, Step,
+};
+pub use collision::{
+    aabb_mesh, wall_cell_aabbs, wall_cell_aabbs_with_heights, wall_collision_mesh,
+};
+pub use edge::{generate_edge_walls, EdgeWalls};
+pub use generate::{
+    generate_chunks, generate_composite, generate_instanced, generate_levels,
+    generate_lod, generate_mesh, generate_mesh_split, generate_mesh_with_chooser,
+    generate_mesh_with_heights, generate_picking_mesh, Chunk, InstanceData, MeshSplit,
+    PickingMesh,
+};
+#[cfg(feature = "wgpu")]
+pub use gpu::vertex_buffer_layout;
+pub use gpu::{IndexOverflow, SplitAttributes};
+pub use graph::{wall_graph, WallEdge, WallGraph, WallNode};
+pub use normals::NormalMode;
+pub use remesh::{
+    diff_geometry, recompute_cell_details, remesh_region, GeometryDelta, WallMesh,
+};
+pub use rooms::room_ids;
+pub use split::DrawRange;
+pub use style::{Ceilings, Config, ConfigBuilder, Style, StyleBuilder};
+pub use terrain::{
+    decode_rle, decode_rle_with_legend, load_terrain, load_terrain_with_legend,
+    parse_terrain, parse_terrain_with_legend, Legend, TerrainError,
+};
+#[cfg(feature = "tmx")]
+pub use tmx::{load_tmx, parse_tmx, TileLegend, TmxError};
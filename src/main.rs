@@ -1,20 +1,26 @@
 extern crate cgmath;
-extern crate direction;
-extern crate grid_2d;
 #[macro_use]
 extern crate gfx;
 extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
 extern crate glutin;
 extern crate image;
+extern crate walls_experiment;
 
-use cgmath::{vec2, vec3, InnerSpace, Matrix4, Vector2, Vector3};
-use direction::{CardinalDirection, OrdinalDirection, OrdinalDirections};
+use cgmath::{vec2, vec3, InnerSpace, Matrix4, Vector3};
 use gfx::state::{CullFace, FrontFace, Rasterizer};
 use gfx::traits::FactoryExt;
 use gfx::{texture, Device, Factory, Primitive};
 use glutin::GlContext;
-use grid_2d::{Coord, Grid, Size};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use walls_experiment::{
+    generate_mesh, load_terrain, parse_terrain, ConfigBuilder, RelativeBuffers,
+    StyleBuilder,
+};
 
 type ColourFormat = gfx::format::Srgba8;
 type DepthFormat = gfx::format::DepthStencil;
@@ -42,436 +48,195 @@ gfx_pipeline!(pipe {
     out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
 });
 
-#[derive(Debug, Clone, Copy)]
-enum CellType {
-    Wall,
-    Floor,
-}
-
-// Inner:
-// #.
-// ##
-//
-// Outer:
-// ..
-// #.
-//
-// Left:
-// ..
-// ##
-//
-// Right:
-// #.
-// #.
-
-struct Config {
-    cell_size_px: f32,
-    tex_top_piece_size: f32,
+struct Camera {
+    position: Vector3<f32>,
+    aspect_ratio: f32,
 }
 
-struct Style {
-    width_px: f32,
-    height_px: f32,
-    face_tex_top_left_px: Vector2<f32>,
-    top_tex_top_left_px: Vector2<f32>,
-}
+impl Camera {
+    fn transform(&self) -> Matrix4<f32> {
+        let camera_position = self.position;
+        let camera_orientation = vec3(0., -1., 0.);
+        let camera_translate = Matrix4::from_translation(-camera_position);
+        let camera_target_orientation = vec3(0., 0., -1.);
+        let camera_rotate_axis = camera_orientation.cross(camera_target_orientation);
+        let camera_rotate_angle = camera_orientation.angle(camera_target_orientation);
+        let camera_rotate =
+            Matrix4::from_axis_angle(camera_rotate_axis, camera_rotate_angle);
+        let camera_perspective = cgmath::perspective(
+            cgmath::Rad(::std::f32::consts::PI / 2.),
+            self.aspect_ratio,
+            10.,
+            300.,
+        );
 
-struct BaseAttribute {
-    face_tex_offset_px_x: f32,
-    space_coord_px: Vector2<f32>,
+        camera_perspective * camera_rotate * camera_translate
+    }
 }
 
-struct TopAttribute {
-    tex_offset_px: Vector2<f32>,
-    space_coord_px: Vector2<f32>,
+const USAGE: &str = "\
+Usage: walls-experiment [OPTIONS] [INPUT]
+
+Options:
+    --input <path>          Terrain file to load (default: built-in terrain)
+    --output <path>         Write the mesh to a file instead of displaying it
+    --format <obj|gltf|ply> Format of the output file (default: from the
+                            extension of the output path, or obj)
+    --wall-height <px>      Height of walls
+    --cell-size <px>        Width and depth of each cell (default: 32)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Obj,
+    Gltf,
+    Ply,
 }
 
-impl TopAttribute {
-    fn new(piece_tex_offset_px: Vector2<f32>, space_coord_px: Vector2<f32>) -> Self {
-        let tex_offset_px =
-            piece_tex_offset_px + vec2(space_coord_px.x, space_coord_px.y);
-        Self {
-            tex_offset_px,
-            space_coord_px,
+impl Format {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "obj" => Some(Format::Obj),
+            "gltf" => Some(Format::Gltf),
+            "ply" => Some(Format::Ply),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Attribute {
-    space_coord_px: Vector3<f32>,
-    tex_coord_px: Vector2<f32>,
+#[derive(Debug, Default)]
+struct Args {
+    input: Option<String>,
+    output: Option<String>,
+    format: Option<Format>,
+    wall_height_px: Option<f32>,
+    cell_size_px: Option<f32>,
 }
 
-#[derive(Debug)]
-struct RelativeBuffers {
-    attributes: Vec<Attribute>,
-    indices: Vec<u32>,
-}
-
-impl RelativeBuffers {
-    fn concat(&self, b: &Self) -> Self {
-        let attributes = self
-            .attributes
-            .iter()
-            .chain(b.attributes.iter())
-            .cloned()
-            .collect::<Vec<_>>();
-        let indices = self
-            .indices
-            .iter()
-            .cloned()
-            .chain(b.indices.iter().map(|i| i + self.attributes.len() as u32))
-            .collect::<Vec<_>>();
-        Self {
-            attributes,
-            indices,
-        }
-    }
-    fn concat_all<I>(i: I) -> Self
-    where
-        I: IntoIterator<Item = Self>,
-    {
-        let mut attributes = Vec::new();
-        let mut indices = Vec::new();
-
-        for b in i {
-            indices.extend(b.indices.iter().map(|&i| i + attributes.len() as u32));
-            attributes.extend_from_slice(&b.attributes);
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} requires a value", arg))
+            };
+            match arg.as_str() {
+                "--input" => parsed.input = Some(value()?),
+                "--output" => parsed.output = Some(value()?),
+                "--format" => {
+                    let format = value()?;
+                    parsed.format = Some(Format::parse(&format).ok_or_else(|| {
+                        format!(
+                            "unknown format {:?} (expected one of obj, gltf, ply)",
+                            format
+                        )
+                    })?);
+                }
+                "--wall-height" => {
+                    parsed.wall_height_px = Some(parse_px(&arg, &value()?)?)
+                }
+                "--cell-size" => parsed.cell_size_px = Some(parse_px(&arg, &value()?)?),
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                other if other.starts_with('-') => {
+                    return Err(format!("unknown option {}", other))
+                }
+                // a bare argument is the input, as before options existed
+                _ if parsed.input.is_none() => parsed.input = Some(arg.clone()),
+                other => return Err(format!("unexpected argument {}", other)),
+            }
         }
-
-        Self {
-            attributes,
-            indices,
+        if parsed.format.is_some() && parsed.output.is_none() {
+            return Err("--format requires --output".to_string());
         }
-    }
-    fn transform(self, m: Matrix4<f32>) -> Self {
-        let Self {
-            mut attributes,
-            indices,
-        } = self;
-        attributes.iter_mut().for_each(|a| {
-            a.space_coord_px = (m * a.space_coord_px.extend(1.)).truncate();
-        });
-        Self {
-            attributes,
-            indices,
+        if parsed.format.is_none() {
+            parsed.format = parsed.output.as_ref().and_then(|path| {
+                Path::new(path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .and_then(Format::parse)
+            });
         }
+        Ok(parsed)
     }
 }
 
-const BASE_TOP_ALTERNATING_INDICES_1: &[u32] = &[0, 1, 2, 1, 3, 2];
-const BASE_TOP_ALTERNATING_INDICES_2: &[u32] = &[0, 1, 2, 1, 3, 2, 2, 3, 4, 3, 5, 4];
-
-fn make_edge_base(
-    piece: Piece,
-    style: &Style,
-    config: &Config,
-) -> (Vec<BaseAttribute>, &'static [u32]) {
-    let s = config.cell_size_px / 2.;
-    let w = style.width_px;
-    match piece {
-        Piece::Inner => (
-            vec![
-                BaseAttribute {
-                    face_tex_offset_px_x: 2. * (s - w),
-                    space_coord_px: vec2(w, s),
-                },
-                BaseAttribute {
-                    // XXX this will produce artifacts where an inner or outer
-                    // edge meets another piece of wall unless s == w * 2
-                    face_tex_offset_px_x: s - w,
-                    space_coord_px: vec2(w, w),
-                },
-                BaseAttribute {
-                    face_tex_offset_px_x: 0.,
-                    space_coord_px: vec2(s, w),
-                },
-            ],
-            BASE_TOP_ALTERNATING_INDICES_2,
-        ),
-        Piece::Outer => (
-            vec![
-                BaseAttribute {
-                    face_tex_offset_px_x: 2. * w,
-                    space_coord_px: vec2(0., w),
-                },
-                BaseAttribute {
-                    face_tex_offset_px_x: w,
-                    space_coord_px: vec2(w, w),
-                },
-                BaseAttribute {
-                    face_tex_offset_px_x: 0.,
-                    space_coord_px: vec2(w, 0.),
-                },
-            ],
-            BASE_TOP_ALTERNATING_INDICES_2,
-        ),
-        Piece::Left => (
-            vec![
-                BaseAttribute {
-                    face_tex_offset_px_x: s,
-                    space_coord_px: vec2(w, s),
-                },
-                BaseAttribute {
-                    face_tex_offset_px_x: 0.,
-                    space_coord_px: vec2(w, 0.),
-                },
-            ],
-            BASE_TOP_ALTERNATING_INDICES_1,
-        ),
-        Piece::Right => (
-            vec![
-                BaseAttribute {
-                    face_tex_offset_px_x: s,
-                    space_coord_px: vec2(0., w),
-                },
-                BaseAttribute {
-                    face_tex_offset_px_x: 0.,
-                    space_coord_px: vec2(s, w),
-                },
-            ],
-            BASE_TOP_ALTERNATING_INDICES_1,
-        ),
-    }
-}
-
-fn make_faces(piece: Piece, style: &Style, config: &Config) -> RelativeBuffers {
-    let (edge_base, indices) = make_edge_base(piece, style, config);
-    let base = edge_base.iter().map(|a| {
-        let tex_coord_px =
-            vec2(a.face_tex_offset_px_x, style.height_px) + style.face_tex_top_left_px;
-        let space_coord_px = vec3(a.space_coord_px.x, 0., a.space_coord_px.y);
-        Attribute {
-            tex_coord_px,
-            space_coord_px,
-        }
-    });
-    let top = edge_base.iter().map(|a| {
-        let tex_coord_px = vec2(a.face_tex_offset_px_x, 0.) + style.face_tex_top_left_px;
-        let space_coord_px =
-            vec3(a.space_coord_px.x, style.height_px, a.space_coord_px.y);
-        Attribute {
-            tex_coord_px,
-            space_coord_px,
-        }
-    });
-    let base_top_alternating = base
-        .zip(top)
-        .flat_map(|(base, top)| vec![base, top])
-        .collect::<Vec<_>>();
-
-    RelativeBuffers {
-        attributes: base_top_alternating,
-        indices: indices.iter().cloned().collect(),
+fn parse_px(option: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(px) if px > 0. => Ok(px),
+        _ => Err(format!(
+            "{} must be a positive number, not {:?}",
+            option, value
+        )),
     }
 }
 
-fn make_rect_top(
-    size: Vector2<f32>,
-    piece_tex_offset_px: Vector2<f32>,
-) -> (Vec<TopAttribute>, &'static [u32]) {
-    const INDICES: &[u32] = &[0, 1, 2, 0, 2, 3];
-    let attributes = vec![
-        TopAttribute::new(piece_tex_offset_px, vec2(0., 0.)),
-        TopAttribute::new(piece_tex_offset_px, vec2(size.x, 0.)),
-        TopAttribute::new(piece_tex_offset_px, size),
-        TopAttribute::new(piece_tex_offset_px, vec2(0., size.y)),
-    ];
-    (attributes, INDICES)
-}
-
-fn make_top(piece: Piece, style: &Style, config: &Config) -> RelativeBuffers {
-    let s = config.tex_top_piece_size;
-    let w = style.width_px;
-    let (attributes, indices) = match piece {
-        Piece::Inner => {
-            const INDICES: &[u32] = &[0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5];
-            let piece_tex_offset_px = vec2(0., 0.);
-            (
-                vec![
-                    TopAttribute::new(piece_tex_offset_px, vec2(w, w)),
-                    TopAttribute::new(piece_tex_offset_px, vec2(w, s)),
-                    TopAttribute::new(piece_tex_offset_px, vec2(0., s)),
-                    TopAttribute::new(piece_tex_offset_px, vec2(0., 0.)),
-                    TopAttribute::new(piece_tex_offset_px, vec2(s, 0.)),
-                    TopAttribute::new(piece_tex_offset_px, vec2(s, w)),
-                ],
-                INDICES,
-            )
+/// Writes `geometry` to `path`. glTF output also writes the binary buffer to
+/// a file next to `path` with the extension "bin".
+fn write_geometry(
+    geometry: &RelativeBuffers,
+    path: &str,
+    format: Option<Format>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format.unwrap_or(Format::Obj) {
+        Format::Obj => geometry.write_obj(&mut writer)?,
+        Format::Ply => geometry.write_ply(&mut writer)?,
+        Format::Gltf => {
+            let bin_path = Path::new(path).with_extension("bin");
+            let bin_uri = bin_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("mesh.bin")
+                .to_string();
+            let mut bin_writer = BufWriter::new(File::create(&bin_path)?);
+            geometry.write_gltf(&mut writer, &mut bin_writer, &bin_uri)?;
+            bin_writer.flush()?;
         }
-        Piece::Outer => make_rect_top(vec2(w, w), vec2(2. * s, 0.)),
-        Piece::Left => make_rect_top(vec2(w, s), vec2(0., s)),
-        Piece::Right => make_rect_top(vec2(s, w), vec2(s, 0.)),
-    };
-    let attributes = attributes
-        .iter()
-        .map(|a| {
-            let space_coord_px =
-                vec3(a.space_coord_px.x, style.height_px, a.space_coord_px.y);
-            let tex_coord_px = a.tex_offset_px + style.top_tex_top_left_px;
-            Attribute {
-                space_coord_px,
-                tex_coord_px,
-            }
-        })
-        .collect::<Vec<_>>();
-    RelativeBuffers {
-        attributes,
-        indices: indices.iter().cloned().collect(),
     }
+    writer.flush()
 }
 
-fn make_geometry(piece: Piece, style: &Style, config: &Config) -> RelativeBuffers {
-    let top = make_top(piece, style, config);
-    let faces = make_faces(piece, style, config);
-    top.concat(&faces)
-}
-
-fn move_to_cell_centre(coord: Coord, config: &Config) -> Matrix4<f32> {
-    let position = vec2(
-        coord.x as f32 * config.cell_size_px,
-        coord.y as f32 * config.cell_size_px,
-    ) + vec2(config.cell_size_px / 2., config.cell_size_px / 2.);
-    Matrix4::from_translation(vec3(position.x, 0., position.y))
-}
+fn main() {
+    let args = Args::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
 
-fn rotate_to_direction(direction: OrdinalDirection) -> Matrix4<f32> {
-    let angle = match direction {
-        OrdinalDirection::NorthEast => ::std::f32::consts::PI / 2.,
-        OrdinalDirection::SouthEast => 0.,
-        OrdinalDirection::SouthWest => -::std::f32::consts::PI / 2.,
-        OrdinalDirection::NorthWest => ::std::f32::consts::PI,
+    let type_grid = match args.input {
+        Some(ref path) => load_terrain(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }),
+        None => parse_terrain(include_str!("terrain_strings.txt"))
+            .expect("Failed to parse built-in terrain"),
     };
-    Matrix4::from_angle_y(cgmath::Rad(angle))
-}
-
-#[derive(Debug, Clone, Copy)]
-enum Piece {
-    Inner,
-    Outer,
-    Left,
-    Right,
-}
-
-impl Piece {
-    fn choose(
-        neigh_a: (CellType, CardinalDirection),
-        neigh_b: (CellType, CardinalDirection),
-    ) -> Self {
-        let (wall_direction, floor_direction) = match (neigh_a.0, neigh_b.0) {
-            (CellType::Floor, CellType::Floor) => return Piece::Outer,
-            (CellType::Wall, CellType::Wall) => return Piece::Inner,
-            (CellType::Wall, CellType::Floor) => (neigh_a.1, neigh_b.1),
-            (CellType::Floor, CellType::Wall) => (neigh_b.1, neigh_a.1),
-        };
-
-        if wall_direction.right90() == floor_direction {
-            return Piece::Right;
-        }
-
-        if wall_direction.left90() == floor_direction {
-            return Piece::Left;
-        }
 
-        unreachable!()
+    let mut style = StyleBuilder::new();
+    if let Some(height_px) = args.wall_height_px {
+        style = style.height_px(height_px);
     }
-}
+    let style = style.build();
 
-#[derive(Debug, Clone, Copy)]
-struct Quarter {
-    piece: Piece,
-}
+    // the example atlas is drawn for cells of 32 pixels
+    let cell_size_px = args.cell_size_px.unwrap_or(32.);
+    let config = ConfigBuilder::new()
+        .cell_size_px(vec2(cell_size_px, cell_size_px))
+        .tex_top_piece_size(16.)
+        .build();
 
-impl Quarter {
-    fn from_grid(
-        grid: &Grid<CellType>,
-        coord: Coord,
-        direction: OrdinalDirection,
-    ) -> Self {
-        let (card_a, card_b) = direction.to_cardinals();
-        let cell_type_a = grid
-            .get(coord + card_a.coord())
-            .cloned()
-            .unwrap_or(CellType::Floor);
-        let cell_type_b = grid
-            .get(coord + card_b.coord())
-            .cloned()
-            .unwrap_or(CellType::Floor);
-        let piece = Piece::choose((cell_type_a, card_a), (cell_type_b, card_b));
-        Self { piece }
-    }
-}
-
-#[derive(Debug)]
-struct CellDetails {
-    quarters: [Quarter; 4],
-}
+    let geometry = generate_mesh(&type_grid, &style, &config);
 
-impl CellDetails {
-    fn outer() -> Self {
-        let quarter = Quarter {
-            piece: Piece::Outer,
-        };
-        Self {
-            quarters: [quarter, quarter, quarter, quarter],
+    if let Some(ref path) = args.output {
+        if let Err(e) = write_geometry(&geometry, path, args.format) {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
         }
+        return;
     }
-    fn from_grid(grid: &Grid<CellType>, coord: Coord) -> Option<Self> {
-        if let CellType::Floor = grid.get(coord).cloned().unwrap_or(CellType::Floor) {
-            return None;
-        }
-        let mut cell_details = Self::outer();
-        for o in OrdinalDirections {
-            cell_details.quarters[o as usize] = Quarter::from_grid(grid, coord, o);
-        }
-        Some(cell_details)
-    }
-    fn make_geometry(
-        &self,
-        coord: Coord,
-        style: &Style,
-        config: &Config,
-    ) -> Vec<RelativeBuffers> {
-        let translate = move_to_cell_centre(coord, config);
-        OrdinalDirections
-            .into_iter()
-            .zip(self.quarters.iter())
-            .map(|(o, q)| {
-                let rotate = rotate_to_direction(o);
-                make_geometry(q.piece, style, config).transform(translate * rotate)
-            })
-            .collect()
-    }
-}
-
-struct Camera {
-    position: Vector3<f32>,
-    aspect_ratio: f32,
-}
-
-impl Camera {
-    fn transform(&self) -> Matrix4<f32> {
-        let camera_position = self.position;
-        let camera_orientation = vec3(0., -1., 0.);
-        let camera_translate = Matrix4::from_translation(-camera_position);
-        let camera_target_orientation = vec3(0., 0., -1.);
-        let camera_rotate_axis = camera_orientation.cross(camera_target_orientation);
-        let camera_rotate_angle = camera_orientation.angle(camera_target_orientation);
-        let camera_rotate =
-            Matrix4::from_axis_angle(camera_rotate_axis, camera_rotate_angle);
-        let camera_perspective = cgmath::perspective(
-            cgmath::Rad(::std::f32::consts::PI / 2.),
-            self.aspect_ratio,
-            10.,
-            300.,
-        );
-
-        camera_perspective * camera_rotate * camera_translate
-    }
-}
 
-fn main() {
     let (width, height) = (960., 720.);
     let mut events_loop = glutin::EventsLoop::new();
     let builder = glutin::WindowBuilder::new()
@@ -543,47 +308,6 @@ fn main() {
         aspect_ratio: (width / height) as f32,
     };
 
-    let terrain_vecs = include_str!("terrain_strings.txt")
-        .split("\n")
-        .filter(|s| !s.is_empty())
-        .map(|s| s.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>();
-    let width = terrain_vecs[0].len();
-    let height = terrain_vecs.len();
-    assert!(
-        terrain_vecs.iter().all(|v| v.len() == width),
-        "inconsistent width"
-    );
-    let size = Size::new(width as u32, height as u32);
-    let type_grid = Grid::new_from_fn(size, |coord| {
-        match terrain_vecs[coord.y as usize][coord.x as usize] {
-            '.' => CellType::Floor,
-            '#' => CellType::Wall,
-            _ => panic!("unknown char"),
-        }
-    });
-    let detail_grid =
-        Grid::new_from_fn(size, |coord| CellDetails::from_grid(&type_grid, coord));
-
-    let style = Style {
-        width_px: 8.,
-        height_px: 32.,
-        face_tex_top_left_px: vec2(64., 16.),
-        top_tex_top_left_px: vec2(16., 16.),
-    };
-
-    let config = Config {
-        cell_size_px: 32.,
-        tex_top_piece_size: 16.,
-    };
-
-    let geometry_iter = detail_grid
-        .enumerate()
-        .filter_map(|(coord, cell)| cell.as_ref().map(|cell| (coord, cell)))
-        .flat_map(|(coord, cell)| cell.make_geometry(coord, &style, &config));
-
-    let geometry = RelativeBuffers::concat_all(geometry_iter);
-
     let index_buffer = factory.create_index_buffer(&geometry.indices[..]);
     let slice = gfx::Slice {
         start: 0,
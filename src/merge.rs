@@ -0,0 +1,470 @@
+use buffers::{Attribute, RelativeBuffers};
+use cgmath::{vec3, InnerSpace, Vector3};
+use cleanup::{quantize, NORMAL_EPSILON};
+use std::collections::HashMap;
+
+/// A vertical rectangular face made of two triangles, with corners in the
+/// order bottom, top, bottom, top along the edge it is built on.
+struct FaceQuad {
+    corners: [Attribute; 4],
+    // positions of either end of the face along the edge
+    start: f32,
+    end: f32,
+    // rate at which the horizontal texture coordinate decreases along the edge
+    tex_scale: f32,
+}
+
+fn edge_direction(normal: Vector3<f32>) -> Vector3<f32> {
+    // inverse of the way normals are derived from edge directions
+    vec3(normal.z, 0., -normal.x)
+}
+
+/// Returns the face made by the pair of triangles `t[0..3]` and `t[3..6]`, if
+/// they share an edge and form a vertical rectangle in the same way as the
+/// faces of walls.
+fn face_quad(attributes: &[Attribute], t: &[u32], epsilon: f32) -> Option<FaceQuad> {
+    // the faces of walls are made from the triangles [0, 1, 2] and [1, 3, 2]
+    if t[3] != t[1] || t[5] != t[2] {
+        return None;
+    }
+    let corners = [
+        attributes[t[0] as usize],
+        attributes[t[1] as usize],
+        attributes[t[2] as usize],
+        attributes[t[4] as usize],
+    ];
+    let [a, b, c, d] = corners;
+    let same = |x: f32, y: f32| (x - y).abs() <= epsilon;
+    let is_face = a.normal.y.abs() < NORMAL_EPSILON
+        && corners.iter().all(|v| {
+            v.normal == a.normal && v.material == a.material && v.tex_layer == a.tex_layer
+        })
+        && same(a.space_coord_px.x, b.space_coord_px.x)
+        && same(a.space_coord_px.z, b.space_coord_px.z)
+        && same(c.space_coord_px.x, d.space_coord_px.x)
+        && same(c.space_coord_px.z, d.space_coord_px.z)
+        && same(a.space_coord_px.y, c.space_coord_px.y)
+        && same(b.space_coord_px.y, d.space_coord_px.y)
+        && a.tex_coord_px.x == b.tex_coord_px.x
+        && c.tex_coord_px.x == d.tex_coord_px.x
+        && a.tex_coord_px.y == c.tex_coord_px.y
+        && b.tex_coord_px.y == d.tex_coord_px.y;
+    if !is_face {
+        return None;
+    }
+    let direction = edge_direction(a.normal);
+    let start = a.space_coord_px.dot(direction);
+    let end = c.space_coord_px.dot(direction);
+    if end - start <= epsilon {
+        return None;
+    }
+    Some(FaceQuad {
+        corners,
+        start,
+        end,
+        tex_scale: (a.tex_coord_px.x - c.tex_coord_px.x) / (end - start),
+    })
+}
+
+impl RelativeBuffers {
+    /// Replaces the vertical faces of walls which lie end to end in the same
+    /// plane, spanning the same heights, with a single face. This is
+    /// typically the case along straight runs of wall. Faces are found as
+    /// consecutive pairs of triangles sharing an edge, so they needn't be
+    /// aligned to any particular position in the index list. Positions within
+    /// `position_epsilon` of each other are considered equal.
+    ///
+    /// Rather than stretching the texture of one face across the whole run,
+    /// the horizontal texture coordinate continues along the merged face at
+    /// the same density as the original faces. Merged faces therefore sample
+    /// beyond a single repeat of the face texture, so this must only be used
+    /// when the face texture wraps horizontally, rather than occupying a
+    /// region of an atlas (see `Style::face_tex_wraps`). Since the ends of
+    /// each face line up with whole periods of the face texture, the result
+    /// then looks the same as before.
+    pub fn merge_straight_faces(&mut self, position_epsilon: f32) {
+        let mut indices = Vec::new();
+        let mut quads_by_plane = HashMap::new();
+        let mut i = 0;
+        while i < self.indices.len() {
+            let quad = self
+                .indices
+                .get(i..i + 6)
+                .and_then(|t| face_quad(&self.attributes, t, position_epsilon));
+            match quad {
+                Some(quad) => {
+                    let [a, b, _, _] = quad.corners;
+                    let n = a.normal;
+                    let key = (
+                        a.material,
+                        a.tex_layer,
+                        [quantize(n.x, NORMAL_EPSILON), quantize(n.z, NORMAL_EPSILON)],
+                        quantize(a.space_coord_px.dot(n), position_epsilon),
+                        [
+                            quantize(a.space_coord_px.y, position_epsilon),
+                            quantize(b.space_coord_px.y, position_epsilon),
+                        ],
+                        [a.tex_coord_px.y.to_bits(), b.tex_coord_px.y.to_bits()],
+                    );
+                    quads_by_plane
+                        .entry(key)
+                        .or_insert_with(Vec::new)
+                        .push(quad);
+                    i += 6;
+                }
+                None => {
+                    // not the first triangle of a face, so keep it and try
+                    // pairing the next one
+                    indices.extend_from_slice(&self.indices[i..i + 3]);
+                    i += 3;
+                }
+            }
+        }
+        let mut attributes = self.attributes.clone();
+        for (_, mut quads) in quads_by_plane {
+            quads.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+            let mut quads = quads.into_iter().peekable();
+            while let Some(first) = quads.next() {
+                let [_, _, mut c, mut d] = first.corners;
+                let mut end = first.end;
+                loop {
+                    let continues = quads.peek().is_some_and(|next| {
+                        (next.start - end).abs() <= position_epsilon
+                            && (next.tex_scale - first.tex_scale).abs() <= NORMAL_EPSILON
+                    });
+                    if !continues {
+                        break;
+                    }
+                    let next = quads.next().unwrap();
+                    c = next.corners[2];
+                    d = next.corners[3];
+                    end = next.end;
+                }
+                let [mut a, mut b, _, _] = first.corners;
+                let start_tex_x =
+                    c.tex_coord_px.x + (end - first.start) * first.tex_scale;
+                a.tex_coord_px.x = start_tex_x;
+                b.tex_coord_px.x = start_tex_x;
+                let base = attributes.len() as u32;
+                attributes.extend_from_slice(&[a, b, c, d]);
+                indices.extend([0, 1, 2, 1, 3, 2].iter().map(|i| i + base));
+            }
+        }
+        self.attributes = attributes;
+        self.indices = indices;
+        self.remove_unused_attributes();
+    }
+
+    fn remove_unused_attributes(&mut self) {
+        let Self {
+            ref mut attributes,
+            ref mut indices,
+        } = *self;
+        let mut remap = vec![None; attributes.len()];
+        let mut used = Vec::new();
+        for i in indices.iter_mut() {
+            let old = *i as usize;
+            *i = *remap[old].get_or_insert_with(|| {
+                used.push(attributes[old]);
+                used.len() as u32 - 1
+            });
+        }
+        *attributes = used;
+    }
+}
+
+type PointKey = [i64; 3];
+
+fn point_key(a: &Attribute, epsilon: f32) -> PointKey {
+    let p = a.space_coord_px;
+    [
+        quantize(p.x, epsilon),
+        quantize(p.y, epsilon),
+        quantize(p.z, epsilon),
+    ]
+}
+
+// the vertical component of the cross product of the edges either side of b
+fn turn(a: &Attribute, b: &Attribute, c: &Attribute) -> f32 {
+    let ab = b.space_coord_px - a.space_coord_px;
+    let bc = c.space_coord_px - b.space_coord_px;
+    ab.z * bc.x - ab.x * bc.z
+}
+
+/// Removes vertices which lie on the straight line between their neighbours.
+fn remove_collinear(polygon: &mut Vec<Attribute>, epsilon: f32) {
+    let mut i = 0;
+    while i < polygon.len() && polygon.len() > 3 {
+        let n = polygon.len();
+        let prev = &polygon[(i + n - 1) % n];
+        let next = &polygon[(i + 1) % n];
+        let span = (next.space_coord_px - prev.space_coord_px).magnitude();
+        if turn(prev, &polygon[i], next).abs() <= epsilon * span {
+            polygon.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Returns true if the texture coordinates of every vertex of `b` follow the
+/// same linear mapping from position as those of `a`.
+fn tex_coords_continuous(a: &[Attribute], b: &[Attribute], epsilon: f32) -> bool {
+    let (p0, p1, p2) = (&a[0], &a[1], &a[2]);
+    let e1 = p1.space_coord_px - p0.space_coord_px;
+    let e2 = p2.space_coord_px - p0.space_coord_px;
+    let det = e1.x * e2.z - e1.z * e2.x;
+    if det.abs() <= epsilon * epsilon {
+        return false;
+    }
+    let t1 = p1.tex_coord_px - p0.tex_coord_px;
+    let t2 = p2.tex_coord_px - p0.tex_coord_px;
+    b.iter().all(|v| {
+        let d = v.space_coord_px - p0.space_coord_px;
+        // express d in terms of e1 and e2
+        let s = (d.x * e2.z - d.z * e2.x) / det;
+        let t = (e1.x * d.z - e1.z * d.x) / det;
+        let expected = p0.tex_coord_px + t1 * s + t2 * t;
+        (expected - v.tex_coord_px).magnitude() <= epsilon
+    })
+}
+
+/// Joins `a` and `b` along the edge from `a[i]` to `a[i + 1]`, which is
+/// shared with the edge from `b[j + 1]` to `b[j]`. Returns `None` if the
+/// result wouldn't be convex.
+fn join(
+    a: &[Attribute],
+    i: usize,
+    b: &[Attribute],
+    j: usize,
+    epsilon: f32,
+) -> Option<Vec<Attribute>> {
+    let (na, nb) = (a.len(), b.len());
+    let mut joined = (1..na + 1)
+        .map(|k| a[(i + k) % na])
+        .chain((2..nb).map(|k| b[(j + k) % nb]))
+        .collect::<Vec<_>>();
+    remove_collinear(&mut joined, epsilon);
+    let n = joined.len();
+    let turns = (0..n)
+        .map(|k| turn(&joined[k], &joined[(k + 1) % n], &joined[(k + 2) % n]))
+        .collect::<Vec<_>>();
+    let is_convex =
+        turns.iter().all(|&t| t >= -epsilon) || turns.iter().all(|&t| t <= epsilon);
+    if is_convex {
+        Some(joined)
+    } else {
+        None
+    }
+}
+
+impl RelativeBuffers {
+    /// Merges upwards facing triangles which lie in the same horizontal plane
+    /// and share edges into larger convex polygons, which are then split back
+    /// into triangles. Triangles are only merged if their texture
+    /// coordinates, materials and texture layers match up across the shared
+    /// edge. Positions within `tolerance` of each other are considered equal.
+    /// Vertices left in the middle of a straight edge of a merged polygon are
+    /// removed, which may leave T-junctions with neighbouring geometry.
+    pub fn merge_coplanar_tops(&mut self, tolerance: f32) {
+        let mut indices = Vec::new();
+        let mut polygons = Vec::new();
+        for t in self.indices.chunks(3) {
+            let vertices = t
+                .iter()
+                .map(|&i| self.attributes[i as usize])
+                .collect::<Vec<_>>();
+            let y = vertices[0].space_coord_px.y;
+            let is_top = vertices.iter().all(|v| {
+                (v.normal.y - 1.).abs() < NORMAL_EPSILON
+                    && (v.space_coord_px.y - y).abs() <= tolerance
+                    && v.material == vertices[0].material
+                    && v.tex_layer == vertices[0].tex_layer
+            });
+            if is_top && t.len() == 3 {
+                polygons.push(Some(vertices));
+            } else {
+                indices.extend_from_slice(t);
+            }
+        }
+        let mut polygon_by_edge = HashMap::new();
+        for (index, polygon) in polygons.iter().enumerate() {
+            let polygon = polygon.as_ref().unwrap();
+            for k in 0..polygon.len() {
+                let edge = (
+                    point_key(&polygon[k], tolerance),
+                    point_key(&polygon[(k + 1) % polygon.len()], tolerance),
+                );
+                polygon_by_edge.insert(edge, index);
+            }
+        }
+        let mut to_visit = (0..polygons.len()).collect::<Vec<_>>();
+        while let Some(index) = to_visit.pop() {
+            let joined = {
+                let a = match polygons[index] {
+                    Some(ref a) => a,
+                    None => continue,
+                };
+                (0..a.len())
+                    .filter_map(|i| {
+                        let start = point_key(&a[i], tolerance);
+                        let end = point_key(&a[(i + 1) % a.len()], tolerance);
+                        let other = *polygon_by_edge.get(&(end, start))?;
+                        let b = polygons[other].as_ref()?;
+                        if other == index
+                            || b[0].material != a[0].material
+                            || b[0].tex_layer != a[0].tex_layer
+                        {
+                            return None;
+                        }
+                        if !tex_coords_continuous(a, b, tolerance) {
+                            return None;
+                        }
+                        let j =
+                            (0..b.len()).find(|&j| point_key(&b[j], tolerance) == end)?;
+                        join(a, i, b, j, tolerance).map(|joined| (other, joined))
+                    })
+                    .next()
+            };
+            if let Some((other, joined)) = joined {
+                for &p in [index, other].iter() {
+                    let polygon = polygons[p].take().unwrap();
+                    for k in 0..polygon.len() {
+                        let edge = (
+                            point_key(&polygon[k], tolerance),
+                            point_key(&polygon[(k + 1) % polygon.len()], tolerance),
+                        );
+                        if polygon_by_edge.get(&edge) == Some(&p) {
+                            polygon_by_edge.remove(&edge);
+                        }
+                    }
+                }
+                for k in 0..joined.len() {
+                    let edge = (
+                        point_key(&joined[k], tolerance),
+                        point_key(&joined[(k + 1) % joined.len()], tolerance),
+                    );
+                    polygon_by_edge.insert(edge, index);
+                }
+                polygons[index] = Some(joined);
+                to_visit.push(index);
+            }
+        }
+        let mut attributes = self.attributes.clone();
+        for polygon in polygons.into_iter().flatten() {
+            // polygons are convex so can be split into a fan of triangles
+            let base = attributes.len() as u32;
+            for k in 1..polygon.len() as u32 - 1 {
+                indices.extend_from_slice(&[base, base + k, base + k + 1]);
+            }
+            attributes.extend(polygon);
+        }
+        self.attributes = attributes;
+        self.indices = indices;
+        self.remove_unused_attributes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+    use generate::generate_mesh;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    fn strip() -> RelativeBuffers {
+        let grid = parse_terrain("##########\n").unwrap();
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build();
+        generate_mesh(&grid, &style, &config)
+    }
+
+    fn num_triangles(buffers: &RelativeBuffers) -> usize {
+        buffers.indices.len() / 3
+    }
+
+    fn num_face_triangles(buffers: &RelativeBuffers) -> usize {
+        buffers
+            .indices
+            .chunks(3)
+            .filter(|t| buffers.attributes[t[0] as usize].normal.y == 0.)
+            .count()
+    }
+
+    #[test]
+    fn straight_run_merges_to_fewer_triangles() {
+        let unmerged = strip();
+        let mut merged = unmerged.clone();
+        merged.merge_straight_faces(1. / 1024.);
+        // a face on each side and each end of the strip
+        assert_eq!(num_face_triangles(&merged), 8);
+        assert!(num_face_triangles(&unmerged) > 8 * 4);
+        assert_eq!(
+            num_triangles(&merged) - num_face_triangles(&merged),
+            num_triangles(&unmerged) - num_face_triangles(&unmerged)
+        );
+    }
+
+    #[test]
+    fn faces_after_an_odd_triangle_are_merged() {
+        let mut merged = strip();
+        merged.merge_straight_faces(1. / 1024.);
+        let mut shifted = strip();
+        shifted.indices.splice(0..0, vec![0, 0, 0]);
+        shifted.merge_straight_faces(1. / 1024.);
+        assert_eq!(num_triangles(&shifted), num_triangles(&merged) + 1);
+    }
+
+    #[test]
+    fn merging_requires_wrapping_face_textures() {
+        let grid = parse_terrain("##########\n").unwrap();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .merge_straight_faces(true)
+            .build();
+        let unmerged = strip();
+        let style = StyleBuilder::new().build();
+        assert_eq!(generate_mesh(&grid, &style, &config), unmerged);
+        let style = StyleBuilder::new().face_tex_wraps(true).build();
+        let merged = generate_mesh(&grid, &style, &config);
+        assert!(num_triangles(&merged) < num_triangles(&unmerged));
+    }
+
+    #[test]
+    fn adjacent_coplanar_quads_are_merged() {
+        let vertex = |x: f32, z: f32| Attribute {
+            space_coord_px: vec3(x, 4., z),
+            tex_coord_px: vec2(x, z),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        };
+        let quad = |x: f32| {
+            let corners = [(x, 0.), (x, 1.), (x + 1., 1.), (x + 1., 0.)];
+            RelativeBuffers {
+                attributes: corners.iter().map(|&(x, z)| vertex(x, z)).collect(),
+                indices: vec![0, 1, 2, 0, 2, 3],
+            }
+        };
+        let mut buffers = quad(0.).concat_into(quad(1.));
+        buffers.merge_coplanar_tops(1. / 1024.);
+        assert_eq!(num_triangles(&buffers), 2);
+        let mut corners = buffers
+            .indices
+            .iter()
+            .map(|&i| {
+                let p = buffers.attributes[i as usize].space_coord_px;
+                (p.x as i32, p.z as i32)
+            })
+            .collect::<Vec<_>>();
+        corners.sort();
+        corners.dedup();
+        assert_eq!(corners, vec![(0, 0), (0, 1), (2, 0), (2, 1)]);
+    }
+}
@@ -0,0 +1,137 @@
+use buffers::{face_normal, RelativeBuffers};
+use cgmath::{vec3, InnerSpace};
+
+/// How normals are computed by `RelativeBuffers::recompute_normals`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalMode {
+    /// Each triangle has its own vertices, with the normal of the triangle
+    Flat,
+    /// Each vertex has the average of the normals of the triangles which
+    /// share it, weighted by their areas
+    Smooth,
+}
+
+impl RelativeBuffers {
+    /// Replaces the normals of all vertices with ones computed from the
+    /// triangles. In `NormalMode::Flat`, vertices shared between triangles
+    /// are duplicated. In `NormalMode::Smooth`, normals are only averaged
+    /// across triangles which share vertices, so call `weld` beforehand to
+    /// join triangles which meet at separate vertices. Vertices which `weld`
+    /// keeps apart, such as those with different texture coordinates or
+    /// normals, are smoothed separately. Vertices which are only part of
+    /// degenerate triangles keep their normals.
+    pub fn recompute_normals(&mut self, mode: NormalMode) {
+        match mode {
+            NormalMode::Flat => {
+                let mut attributes = Vec::with_capacity(self.indices.len());
+                for t in self.indices.chunks(3) {
+                    let corners = t
+                        .iter()
+                        .map(|&i| self.attributes[i as usize])
+                        .collect::<Vec<_>>();
+                    let normal = face_normal(
+                        corners[0].space_coord_px,
+                        corners[1].space_coord_px,
+                        corners[2].space_coord_px,
+                    );
+                    for mut a in corners {
+                        if normal.magnitude2() > 0. {
+                            a.normal = normal.normalize();
+                        }
+                        attributes.push(a);
+                    }
+                }
+                self.indices = (0..attributes.len() as u32).collect();
+                self.attributes = attributes;
+            }
+            NormalMode::Smooth => {
+                let mut sums = vec![vec3(0., 0., 0.); self.attributes.len()];
+                for t in self.indices.chunks(3) {
+                    // the magnitude of the face normal is proportional to
+                    // the area of the triangle
+                    let normal = face_normal(
+                        self.attributes[t[0] as usize].space_coord_px,
+                        self.attributes[t[1] as usize].space_coord_px,
+                        self.attributes[t[2] as usize].space_coord_px,
+                    );
+                    for &i in t {
+                        sums[i as usize] += normal;
+                    }
+                }
+                for (a, sum) in self.attributes.iter_mut().zip(sums) {
+                    if sum.magnitude2() > 0. {
+                        a.normal = sum.normalize();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cgmath::{vec2, Vector3};
+    use collision::aabb_mesh;
+
+    fn vertex(space_coord_px: Vector3<f32>) -> Attribute {
+        Attribute {
+            space_coord_px,
+            tex_coord_px: vec2(0., 0.),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        }
+    }
+
+    #[test]
+    fn flat_box_has_a_normal_per_side() {
+        let mut buffers = aabb_mesh(vec3(0., 0., 0.), vec3(1., 2., 3.));
+        buffers.recompute_normals(NormalMode::Flat);
+        assert_eq!(buffers.attributes.len(), buffers.indices.len());
+        let mut normals = buffers
+            .attributes
+            .iter()
+            .map(|a| {
+                let n = a.normal;
+                (n.x as i32, n.y as i32, n.z as i32)
+            })
+            .collect::<Vec<_>>();
+        normals.sort();
+        normals.dedup();
+        assert_eq!(normals.len(), 6);
+    }
+
+    #[test]
+    fn smooth_cylinder_has_interpolated_normals() {
+        // the side of an octagonal prism, with vertices shared between sides
+        const SIDES: u32 = 8;
+        let mut buffers = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: Vec::new(),
+        };
+        for i in 0..SIDES {
+            let angle = i as f32 * 2. * ::std::f32::consts::PI / SIDES as f32;
+            let (x, z) = (angle.cos(), angle.sin());
+            buffers.attributes.push(vertex(vec3(x, 0., z)));
+            buffers.attributes.push(vertex(vec3(x, 1., z)));
+            let next = (i + 1) % SIDES;
+            let (a, b, c, d) = (2 * i, 2 * i + 1, 2 * next, 2 * next + 1);
+            buffers.indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+        buffers.recompute_normals(NormalMode::Smooth);
+        // the normals of the sides are half a side either side of each
+        // vertex, so interpolated normals are closer to pointing away from the
+        // axis
+        let half_side_cos = (::std::f32::consts::PI / SIDES as f32).cos();
+        for a in buffers.attributes.iter() {
+            let p = a.space_coord_px;
+            let radial = vec3(p.x, 0., p.z).normalize();
+            assert!(a.normal.y.abs() < 1e-5);
+            assert!((a.normal.magnitude() - 1.).abs() < 1e-5);
+            assert!(a.normal.dot(radial).abs() > half_side_cos + 1e-3);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use buffers::RelativeBuffers;
+use cgmath::{vec3, Vector3};
+
+impl RelativeBuffers {
+    fn translate_positions(&mut self, offset: Vector3<f32>) {
+        for a in self.attributes.iter_mut() {
+            a.space_coord_px += offset;
+        }
+    }
+
+    /// Moves the geometry such that the centre of its bounding box is at the
+    /// origin. Empty buffers are left unchanged.
+    pub fn recenter(&mut self) {
+        if let Some(center) = self.center() {
+            self.translate_positions(-center);
+        }
+    }
+
+    /// Like `recenter`, but only moves the geometry horizontally, so the
+    /// floor stays at a height of 0.
+    pub fn recenter_xz(&mut self) {
+        if let Some(center) = self.center() {
+            self.translate_positions(vec3(-center.x, 0., -center.z));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cgmath::{vec2, InnerSpace};
+
+    fn aabb_buffers() -> RelativeBuffers {
+        let vertex = |x: f32, y: f32, z: f32| Attribute {
+            space_coord_px: vec3(x, y, z),
+            tex_coord_px: vec2(0., 0.),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        };
+        RelativeBuffers {
+            attributes: vec![vertex(10., 0., 20.), vertex(30., 8., 24.)],
+            indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn recenter_moves_centre_to_origin() {
+        let mut buffers = aabb_buffers();
+        buffers.recenter();
+        let centre = buffers.center().unwrap();
+        assert!(centre.magnitude() < 1e-6);
+        let mut buffers = aabb_buffers();
+        buffers.recenter_xz();
+        assert_eq!(buffers.center(), Some(vec3(0., 4., 0.)));
+        assert_eq!(buffers.attributes[0].space_coord_px.y, 0.);
+    }
+}
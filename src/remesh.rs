@@ -0,0 +1,324 @@
+use buffers::RelativeBuffers;
+use cell::{wrap_coord, CellDetails, CellType};
+use generate::{cell_details, make_cell_walls, wall_heights};
+use geometry::make_floor;
+use grid_2d::{Coord, Grid, Size};
+use std::collections::HashMap;
+use style::{Config, Style};
+
+/// Returns `coord` and its eight neighbours which are in a grid of size
+/// `size`, wrapping around the edges of the grid if `config.wrap` is set.
+/// Each cell appears once, even in grids small enough for neighbours to wrap
+/// onto the same cell.
+fn neighbourhood(coord: Coord, size: Size, config: &Config) -> Vec<Coord> {
+    let mut coords = Vec::with_capacity(9);
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let neighbour = wrap_coord(coord + Coord::new(x, y), size, config);
+            let in_grid = neighbour.x >= 0
+                && neighbour.y >= 0
+                && (neighbour.x as u32) < size.x()
+                && (neighbour.y as u32) < size.y();
+            if in_grid && !coords.contains(&neighbour) {
+                coords.push(neighbour);
+            }
+        }
+    }
+    coords
+}
+
+/// Recomputes the details of the cells affected by a change to the type of
+/// the cell at `coord` in `grid`, which is the grid after the change. The
+/// pieces of a cell only depend on its eight neighbours, so only `coord` and
+/// its neighbours (wrapping around the edges if `config.wrap` is set) are
+/// affected, and the details of every other cell are unchanged. Each affected
+/// cell is returned with its details, which are `None` for cells which
+/// aren't solid.
+pub fn recompute_cell_details(
+    grid: &Grid<CellType>,
+    coord: Coord,
+    style: &Style,
+    config: &Config,
+) -> Vec<(Coord, Option<CellDetails>)> {
+    let height_grid = wall_heights(grid, None, style);
+    neighbourhood(coord, grid.size(), config)
+        .into_iter()
+        .map(|coord| {
+            (
+                coord,
+                cell_details(grid, height_grid.as_ref(), coord, config),
+            )
+        })
+        .collect()
+}
+
+/// Returns the walls and floor of the cell at `coord`, where walls have the
+/// heights in `height_grid` (see `wall_heights`).
+fn make_cell(
+    grid: &Grid<CellType>,
+    height_grid: Option<&Grid<f32>>,
+    coord: Coord,
+    style: &Style,
+    config: &Config,
+) -> Vec<RelativeBuffers> {
+    let cell = cell_details(grid, height_grid, coord, config);
+    let mut buffers = make_cell_walls(grid, cell.as_ref(), coord, style, config);
+    if grid
+        .get(coord)
+        .is_some_and(|cell_type| cell_type.has_floor())
+    {
+        buffers.push(make_floor(coord, style, config));
+    }
+    buffers
+}
+
+/// Returns the geometry of each cell affected by changes to the types of the
+/// cells at `dirty` in `grid`, which is the grid after the changes. These are
+/// the cells in `dirty` and their neighbours (see `recompute_cell_details`).
+/// Each cell is made once, even if it neighbours several dirty cells, and its
+/// geometry is the same as `generate_mesh` produces for it, except that
+/// ceilings aren't included and straight faces aren't merged. Cells with no
+/// geometry map to an empty list.
+pub fn remesh_region(
+    grid: &Grid<CellType>,
+    dirty: &[Coord],
+    style: &Style,
+    config: &Config,
+) -> HashMap<Coord, Vec<RelativeBuffers>> {
+    let height_grid = wall_heights(grid, None, style);
+    let mut region = HashMap::new();
+    for &dirty_coord in dirty {
+        for coord in neighbourhood(dirty_coord, grid.size(), config) {
+            region.entry(coord).or_insert_with(|| {
+                make_cell(grid, height_grid.as_ref(), coord, style, config)
+            });
+        }
+    }
+    region
+}
+
+/// The changes to the geometry of a grid between two versions of the grid.
+/// Every cell whose details may have changed, which are the changed cells
+/// and their neighbours (see `remesh_region`), appears in exactly one of
+/// `cells` and `removed`. Applying the delta means replacing the geometry of
+/// each cell in `cells`, and dropping the geometry of each cell in `removed`.
+#[derive(Debug, Clone)]
+pub struct GeometryDelta {
+    /// New geometry of each affected cell which has any geometry
+    pub cells: HashMap<Coord, Vec<RelativeBuffers>>,
+    /// Affected cells which no longer have any geometry, in row-major order,
+    /// whose previous geometry must be dropped without replacement
+    pub removed: Vec<Coord>,
+}
+
+/// Returns the changes to the geometry of `old` needed to match `new`, which
+/// must be the same size as `old` (see `GeometryDelta`).
+pub fn diff_geometry(
+    old: &Grid<CellType>,
+    new: &Grid<CellType>,
+    style: &Style,
+    config: &Config,
+) -> GeometryDelta {
+    let changed = old
+        .enumerate()
+        .filter(|&(coord, cell_type)| new.get(coord) != Some(cell_type))
+        .map(|(coord, _)| coord)
+        .collect::<Vec<_>>();
+    let mut cells = remesh_region(new, &changed, style, config);
+    let mut removed = cells
+        .iter()
+        .filter(|&(_, buffers)| buffers.iter().all(|b| b.indices.is_empty()))
+        .map(|(&coord, _)| coord)
+        .collect::<Vec<_>>();
+    removed.sort_by_key(|coord| (coord.y, coord.x));
+    for coord in removed.iter() {
+        cells.remove(coord);
+    }
+    GeometryDelta { cells, removed }
+}
+
+/// A grid together with the geometry of each of its cells, which keeps the
+/// geometry up to date as cells are changed, by regenerating only the cells
+/// affected by each change. The geometry doesn't include ceilings, and
+/// straight faces aren't merged.
+#[derive(Debug, Clone)]
+pub struct WallMesh {
+    grid: Grid<CellType>,
+    style: Style,
+    config: Config,
+    cells: Grid<Vec<RelativeBuffers>>,
+}
+
+impl WallMesh {
+    pub fn new(grid: Grid<CellType>, style: Style, config: Config) -> Self {
+        let height_grid = wall_heights(&grid, None, &style);
+        let cells = Grid::new_from_fn(grid.size(), |coord| {
+            make_cell(&grid, height_grid.as_ref(), coord, &style, &config)
+        });
+        Self {
+            grid,
+            style,
+            config,
+            cells,
+        }
+    }
+    pub fn grid(&self) -> &Grid<CellType> {
+        &self.grid
+    }
+    /// Changes the type of the cell at `coord`, and regenerates the geometry
+    /// of the cell and its neighbours. Does nothing if `coord` is outside the
+    /// grid or the cell already has the type `cell_type`.
+    pub fn set_cell(&mut self, coord: Coord, cell_type: CellType) {
+        match self.grid.get_mut(coord) {
+            Some(cell) if *cell != cell_type => *cell = cell_type,
+            _ => return,
+        }
+        let region = remesh_region(&self.grid, &[coord], &self.style, &self.config);
+        for (coord, buffers) in region {
+            if let Some(cell) = self.cells.get_mut(coord) {
+                *cell = buffers;
+            }
+        }
+    }
+    /// Returns the geometry of every cell combined, in the order of the cells.
+    pub fn full_buffers(&self) -> RelativeBuffers {
+        RelativeBuffers::concat_all(
+            self.cells
+                .iter()
+                .flat_map(|buffers| buffers.iter().cloned()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    const TERRAIN: &str = concat!(
+        "#######\n",
+        "#.....#\n",
+        "#.....#\n",
+        "#.....#\n",
+        "#.....#\n",
+        "#.....#\n",
+        "#######\n",
+    );
+
+    fn style() -> Style {
+        StyleBuilder::new().build()
+    }
+
+    fn config() -> Config {
+        ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build()
+    }
+
+    /// The details of every cell of `grid`, computed from scratch
+    fn all_details(grid: &Grid<CellType>) -> Grid<Option<CellDetails>> {
+        let height_grid = wall_heights(grid, None, &style());
+        Grid::new_from_fn(grid.size(), |coord| {
+            cell_details(grid, height_grid.as_ref(), coord, &config())
+        })
+    }
+
+    #[test]
+    fn only_neighbourhood_details_change() {
+        let old = parse_terrain(TERRAIN).unwrap();
+        let mut new = old.clone();
+        let coord = Coord::new(3, 1);
+        *new.get_mut(coord).unwrap() = CellType::Wall;
+        let recomputed = recompute_cell_details(&new, coord, &style(), &config());
+        let mut coords = recomputed.iter().map(|&(c, _)| c).collect::<Vec<_>>();
+        coords.sort_by_key(|c| (c.y, c.x));
+        let mut expected = Vec::new();
+        for y in 0..=2 {
+            for x in 2..=4 {
+                expected.push(Coord::new(x, y));
+            }
+        }
+        assert_eq!(coords, expected);
+        let old_details = all_details(&old);
+        let new_details = all_details(&new);
+        let changed = new_details
+            .enumerate()
+            .filter(|&(c, details)| old_details.get(c) != Some(details))
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        // the wall the new cell joins onto changes as well as the new cell
+        assert!(changed.contains(&Coord::new(3, 0)));
+        for c in changed {
+            assert!(coords.contains(&c), "{:?} changed", c);
+        }
+        for (c, details) in recomputed {
+            assert_eq!(new_details.get(c), Some(&details));
+        }
+    }
+
+    #[test]
+    fn region_matches_full_regeneration() {
+        let mut grid = parse_terrain(TERRAIN).unwrap();
+        let dirty = [Coord::new(3, 1), Coord::new(4, 2), Coord::new(1, 5)];
+        for &coord in dirty.iter() {
+            *grid.get_mut(coord).unwrap() = CellType::Wall;
+        }
+        let region = remesh_region(&grid, &dirty, &style(), &config());
+        // cells neighbouring more than one dirty cell are only made once
+        assert_eq!(region.len(), 9 + 9 - 4 + 9);
+        let full = WallMesh::new(grid, style(), config());
+        for (&coord, buffers) in region.iter() {
+            assert_eq!(Some(buffers), full.cells.get(coord), "{:?}", coord);
+        }
+    }
+
+    #[test]
+    fn delta_covers_neighbourhood_once() {
+        let old = parse_terrain(TERRAIN).unwrap();
+        let mut new = old.clone();
+        *new.get_mut(Coord::new(3, 1)).unwrap() = CellType::Wall;
+        let delta = diff_geometry(&old, &new, &style(), &config());
+        for coord in delta.removed.iter() {
+            assert!(!delta.cells.contains_key(coord), "{:?}", coord);
+        }
+        let mut coords = delta
+            .cells
+            .keys()
+            .chain(delta.removed.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        coords.sort_by_key(|c| (c.y, c.x));
+        let mut expected = Vec::new();
+        for y in 0..=2 {
+            for x in 2..=4 {
+                expected.push(Coord::new(x, y));
+            }
+        }
+        assert_eq!(coords, expected);
+        let unchanged = diff_geometry(&new, &new, &style(), &config());
+        assert!(unchanged.cells.is_empty() && unchanged.removed.is_empty());
+    }
+
+    #[test]
+    fn set_and_unset_restores_buffers() {
+        let grid = parse_terrain(TERRAIN).unwrap();
+        let mut mesh = WallMesh::new(grid.clone(), style(), config());
+        let original = mesh.full_buffers();
+        let coord = Coord::new(3, 1);
+        mesh.set_cell(coord, CellType::Wall);
+        assert_eq!(mesh.grid().get(coord), Some(&CellType::Wall));
+        assert_ne!(mesh.full_buffers(), original);
+        let mut changed = grid.clone();
+        *changed.get_mut(coord).unwrap() = CellType::Wall;
+        assert_eq!(
+            mesh.full_buffers(),
+            WallMesh::new(changed, style(), config()).full_buffers()
+        );
+        mesh.set_cell(coord, CellType::Floor);
+        assert!(mesh.grid().iter().eq(grid.iter()));
+        assert_eq!(mesh.full_buffers(), original);
+    }
+}
@@ -0,0 +1,110 @@
+use buffers::{face_normal, RelativeBuffers};
+use cell::CellType;
+use cgmath::{vec3, InnerSpace};
+use direction::CardinalDirections;
+use grid_2d::{Coord, Grid};
+use style::Config;
+
+fn is_room(cell_type: CellType) -> bool {
+    match cell_type {
+        CellType::Floor | CellType::Stairs(_) | CellType::Ramp(_) => true,
+        CellType::Wall
+        | CellType::Door
+        | CellType::Window
+        | CellType::Railing
+        | CellType::Diagonal(_) => false,
+    }
+}
+
+/// Labels each region of floor, stairs and ramps in `grid` which is
+/// connected without passing through a wall, numbering regions from 0 in
+/// the order their first cell appears in row-major order. Doors, windows,
+/// railings and diagonal walls separate regions like walls do, and have no
+/// label.
+pub fn room_ids(grid: &Grid<CellType>) -> Grid<Option<u32>> {
+    let mut ids = Grid::new_from_fn(grid.size(), |_| None);
+    let mut next_id = 0;
+    for (start, &cell_type) in grid.enumerate() {
+        if !is_room(cell_type) || ids.get(start) != Some(&None) {
+            continue;
+        }
+        // flood fill with an explicit stack, so large rooms can't overflow
+        // the call stack
+        let mut to_visit = vec![start];
+        while let Some(coord) = to_visit.pop() {
+            match grid.get(coord) {
+                Some(&cell_type) if is_room(cell_type) => (),
+                _ => continue,
+            }
+            match ids.get_mut(coord) {
+                Some(id) if id.is_none() => *id = Some(next_id),
+                _ => continue,
+            }
+            for direction in CardinalDirections {
+                to_visit.push(coord + direction.coord());
+            }
+        }
+        next_id += 1;
+    }
+    ids
+}
+
+impl RelativeBuffers {
+    /// Returns the room (see `room_ids`) each triangle faces, in order, or
+    /// `None` for triangles which don't face a room, such as the tops of
+    /// walls. The faces of walls take the room of the cell half a cell in
+    /// front of them, and floors and ceilings the room of the cell they
+    /// cover. `config` must be the one the geometry was generated with.
+    pub fn triangle_rooms(
+        &self,
+        room_ids: &Grid<Option<u32>>,
+        config: &Config,
+    ) -> Vec<Option<u32>> {
+        let cell_size = config.cell_size_px * config.units_per_px;
+        self.indices
+            .chunks(3)
+            .map(|t| {
+                let corners = [
+                    self.attributes[t[0] as usize].space_coord_px,
+                    self.attributes[t[1] as usize].space_coord_px,
+                    self.attributes[t[2] as usize].space_coord_px,
+                ];
+                let normal = face_normal(corners[0], corners[1], corners[2]);
+                if normal.magnitude2() == 0. {
+                    return None;
+                }
+                let normal = normal.normalize();
+                let centre = (corners[0] + corners[1] + corners[2]) / 3.;
+                let p = centre
+                    + vec3(normal.x * cell_size.x, 0., normal.z * cell_size.y) / 2.;
+                let coord = Coord::new(
+                    (p.x / cell_size.x).floor() as i32,
+                    (p.z / cell_size.y).floor() as i32,
+                );
+                room_ids.get(coord).and_then(|&id| id)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::parse_terrain;
+
+    #[test]
+    fn walls_and_doors_separate_rooms() {
+        let grid =
+            parse_terrain(concat!("#######\n", "#..#..#\n", "#..+..#\n", "#######\n",))
+                .unwrap();
+        let ids = room_ids(&grid);
+        for (coord, &cell_type) in grid.enumerate() {
+            let id = *ids.get(coord).unwrap();
+            match cell_type {
+                CellType::Floor if coord.x < 3 => assert_eq!(id, Some(0)),
+                CellType::Floor => assert_eq!(id, Some(1)),
+                _ => assert_eq!(id, None),
+            }
+        }
+    }
+}
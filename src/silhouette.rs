@@ -0,0 +1,105 @@
+use buffers::{face_normal, RelativeBuffers};
+use cgmath::{InnerSpace, Vector3};
+use cleanup::{quantize, NORMAL_EPSILON};
+use std::collections::HashMap;
+
+// Edges are matched up by the positions of their ends, as vertices at the
+// same position are often distinct, such as either side of a corner.
+const POSITION_EPSILON: f32 = 1. / 1024.;
+
+type PositionKey = [i64; 3];
+
+fn position_key(p: Vector3<f32>) -> PositionKey {
+    [
+        quantize(p.x, POSITION_EPSILON),
+        quantize(p.y, POSITION_EPSILON),
+        quantize(p.z, POSITION_EPSILON),
+    ]
+}
+
+impl RelativeBuffers {
+    /// Returns the edges which separate triangles facing towards a light
+    /// shining in the direction `light_dir` from triangles facing away from
+    /// it, for extruding into shadow volumes. Edges of only one triangle are
+    /// also included, so open meshes still cast closed shadow volumes. Each
+    /// edge is a pair of vertex indices, ordered as they are wound in a
+    /// triangle which faces the light where there is one. Triangles are
+    /// joined along edges whose ends are in the same positions, even if they
+    /// are made of different vertices.
+    pub fn silhouette_edges(&self, light_dir: Vector3<f32>) -> Vec<(u32, u32)> {
+        let light_dir = light_dir.normalize();
+        let mut order = Vec::new();
+        let mut triangles_by_edge: HashMap<_, Vec<(bool, (u32, u32))>> = HashMap::new();
+        for t in self.indices.chunks(3) {
+            let position = |i: u32| self.attributes[i as usize].space_coord_px;
+            let normal = face_normal(position(t[0]), position(t[1]), position(t[2]));
+            // faces which are edge-on to the light, up to rounding errors,
+            // count as facing away from it
+            let faces_light = normal.normalize().dot(light_dir) < -NORMAL_EPSILON;
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                let (key_a, key_b) =
+                    (position_key(position(a)), position_key(position(b)));
+                let key = if key_a < key_b {
+                    (key_a, key_b)
+                } else {
+                    (key_b, key_a)
+                };
+                let triangles = triangles_by_edge.entry(key).or_insert_with(|| {
+                    order.push(key);
+                    Vec::new()
+                });
+                triangles.push((faces_light, (a, b)));
+            }
+        }
+        order
+            .iter()
+            .filter_map(|key| {
+                let triangles = &triangles_by_edge[key];
+                let lit = triangles.iter().find(|&&(faces_light, _)| faces_light);
+                let is_silhouette = triangles.len() == 1
+                    || triangles
+                        .iter()
+                        .any(|&(faces_light, _)| faces_light != triangles[0].0);
+                if is_silhouette {
+                    Some(lit.unwrap_or(&triangles[0]).1)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+    use collision::aabb_mesh;
+
+    #[test]
+    fn box_silhouette_edges() {
+        let mesh = aabb_mesh(vec3(0., 0., 0.), vec3(1., 1., 1.));
+        // from above, only the top faces the light, so the silhouette is the
+        // outline of the top
+        let edges = mesh.silhouette_edges(vec3(0., -1., 0.));
+        assert_eq!(edges.len(), 4);
+        for &(a, b) in edges.iter() {
+            assert_eq!(mesh.attributes[a as usize].space_coord_px.y, 1.);
+            assert_eq!(mesh.attributes[b as usize].space_coord_px.y, 1.);
+        }
+        // from above and to the side, the top and one side face the light
+        let edges = mesh.silhouette_edges(vec3(1., -1., 0.));
+        assert_eq!(edges.len(), 6);
+        // each edge is wound as in a triangle which faces the light
+        for &(a, b) in edges.iter() {
+            let found = mesh.indices.chunks(3).any(|t| {
+                let position = |i: u32| mesh.attributes[i as usize].space_coord_px;
+                let normal = face_normal(position(t[0]), position(t[1]), position(t[2]));
+                let faces_light = normal.dot(vec3(1., -1., 0.)) < 0.;
+                faces_light && (0..3).any(|k| (t[k], t[(k + 1) % 3]) == (a, b))
+            });
+            assert!(found, "{:?}", (a, b));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use buffers::RelativeBuffers;
+use std::collections::HashMap;
+use std::mem;
+
+/// A contiguous range of the index buffer whose triangles all share a
+/// material
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRange {
+    pub material: u32,
+    pub start_index: u32,
+    pub index_count: u32,
+}
+
+impl RelativeBuffers {
+    /// Splits the buffers into one set of buffers per material, so each
+    /// material can be drawn separately. Each triangle is placed according to
+    /// the material of its first vertex.
+    pub fn split_by_material(&self) -> HashMap<u32, RelativeBuffers> {
+        let mut split: HashMap<u32, (RelativeBuffers, HashMap<u32, u32>)> =
+            HashMap::new();
+        for triangle in self.indices.chunks(3) {
+            let material = self.attributes[triangle[0] as usize].material;
+            let (buffers, remap) = split.entry(material).or_insert_with(|| {
+                (
+                    RelativeBuffers {
+                        attributes: Vec::new(),
+                        indices: Vec::new(),
+                    },
+                    HashMap::new(),
+                )
+            });
+            for &i in triangle {
+                let attributes = &mut buffers.attributes;
+                let index = *remap.entry(i).or_insert_with(|| {
+                    attributes.push(self.attributes[i as usize]);
+                    attributes.len() as u32 - 1
+                });
+                buffers.indices.push(index);
+            }
+        }
+        split
+            .into_iter()
+            .map(|(material, (buffers, _))| (material, buffers))
+            .collect()
+    }
+
+    /// Reorders the triangles in the index buffer so that the triangles of
+    /// each material are contiguous, and returns the range of each material,
+    /// so each material can be drawn separately from a single index buffer.
+    /// Materials are ordered as they appear in `materials`, followed by any
+    /// materials not in `materials` in increasing order. Each triangle is
+    /// placed according to the material of its first vertex, and the order
+    /// of the triangles of a material is preserved. Vertices are unchanged.
+    pub fn sort_by_material(&mut self, materials: &[u32]) -> Vec<DrawRange> {
+        let order = |material: u32| {
+            materials
+                .iter()
+                .position(|&m| m == material)
+                .map_or((1, material), |position| (0, position as u32))
+        };
+        let mut triangles = self
+            .indices
+            .chunks(3)
+            .map(|triangle| {
+                let material = self.attributes[triangle[0] as usize].material;
+                (material, [triangle[0], triangle[1], triangle[2]])
+            })
+            .collect::<Vec<_>>();
+        triangles.sort_by_key(|&(material, _)| order(material));
+        self.indices.clear();
+        let mut ranges: Vec<DrawRange> = Vec::new();
+        for (material, triangle) in triangles {
+            let start_index = self.indices.len() as u32;
+            self.indices.extend_from_slice(&triangle);
+            match ranges.last_mut() {
+                Some(range) if range.material == material => range.index_count += 3,
+                _ => ranges.push(DrawRange {
+                    material,
+                    start_index,
+                    index_count: 3,
+                }),
+            }
+        }
+        ranges
+    }
+
+    /// Splits the buffers into consecutive parts of at most `max_vertices`
+    /// vertices each, such as to fit the indices of each part in 16 bits.
+    /// Triangles are kept whole and in order, and each part only contains the
+    /// vertices its triangles use, so vertices shared by triangles in
+    /// different parts are duplicated. `max_vertices` must be at least 3.
+    pub fn split_by_vertex_limit(&self, max_vertices: usize) -> Vec<RelativeBuffers> {
+        let mut parts = Vec::new();
+        let mut part = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: Vec::new(),
+        };
+        let mut remap = HashMap::new();
+        for triangle in self.indices.chunks(3) {
+            let mut new_vertices = triangle
+                .iter()
+                .filter(|i| !remap.contains_key(*i))
+                .collect::<Vec<_>>();
+            new_vertices.sort();
+            new_vertices.dedup();
+            if part.attributes.len() + new_vertices.len() > max_vertices {
+                let full = mem::replace(
+                    &mut part,
+                    RelativeBuffers {
+                        attributes: Vec::new(),
+                        indices: Vec::new(),
+                    },
+                );
+                parts.push(full);
+                remap.clear();
+            }
+            for &i in triangle {
+                let attributes = &mut part.attributes;
+                let index = *remap.entry(i).or_insert_with(|| {
+                    attributes.push(self.attributes[i as usize]);
+                    attributes.len() as u32 - 1
+                });
+                part.indices.push(index);
+            }
+        }
+        if !part.indices.is_empty() {
+            parts.push(part);
+        }
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffers::RelativeBuffers;
+    use cgmath::vec2;
+    use generate::generate_mesh;
+    use style::{ConfigBuilder, StyleBuilder};
+    use terrain::parse_terrain;
+
+    #[test]
+    fn tops_and_faces_are_split() {
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build();
+        let grid = parse_terrain("##\n").unwrap();
+        let split = generate_mesh(&grid, &style, &config).split_by_material();
+        assert_eq!(split.len(), 2);
+        let tops = &split[&style.top_material];
+        let faces = &split[&style.face_material];
+        assert!(!tops.indices.is_empty() && !faces.indices.is_empty());
+        assert!(tops.attributes.iter().all(|a| a.normal.y == 1.));
+        assert!(faces.attributes.iter().all(|a| a.normal.y == 0.));
+    }
+
+    #[test]
+    fn draw_ranges_are_contiguous() {
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build();
+        let grid = parse_terrain("##\n").unwrap();
+        let original = generate_mesh(&grid, &style, &config);
+        let mut sorted = original.clone();
+        let ranges = sorted.sort_by_material(&[style.top_material]);
+        assert_eq!(sorted.attributes, original.attributes);
+        let materials = ranges.iter().map(|r| r.material).collect::<Vec<_>>();
+        assert_eq!(materials, vec![style.top_material, style.face_material]);
+        let mut start_index = 0;
+        for range in ranges {
+            assert_eq!(range.start_index, start_index);
+            let end_index = start_index + range.index_count;
+            for &i in sorted.indices[start_index as usize..end_index as usize].iter() {
+                assert_eq!(sorted.attributes[i as usize].material, range.material);
+            }
+            start_index = end_index;
+        }
+        assert_eq!(start_index as usize, sorted.indices.len());
+    }
+
+    #[test]
+    fn parts_fit_the_vertex_limit() {
+        let style = StyleBuilder::new().build();
+        let config = ConfigBuilder::new()
+            .cell_size_px(vec2(32., 32.))
+            .tex_top_piece_size(16.)
+            .build();
+        let grid = parse_terrain("####\n#..#\n####\n").unwrap();
+        let original = generate_mesh(&grid, &style, &config);
+        let parts = original.split_by_vertex_limit(64);
+        assert!(parts.len() > 1);
+        for part in parts.iter() {
+            assert!(part.attributes.len() <= 64);
+        }
+        // the parts have the same triangles in the same order
+        let triangles = |buffers: &RelativeBuffers| {
+            buffers
+                .triangles()
+                .map(|t| [*t[0], *t[1], *t[2]])
+                .collect::<Vec<_>>()
+        };
+        let split_triangles = parts.iter().flat_map(triangles).collect::<Vec<_>>();
+        assert_eq!(split_triangles, triangles(&original));
+    }
+}
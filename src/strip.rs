@@ -0,0 +1,112 @@
+use buffers::RelativeBuffers;
+
+// Returns the vertex to add to `strip` so that its next triangle is
+// `triangle`, if `triangle` shares the last edge of the strip. Odd triangles
+// of a strip have their winding reversed.
+fn continues(strip: &[u32], triangle: [u32; 3]) -> Option<u32> {
+    let n = strip.len();
+    if n < 3 {
+        return None;
+    }
+    let edge = if (n - 2).is_multiple_of(2) {
+        (strip[n - 2], strip[n - 1])
+    } else {
+        (strip[n - 1], strip[n - 2])
+    };
+    (0..3)
+        .map(|r| rotate(triangle, r))
+        .find(|t| (t[0], t[1]) == edge)
+        .map(|t| t[2])
+}
+
+fn rotate(t: [u32; 3], r: usize) -> [u32; 3] {
+    [t[r % 3], t[(r + 1) % 3], t[(r + 2) % 3]]
+}
+
+impl RelativeBuffers {
+    /// Returns indices which draw the same triangles as `indices` with the
+    /// triangle strip primitive topology, where each index after the first
+    /// two adds a triangle made of it and the previous two indices. Each run
+    /// of consecutive triangles in `indices` which share edges becomes part
+    /// of a strip, and runs are joined by degenerate triangles, so the whole
+    /// mesh is a single strip without restart indices. Triangles keep their
+    /// winding, as strips reverse the winding of every other triangle.
+    pub fn to_triangle_strip(&self) -> Vec<u32> {
+        let triangles = self
+            .indices
+            .chunks(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect::<Vec<_>>();
+        let mut strip: Vec<u32> = Vec::new();
+        for (i, &triangle) in triangles.iter().enumerate() {
+            if let Some(index) = continues(&strip, triangle) {
+                strip.push(index);
+                continue;
+            }
+            // start a new run, rotated such that the next triangle can
+            // continue it if possible
+            let next = triangles.get(i + 1);
+            let start = (0..3)
+                .map(|r| rotate(triangle, r))
+                .find(|&t| next.is_some_and(|&next| continues(&t, next).is_some()))
+                .unwrap_or(triangle);
+            if let Some(&last) = strip.last() {
+                strip.push(last);
+                strip.push(start[0]);
+                // the run must start at an even position to keep its winding
+                if strip.len() % 2 == 1 {
+                    strip.push(start[0]);
+                }
+            }
+            strip.extend_from_slice(&start);
+        }
+        strip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+    use collision::aabb_mesh;
+
+    /// The non-degenerate triangles drawn by `strip`, each rotated to start at
+    /// its lowest index, so triangles with the same winding are equal
+    fn strip_triangles(strip: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles = strip
+            .windows(3)
+            .enumerate()
+            .filter(|&(_, w)| w[0] != w[1] && w[1] != w[2] && w[0] != w[2])
+            .map(|(i, w)| {
+                if i % 2 == 0 {
+                    [w[0], w[1], w[2]]
+                } else {
+                    [w[1], w[0], w[2]]
+                }
+            })
+            .map(canonical)
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    fn canonical(t: [u32; 3]) -> [u32; 3] {
+        (0..3).map(|r| rotate(t, r)).min().unwrap()
+    }
+
+    #[test]
+    fn strip_draws_the_same_triangles() {
+        let mesh = aabb_mesh(vec3(0., 0., 0.), vec3(1., 2., 3.));
+        let strip = mesh.to_triangle_strip();
+        let mut triangles = mesh
+            .indices
+            .chunks(3)
+            .map(|t| canonical([t[0], t[1], t[2]]))
+            .collect::<Vec<_>>();
+        triangles.sort();
+        assert_eq!(strip_triangles(&strip), triangles);
+        // the two triangles of each side of the box share an edge, so are
+        // drawn with one index after the first triangle
+        assert!(strip.len() < mesh.indices.len());
+    }
+}
@@ -0,0 +1,557 @@
+use atlas::TextureNames;
+use cell::CellType;
+use cgmath::{vec2, Vector2};
+use direction::CardinalDirection;
+use grid_2d::Coord;
+use std::borrow::Cow;
+
+/// Which floor cells have a ceiling above them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Ceilings {
+    None,
+    /// Only floor cells which are completely surrounded by walls, and can't
+    /// reach the edge of the grid without passing through one
+    Enclosed,
+    All,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Config {
+    /// Size of each cell, where `x` is along the x axis and `y` is along the
+    /// z axis, corresponding to the rows of the grid
+    pub cell_size_px: Vector2<f32>,
+    pub tex_top_piece_size: f32,
+    pub ceilings: Ceilings,
+    /// Size of a pixel in world units. Generated positions are multiplied by
+    /// this, but texture coordinates remain in pixels. Use 1.0 to output
+    /// positions in pixels.
+    pub units_per_px: f32,
+    /// Whether to merge the faces of walls along straight runs into single
+    /// faces (see `RelativeBuffers::merge_straight_faces`), rather than
+    /// making separate faces for each cell. This has no effect unless the
+    /// face texture wraps (see `Style::face_tex_wraps`).
+    pub merge_straight_faces: bool,
+    /// Number of segments in the arc of rounded corners. Corners are sharp
+    /// when this is 0 or 1.
+    pub corner_segments: u32,
+    /// Number of segments in each quarter of the outline of pillars. When
+    /// this is positive, wall cells with no solid neighbours are made as
+    /// round pillars (see `Piece::Pillar`) rather than square posts, so 2
+    /// gives octagonal pillars.
+    pub pillar_segments: u32,
+    /// Number of parts each segment of the arcs of rounded corners and
+    /// pillars is divided into, for smoother curves without changing which
+    /// corners are rounded. 0 is treated as 1.
+    pub subdivisions: u32,
+    /// Number of steps in stairs (see `CellType::Stairs`)
+    pub stair_step_count: u32,
+    /// The type of cell assumed beyond the edges of the grid. With `Floor`,
+    /// walls at the edge of the grid have faces along the edge, and with
+    /// `Wall`, they are treated as continuing beyond it.
+    pub border: CellType,
+    /// Whether cells beyond each edge of the grid are taken from the opposite
+    /// edge, such that the grid tiles seamlessly. When set, `border` is
+    /// unused.
+    pub wrap: bool,
+    /// Seed for the choice of face texture variant of each cell (see
+    /// `Style::face_tex_variants_px`)
+    pub seed: u64,
+}
+
+impl Default for Config {
+    /// Cells of 16x16 pixels, with pieces of the top texture the size of a
+    /// quarter of a cell, positions in pixels, and no ceilings or merging
+    fn default() -> Self {
+        Self {
+            cell_size_px: vec2(16., 16.),
+            tex_top_piece_size: 8.,
+            ceilings: Ceilings::None,
+            units_per_px: 1.,
+            merge_straight_faces: false,
+            corner_segments: 1,
+            pillar_segments: 0,
+            subdivisions: 1,
+            stair_step_count: 4,
+            border: CellType::Floor,
+            wrap: false,
+            seed: 0,
+        }
+    }
+}
+
+/// Builds a `Config`, starting from `Config::default()` and overriding
+/// individual fields.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cell_size_px(mut self, cell_size_px: Vector2<f32>) -> Self {
+        self.config.cell_size_px = cell_size_px;
+        self
+    }
+    pub fn tex_top_piece_size(mut self, tex_top_piece_size: f32) -> Self {
+        self.config.tex_top_piece_size = tex_top_piece_size;
+        self
+    }
+    pub fn ceilings(mut self, ceilings: Ceilings) -> Self {
+        self.config.ceilings = ceilings;
+        self
+    }
+    pub fn units_per_px(mut self, units_per_px: f32) -> Self {
+        self.config.units_per_px = units_per_px;
+        self
+    }
+    pub fn merge_straight_faces(mut self, merge_straight_faces: bool) -> Self {
+        self.config.merge_straight_faces = merge_straight_faces;
+        self
+    }
+    pub fn corner_segments(mut self, corner_segments: u32) -> Self {
+        self.config.corner_segments = corner_segments;
+        self
+    }
+    pub fn pillar_segments(mut self, pillar_segments: u32) -> Self {
+        self.config.pillar_segments = pillar_segments;
+        self
+    }
+    pub fn subdivisions(mut self, subdivisions: u32) -> Self {
+        self.config.subdivisions = subdivisions;
+        self
+    }
+    pub fn stair_step_count(mut self, stair_step_count: u32) -> Self {
+        self.config.stair_step_count = stair_step_count;
+        self
+    }
+    pub fn border(mut self, border: CellType) -> Self {
+        self.config.border = border;
+        self
+    }
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.config.wrap = wrap;
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = seed;
+        self
+    }
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Style {
+    pub width_px: f32,
+    pub height_px: f32,
+    pub face_tex_top_left_px: Vector2<f32>,
+    /// Top-left of the face texture for faces facing each cardinal direction
+    /// (indexed by `CardinalDirection as usize`), or `None` to use
+    /// `face_tex_top_left_px` for all faces
+    pub face_tex_top_left_px_by_facing: Option<[Vector2<f32>; 4]>,
+    /// Top-left of each variant of the face texture. If there are any
+    /// variants, the faces of each cell use one of them in place of
+    /// `face_tex_top_left_px` and `face_tex_top_left_px_by_facing`, chosen
+    /// pseudo-randomly from the cell's coordinate and `Config::seed`.
+    pub face_tex_variants_px: Vec<Vector2<f32>>,
+    /// Height of the face texture, if it repeats vertically. Faces are split
+    /// into rows this high, counting down from `height_px`, each of which
+    /// samples the same strip of texture, with a partial row at the bottom.
+    /// If `None`, faces sample a single strip of texture as high as the wall.
+    pub face_tex_tile_height_px: Option<f32>,
+    /// Whether the face texture wraps horizontally, as when it has a texture
+    /// or texture array layer of its own with a repeating sampler, rather
+    /// than a region of an atlas. Faces are only merged along straight runs
+    /// of wall (see `Config::merge_straight_faces`) when this is set, since
+    /// merged faces sample beyond a single repeat of the texture.
+    pub face_tex_wraps: bool,
+    pub top_tex_top_left_px: Vector2<f32>,
+    /// Number of clockwise quarter turns by which the top texture is rotated
+    /// within each piece of the texture. This only affects the tops of walls,
+    /// and not their faces.
+    pub top_tex_quarter_turns: u32,
+    /// Whether the top texture of each cell is rotated by a further number of
+    /// quarter turns, chosen pseudo-randomly from the cell's coordinate and
+    /// `Config::seed`, to hide repetition
+    pub rotate_top_tex_per_cell: bool,
+    pub floor_tex_top_left_px: Vector2<f32>,
+    pub ceiling_tex_top_left_px: Vector2<f32>,
+    /// Names of the atlas entries for the faces, tops, floors and ceilings,
+    /// or `None` to use the texture offsets above. Names are looked up when
+    /// generating geometry with an atlas (see `generate_mesh_with_atlas`),
+    /// and replace the corresponding `*_tex_top_left_px`.
+    pub texture_names: Option<TextureNames>,
+    /// Material of the vertical faces of walls
+    pub face_material: u32,
+    /// Material of the horizontal tops of walls, including the surfaces above
+    /// and below openings
+    pub top_material: u32,
+    /// Material of the floor of floor cells
+    pub floor_material: u32,
+    /// Material of the ceiling above floor cells
+    pub ceiling_material: u32,
+    /// Layer of a texture array sampled by the vertical faces of walls. When
+    /// each surface has its own layer, the corresponding `*_tex_top_left_px`
+    /// can be left at zero.
+    pub face_tex_layer: u32,
+    /// Layer of a texture array sampled by the horizontal tops of walls
+    pub top_tex_layer: u32,
+    /// Layer of a texture array sampled by the floor of floor cells
+    pub floor_tex_layer: u32,
+    /// Layer of a texture array sampled by the ceiling above floor cells
+    pub ceiling_tex_layer: u32,
+    /// Width of the opening in door cells, as a fraction of `cell_size_px`
+    pub door_width_fraction: f32,
+    /// Height of the underside of the lintel above the opening in door cells
+    pub door_height_px: f32,
+    /// Width of the opening in window cells, as a fraction of `cell_size_px`
+    pub window_width_fraction: f32,
+    /// Height of the bottom of the opening in window cells
+    pub window_sill_px: f32,
+    /// Height of the opening in window cells
+    pub window_height_px: f32,
+    /// Size of the chamfer along the top edges of walls. The top of each wall
+    /// is inset by this amount, and joined to the faces by an angled strip.
+    /// Diagonal walls aren't bevelled.
+    pub top_bevel_px: f32,
+    /// Height of railing cells
+    pub railing_height_px: f32,
+    /// Height of the baseboard along the bottom of wall faces, or 0 for no
+    /// baseboard. Diagonal walls don't have baseboards.
+    pub baseboard_px: f32,
+    /// Distance the baseboard protrudes from wall faces
+    pub baseboard_depth_px: f32,
+    /// Top-left of the baseboard texture. The front of the baseboard uses
+    /// the strip of texture as high as the baseboard, and its top the strip
+    /// below that as deep as the baseboard.
+    pub baseboard_tex_top_left_px: Vector2<f32>,
+    /// Height of the crown molding along the top of wall faces, or 0 for no
+    /// crown. The crown sits below the bevel (see `top_bevel_px`). Diagonal
+    /// walls don't have crowns.
+    pub crown_px: f32,
+    /// Distance the crown protrudes from wall faces
+    pub crown_depth_px: f32,
+    /// Top-left of the crown texture, which is laid out like the baseboard
+    /// texture (see `baseboard_tex_top_left_px`)
+    pub crown_tex_top_left_px: Vector2<f32>,
+}
+
+impl Style {
+    /// Returns the top-left of the face texture for faces facing `facing`
+    pub fn face_tex_top_left_px_facing(&self, facing: CardinalDirection) -> Vector2<f32> {
+        self.face_tex_top_left_px_by_facing
+            .map_or(self.face_tex_top_left_px, |by_facing| {
+                by_facing[facing as usize]
+            })
+    }
+    /// Returns the style of the cell at `coord`, whose faces use the face
+    /// texture variant chosen for the cell, and whose top texture is rotated
+    /// if `rotate_top_tex_per_cell` is set. The style is only copied when it
+    /// differs between cells.
+    pub fn for_cell<'a>(&'a self, coord: Coord, config: &Config) -> Cow<'a, Style> {
+        if self.face_tex_variants_px.is_empty() && !self.rotate_top_tex_per_cell {
+            return Cow::Borrowed(self);
+        }
+        let cell = ((coord.x as u32 as u64) << 32) | coord.y as u32 as u64;
+        let hash = mix(config.seed ^ mix(cell));
+        let mut style = self.clone();
+        if !self.face_tex_variants_px.is_empty() {
+            style.face_tex_top_left_px = self.face_tex_variants_px
+                [(hash % self.face_tex_variants_px.len() as u64) as usize];
+            style.face_tex_top_left_px_by_facing = None;
+            style.face_tex_variants_px = Vec::new();
+        }
+        if self.rotate_top_tex_per_cell {
+            style.top_tex_quarter_turns += (hash >> 62) as u32;
+            style.rotate_top_tex_per_cell = false;
+        }
+        Cow::Owned(style)
+    }
+}
+
+/// Scrambles the bits of `x`, such that similar inputs give unrelated
+/// outputs (this is the finaliser of SplitMix64). Unlike the hashers in the
+/// standard library, the result is guaranteed not to change between
+/// versions, so the same seed always gives the same variants.
+fn mix(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Builds a `Style`, starting from defaults which suit the example texture
+/// atlas with cells of 32x32 pixels, and overriding individual fields.
+#[derive(Debug, Clone)]
+pub struct StyleBuilder {
+    style: Style,
+}
+
+impl StyleBuilder {
+    pub fn new() -> Self {
+        Self {
+            style: Style {
+                width_px: 8.,
+                height_px: 32.,
+                face_tex_top_left_px: vec2(64., 16.),
+                face_tex_top_left_px_by_facing: None,
+                face_tex_variants_px: Vec::new(),
+                face_tex_tile_height_px: None,
+                face_tex_wraps: false,
+                top_tex_top_left_px: vec2(16., 16.),
+                top_tex_quarter_turns: 0,
+                rotate_top_tex_per_cell: false,
+                floor_tex_top_left_px: vec2(0., 48.),
+                ceiling_tex_top_left_px: vec2(0., 48.),
+                texture_names: None,
+                face_material: 0,
+                top_material: 1,
+                floor_material: 2,
+                ceiling_material: 3,
+                face_tex_layer: 0,
+                top_tex_layer: 0,
+                floor_tex_layer: 0,
+                ceiling_tex_layer: 0,
+                door_width_fraction: 0.5,
+                door_height_px: 24.,
+                window_width_fraction: 0.5,
+                window_sill_px: 8.,
+                window_height_px: 16.,
+                top_bevel_px: 0.,
+                railing_height_px: 12.,
+                baseboard_px: 0.,
+                baseboard_depth_px: 2.,
+                baseboard_tex_top_left_px: vec2(0., 0.),
+                crown_px: 0.,
+                crown_depth_px: 2.,
+                crown_tex_top_left_px: vec2(0., 0.),
+            },
+        }
+    }
+    pub fn width_px(mut self, width_px: f32) -> Self {
+        self.style.width_px = width_px;
+        self
+    }
+    pub fn height_px(mut self, height_px: f32) -> Self {
+        self.style.height_px = height_px;
+        self
+    }
+    pub fn face_tex_top_left_px(mut self, face_tex_top_left_px: Vector2<f32>) -> Self {
+        self.style.face_tex_top_left_px = face_tex_top_left_px;
+        self
+    }
+    pub fn face_tex_top_left_px_by_facing(
+        mut self,
+        face_tex_top_left_px_by_facing: Option<[Vector2<f32>; 4]>,
+    ) -> Self {
+        self.style.face_tex_top_left_px_by_facing = face_tex_top_left_px_by_facing;
+        self
+    }
+    pub fn face_tex_variants_px(
+        mut self,
+        face_tex_variants_px: Vec<Vector2<f32>>,
+    ) -> Self {
+        self.style.face_tex_variants_px = face_tex_variants_px;
+        self
+    }
+    pub fn face_tex_tile_height_px(
+        mut self,
+        face_tex_tile_height_px: Option<f32>,
+    ) -> Self {
+        self.style.face_tex_tile_height_px = face_tex_tile_height_px;
+        self
+    }
+    pub fn face_tex_wraps(mut self, face_tex_wraps: bool) -> Self {
+        self.style.face_tex_wraps = face_tex_wraps;
+        self
+    }
+    pub fn top_tex_top_left_px(mut self, top_tex_top_left_px: Vector2<f32>) -> Self {
+        self.style.top_tex_top_left_px = top_tex_top_left_px;
+        self
+    }
+    pub fn top_tex_quarter_turns(mut self, top_tex_quarter_turns: u32) -> Self {
+        self.style.top_tex_quarter_turns = top_tex_quarter_turns;
+        self
+    }
+    pub fn rotate_top_tex_per_cell(mut self, rotate_top_tex_per_cell: bool) -> Self {
+        self.style.rotate_top_tex_per_cell = rotate_top_tex_per_cell;
+        self
+    }
+    pub fn floor_tex_top_left_px(mut self, floor_tex_top_left_px: Vector2<f32>) -> Self {
+        self.style.floor_tex_top_left_px = floor_tex_top_left_px;
+        self
+    }
+    pub fn ceiling_tex_top_left_px(
+        mut self,
+        ceiling_tex_top_left_px: Vector2<f32>,
+    ) -> Self {
+        self.style.ceiling_tex_top_left_px = ceiling_tex_top_left_px;
+        self
+    }
+    pub fn texture_names(mut self, texture_names: Option<TextureNames>) -> Self {
+        self.style.texture_names = texture_names;
+        self
+    }
+    pub fn face_material(mut self, face_material: u32) -> Self {
+        self.style.face_material = face_material;
+        self
+    }
+    pub fn top_material(mut self, top_material: u32) -> Self {
+        self.style.top_material = top_material;
+        self
+    }
+    pub fn floor_material(mut self, floor_material: u32) -> Self {
+        self.style.floor_material = floor_material;
+        self
+    }
+    pub fn ceiling_material(mut self, ceiling_material: u32) -> Self {
+        self.style.ceiling_material = ceiling_material;
+        self
+    }
+    pub fn face_tex_layer(mut self, face_tex_layer: u32) -> Self {
+        self.style.face_tex_layer = face_tex_layer;
+        self
+    }
+    pub fn top_tex_layer(mut self, top_tex_layer: u32) -> Self {
+        self.style.top_tex_layer = top_tex_layer;
+        self
+    }
+    pub fn floor_tex_layer(mut self, floor_tex_layer: u32) -> Self {
+        self.style.floor_tex_layer = floor_tex_layer;
+        self
+    }
+    pub fn ceiling_tex_layer(mut self, ceiling_tex_layer: u32) -> Self {
+        self.style.ceiling_tex_layer = ceiling_tex_layer;
+        self
+    }
+    pub fn door_width_fraction(mut self, door_width_fraction: f32) -> Self {
+        self.style.door_width_fraction = door_width_fraction;
+        self
+    }
+    pub fn door_height_px(mut self, door_height_px: f32) -> Self {
+        self.style.door_height_px = door_height_px;
+        self
+    }
+    pub fn window_width_fraction(mut self, window_width_fraction: f32) -> Self {
+        self.style.window_width_fraction = window_width_fraction;
+        self
+    }
+    pub fn window_sill_px(mut self, window_sill_px: f32) -> Self {
+        self.style.window_sill_px = window_sill_px;
+        self
+    }
+    pub fn window_height_px(mut self, window_height_px: f32) -> Self {
+        self.style.window_height_px = window_height_px;
+        self
+    }
+    pub fn top_bevel_px(mut self, top_bevel_px: f32) -> Self {
+        self.style.top_bevel_px = top_bevel_px;
+        self
+    }
+    pub fn railing_height_px(mut self, railing_height_px: f32) -> Self {
+        self.style.railing_height_px = railing_height_px;
+        self
+    }
+    pub fn baseboard_px(mut self, baseboard_px: f32) -> Self {
+        self.style.baseboard_px = baseboard_px;
+        self
+    }
+    pub fn baseboard_depth_px(mut self, baseboard_depth_px: f32) -> Self {
+        self.style.baseboard_depth_px = baseboard_depth_px;
+        self
+    }
+    pub fn baseboard_tex_top_left_px(
+        mut self,
+        baseboard_tex_top_left_px: Vector2<f32>,
+    ) -> Self {
+        self.style.baseboard_tex_top_left_px = baseboard_tex_top_left_px;
+        self
+    }
+    pub fn crown_px(mut self, crown_px: f32) -> Self {
+        self.style.crown_px = crown_px;
+        self
+    }
+    pub fn crown_depth_px(mut self, crown_depth_px: f32) -> Self {
+        self.style.crown_depth_px = crown_depth_px;
+        self
+    }
+    pub fn crown_tex_top_left_px(mut self, crown_tex_top_left_px: Vector2<f32>) -> Self {
+        self.style.crown_tex_top_left_px = crown_tex_top_left_px;
+        self
+    }
+    pub fn build(self) -> Style {
+        self.style
+    }
+}
+
+impl Default for StyleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_cell_size() {
+        let config = Config::default();
+        assert_eq!(config.cell_size_px, vec2(16., 16.));
+        assert_eq!(ConfigBuilder::new().build().cell_size_px, vec2(16., 16.));
+    }
+
+    #[test]
+    fn style_builder_defaults() {
+        // the defaults are the values the example used before the builder
+        let style = StyleBuilder::new().build();
+        assert_eq!(style.width_px, 8.);
+        assert_eq!(style.height_px, 32.);
+        assert_eq!(style.face_tex_top_left_px, vec2(64., 16.));
+        assert_eq!(style.top_tex_top_left_px, vec2(16., 16.));
+        assert_eq!(style.floor_tex_top_left_px, vec2(0., 48.));
+        assert_eq!(style.ceiling_tex_top_left_px, vec2(0., 48.));
+        assert_eq!(
+            (
+                style.face_material,
+                style.top_material,
+                style.floor_material,
+                style.ceiling_material
+            ),
+            (0, 1, 2, 3)
+        );
+        assert_eq!(style.door_height_px, 24.);
+        assert_eq!(style.window_sill_px, 8.);
+        assert_eq!(style.window_height_px, 16.);
+    }
+
+    #[test]
+    fn style_builder_overrides_defaults() {
+        let style = StyleBuilder::new().width_px(4.).height_px(48.).build();
+        assert_eq!(style.width_px, 4.);
+        assert_eq!(style.height_px, 48.);
+        // other fields keep their defaults
+        assert_eq!(style.face_tex_top_left_px, vec2(64., 16.));
+    }
+
+    #[test]
+    fn seeds_choose_variants() {
+        let variants = vec![vec2(0., 0.), vec2(32., 0.), vec2(64., 0.), vec2(96., 0.)];
+        let style = StyleBuilder::new().face_tex_variants_px(variants).build();
+        let variant = |seed| {
+            let config = ConfigBuilder::new().seed(seed).build();
+            style
+                .for_cell(Coord::new(3, 5), &config)
+                .face_tex_top_left_px
+        };
+        assert_eq!(variant(1), variant(1));
+        let mut chosen = (0..8).map(variant).collect::<Vec<_>>();
+        chosen.dedup();
+        assert!(chosen.len() > 1);
+    }
+}
@@ -0,0 +1,299 @@
+use cell::{CellType, Diagonal};
+use grid_2d::{Coord, Grid, Size};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum TerrainError {
+    Io(io::Error),
+    InconsistentWidth {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownChar {
+        ch: char,
+        coord: Coord,
+    },
+    /// A row of run-length encoded terrain ends with the length of a run,
+    /// but not its character
+    IncompleteRun {
+        row: usize,
+    },
+}
+
+impl From<io::Error> for TerrainError {
+    fn from(e: io::Error) -> Self {
+        TerrainError::Io(e)
+    }
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TerrainError::Io(ref e) => write!(f, "failed to read terrain: {}", e),
+            TerrainError::InconsistentWidth {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has width {}, but expected width {}",
+                row, found, expected
+            ),
+            TerrainError::UnknownChar { ch, coord } => write!(
+                f,
+                "unknown character {:?} at ({}, {})",
+                ch, coord.x, coord.y
+            ),
+            TerrainError::IncompleteRun { row } => {
+                write!(f, "row {} ends with a run length but no character", row)
+            }
+        }
+    }
+}
+
+impl Error for TerrainError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TerrainError::Io(ref e) => Some(e),
+            TerrainError::InconsistentWidth { .. }
+            | TerrainError::UnknownChar { .. }
+            | TerrainError::IncompleteRun { .. } => None,
+        }
+    }
+}
+
+/// Mapping from characters in a terrain string to cell types.
+#[derive(Debug, Clone)]
+pub struct Legend {
+    cell_types: HashMap<char, CellType>,
+}
+
+impl Legend {
+    /// Creates a legend which doesn't recognise any characters.
+    pub fn empty() -> Self {
+        Self {
+            cell_types: HashMap::new(),
+        }
+    }
+    pub fn with(mut self, ch: char, cell_type: CellType) -> Self {
+        self.insert(ch, cell_type);
+        self
+    }
+    pub fn insert(&mut self, ch: char, cell_type: CellType) {
+        self.cell_types.insert(ch, cell_type);
+    }
+    pub fn get(&self, ch: char) -> Option<CellType> {
+        self.cell_types.get(&ch).cloned()
+    }
+}
+
+impl Default for Legend {
+    /// '.' is floor, '#' is wall, '+' is door, '=' is window, '-' is railing,
+    /// and '\\' and '/' are diagonal walls.
+    fn default() -> Self {
+        Self::empty()
+            .with('.', CellType::Floor)
+            .with('#', CellType::Wall)
+            .with('+', CellType::Door)
+            .with('=', CellType::Window)
+            .with('-', CellType::Railing)
+            .with('\\', CellType::Diagonal(Diagonal::NorthWestToSouthEast))
+            .with('/', CellType::Diagonal(Diagonal::NorthEastToSouthWest))
+    }
+}
+
+/// Parses a grid from a string with one line per row, using the default
+/// `Legend`. Empty lines are ignored.
+pub fn parse_terrain(s: &str) -> Result<Grid<CellType>, TerrainError> {
+    parse_terrain_with_legend(s, &Legend::default())
+}
+
+/// Parses a grid from a string with one line per row, where each character
+/// is mapped to a cell type by `legend`. Empty lines are ignored.
+pub fn parse_terrain_with_legend(
+    s: &str,
+    legend: &Legend,
+) -> Result<Grid<CellType>, TerrainError> {
+    let terrain_vecs = s
+        .lines()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let width = terrain_vecs.first().map(|v| v.len()).unwrap_or(0);
+    let height = terrain_vecs.len();
+    for (row, v) in terrain_vecs.iter().enumerate() {
+        if v.len() != width {
+            return Err(TerrainError::InconsistentWidth {
+                row,
+                expected: width,
+                found: v.len(),
+            });
+        }
+    }
+    let mut cells = Vec::with_capacity(width * height);
+    for (y, v) in terrain_vecs.iter().enumerate() {
+        for (x, &ch) in v.iter().enumerate() {
+            let cell = legend.get(ch).ok_or_else(|| TerrainError::UnknownChar {
+                ch,
+                coord: Coord::new(x as i32, y as i32),
+            })?;
+            cells.push(cell);
+        }
+    }
+    let size = Size::new(width as u32, height as u32);
+    Ok(Grid::new_from_fn(size, |coord| {
+        cells[coord.y as usize * width + coord.x as usize]
+    }))
+}
+
+/// Decodes a grid from a run-length encoded string with one line per row,
+/// using the default `Legend`. See `decode_rle_with_legend`.
+pub fn decode_rle(s: &str, width: u32) -> Result<Grid<CellType>, TerrainError> {
+    decode_rle_with_legend(s, width, &Legend::default())
+}
+
+/// Decodes a grid from a string with one line per row, where each row is a
+/// sequence of runs. A run is a character, mapped to a cell type by
+/// `legend`, optionally preceded by the number of times it repeats (e.g.
+/// "5#3." is five walls followed by three floors). Each row must decode to
+/// exactly `width` cells. Empty lines are ignored, and digits can't appear in
+/// the legend.
+pub fn decode_rle_with_legend(
+    s: &str,
+    width: u32,
+    legend: &Legend,
+) -> Result<Grid<CellType>, TerrainError> {
+    let width = width as usize;
+    let mut cells = Vec::new();
+    let mut height = 0;
+    for (row, line) in s.lines().filter(|s| !s.is_empty()).enumerate() {
+        let mut found = 0;
+        let mut count: Option<usize> = None;
+        for ch in line.chars() {
+            if let Some(digit) = ch.to_digit(10) {
+                let count = count.get_or_insert(0);
+                *count = count.saturating_mul(10).saturating_add(digit as usize);
+                continue;
+            }
+            let cell = legend.get(ch).ok_or_else(|| TerrainError::UnknownChar {
+                ch,
+                coord: Coord::new(found as i32, row as i32),
+            })?;
+            let count = count.take().unwrap_or(1);
+            // checked before expanding the run, so long runs fail quickly
+            if found + count > width {
+                return Err(TerrainError::InconsistentWidth {
+                    row,
+                    expected: width,
+                    found: found.saturating_add(count),
+                });
+            }
+            cells.extend(iter::repeat_n(cell, count));
+            found += count;
+        }
+        if count.is_some() {
+            return Err(TerrainError::IncompleteRun { row });
+        }
+        if found != width {
+            return Err(TerrainError::InconsistentWidth {
+                row,
+                expected: width,
+                found,
+            });
+        }
+        height += 1;
+    }
+    let size = Size::new(width as u32, height);
+    Ok(Grid::new_from_fn(size, |coord| {
+        cells[coord.y as usize * width + coord.x as usize]
+    }))
+}
+
+/// Reads the file at `path` and parses it with `parse_terrain`.
+pub fn load_terrain<P: AsRef<Path>>(path: P) -> Result<Grid<CellType>, TerrainError> {
+    load_terrain_with_legend(path, &Legend::default())
+}
+
+/// Reads the file at `path` and parses it with `parse_terrain_with_legend`.
+pub fn load_terrain_with_legend<P: AsRef<Path>>(
+    path: P,
+    legend: &Legend,
+) -> Result<Grid<CellType>, TerrainError> {
+    let s = fs::read_to_string(path)?;
+    parse_terrain_with_legend(&s, legend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_half_wall_half_floor() {
+        let grid = decode_rle("4#4.\n", 8).unwrap();
+        let expected = parse_terrain("####....\n").unwrap();
+        assert_eq!(grid.size(), expected.size());
+        assert!(grid.iter().eq(expected.iter()));
+        match decode_rle("4#5.\n", 8) {
+            Err(TerrainError::InconsistentWidth { found: 9, .. }) => (),
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+        match decode_rle("4#3.\n", 8) {
+            Err(TerrainError::InconsistentWidth { found: 7, .. }) => (),
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+    }
+
+    #[test]
+    fn custom_legend() {
+        let legend = Legend::empty()
+            .with('x', CellType::Wall)
+            .with(' ', CellType::Floor);
+        let expected = parse_terrain("#.\n.#\n").unwrap();
+        let grid = parse_terrain_with_legend("x \n x\n", &legend).unwrap();
+        assert_eq!(grid.size(), expected.size());
+        assert!(grid.iter().eq(expected.iter()));
+        let grid = decode_rle_with_legend("x \n x\n", 2, &legend).unwrap();
+        assert!(grid.iter().eq(expected.iter()));
+        // characters in the default legend aren't recognised unless added
+        match parse_terrain_with_legend("x#\n", &legend) {
+            Err(TerrainError::UnknownChar { ch: '#', .. }) => (),
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+    }
+
+    #[test]
+    fn unknown_char_reports_its_coord() {
+        match parse_terrain("...\n.#.\n..?\n") {
+            Err(TerrainError::UnknownChar { ch: '?', coord }) => {
+                assert_eq!(coord, Coord::new(2, 2))
+            }
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+        // the coord of a run is that of its first cell
+        match decode_rle("4.\n3#?\n", 4) {
+            Err(TerrainError::UnknownChar { ch: '?', coord }) => {
+                assert_eq!(coord, Coord::new(3, 1))
+            }
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+    }
+
+    #[test]
+    fn run_length_without_a_char_is_incomplete() {
+        match decode_rle("4#4.\n4#4\n", 8) {
+            Err(TerrainError::IncompleteRun { row: 1 }) => (),
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+        match decode_rle("8\n", 8) {
+            Err(TerrainError::IncompleteRun { row: 0 }) => (),
+            other => panic!("unexpected result {:?}", other.map(|grid| grid.size())),
+        }
+    }
+}
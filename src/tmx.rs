@@ -0,0 +1,204 @@
+use cell::CellType;
+use grid_2d::{Coord, Grid, Size};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// the top bits of a gid store whether the tile is flipped or rotated
+const GID_FLAGS: u32 = 0xf000_0000;
+
+#[derive(Debug)]
+pub enum TmxError {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    /// The map isn't orthogonal
+    UnsupportedOrientation(String),
+    /// The layer data uses an encoding other than csv or xml
+    UnsupportedEncoding(String),
+    MissingLayer(String),
+    /// The layer data doesn't contain exactly one tile per cell of the layer
+    InvalidData,
+    UnknownGid {
+        gid: u32,
+        coord: Coord,
+    },
+}
+
+impl From<io::Error> for TmxError {
+    fn from(e: io::Error) -> Self {
+        TmxError::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for TmxError {
+    fn from(e: roxmltree::Error) -> Self {
+        TmxError::Xml(e)
+    }
+}
+
+impl fmt::Display for TmxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TmxError::Io(ref e) => write!(f, "failed to read tmx map: {}", e),
+            TmxError::Xml(ref e) => write!(f, "failed to parse tmx map: {}", e),
+            TmxError::UnsupportedOrientation(ref orientation) => {
+                write!(f, "unsupported map orientation {:?}", orientation)
+            }
+            TmxError::UnsupportedEncoding(ref encoding) => {
+                write!(f, "unsupported layer encoding {:?}", encoding)
+            }
+            TmxError::MissingLayer(ref name) => {
+                write!(f, "no tile layer named {:?}", name)
+            }
+            TmxError::InvalidData => write!(f, "layer data doesn't match the layer size"),
+            TmxError::UnknownGid { gid, coord } => {
+                write!(f, "unknown tile gid {} at ({}, {})", gid, coord.x, coord.y)
+            }
+        }
+    }
+}
+
+impl Error for TmxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TmxError::Io(ref e) => Some(e),
+            TmxError::Xml(ref e) => Some(e),
+            TmxError::UnsupportedOrientation(_)
+            | TmxError::UnsupportedEncoding(_)
+            | TmxError::MissingLayer(_)
+            | TmxError::InvalidData
+            | TmxError::UnknownGid { .. } => None,
+        }
+    }
+}
+
+/// Mapping from the global tile ids of a Tiled map to cell types. Empty
+/// tiles have the gid 0. Flipped and rotated tiles have the same gid as
+/// their unflipped tile.
+#[derive(Debug, Clone)]
+pub struct TileLegend {
+    cell_types: HashMap<u32, CellType>,
+}
+
+impl TileLegend {
+    /// Creates a legend which doesn't recognise any tiles.
+    pub fn empty() -> Self {
+        Self {
+            cell_types: HashMap::new(),
+        }
+    }
+    pub fn with(mut self, gid: u32, cell_type: CellType) -> Self {
+        self.insert(gid, cell_type);
+        self
+    }
+    pub fn insert(&mut self, gid: u32, cell_type: CellType) {
+        self.cell_types.insert(gid, cell_type);
+    }
+    pub fn get(&self, gid: u32) -> Option<CellType> {
+        self.cell_types.get(&(gid & !GID_FLAGS)).cloned()
+    }
+}
+
+fn parse_attribute(node: Node, name: &str) -> Result<u32, TmxError> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or(TmxError::InvalidData)
+}
+
+fn layer_gids(data: Node) -> Result<Vec<u32>, TmxError> {
+    match data.attribute("encoding") {
+        Some("csv") => data
+            .text()
+            .unwrap_or("")
+            .split(',')
+            .map(|gid| gid.trim().parse().map_err(|_| TmxError::InvalidData))
+            .collect(),
+        None => data
+            .children()
+            .filter(|n| n.has_tag_name("tile"))
+            .map(|tile| match tile.attribute("gid") {
+                Some(_) => parse_attribute(tile, "gid"),
+                None => Ok(0),
+            })
+            .collect(),
+        Some(encoding) => Err(TmxError::UnsupportedEncoding(encoding.to_string())),
+    }
+}
+
+/// Parses a grid from the tile layer named `layer` of an orthogonal Tiled
+/// map in the TMX format, where each tile is mapped to a cell type by
+/// `legend`. The layer data must be encoded as csv or xml.
+pub fn parse_tmx(
+    s: &str,
+    layer: &str,
+    legend: &TileLegend,
+) -> Result<Grid<CellType>, TmxError> {
+    let document = Document::parse(s)?;
+    let map = document.root_element();
+    match map.attribute("orientation") {
+        None | Some("orthogonal") => (),
+        Some(orientation) => {
+            return Err(TmxError::UnsupportedOrientation(orientation.to_string()))
+        }
+    }
+    let layer_node = map
+        .children()
+        .find(|n| n.has_tag_name("layer") && n.attribute("name") == Some(layer))
+        .ok_or_else(|| TmxError::MissingLayer(layer.to_string()))?;
+    let width = parse_attribute(layer_node, "width")?;
+    let height = parse_attribute(layer_node, "height")?;
+    let data = layer_node
+        .children()
+        .find(|n| n.has_tag_name("data"))
+        .ok_or(TmxError::InvalidData)?;
+    let gids = layer_gids(data)?;
+    if gids.len() != width as usize * height as usize {
+        return Err(TmxError::InvalidData);
+    }
+    let mut cells = Vec::with_capacity(gids.len());
+    for (i, &gid) in gids.iter().enumerate() {
+        let cell = legend.get(gid).ok_or_else(|| TmxError::UnknownGid {
+            gid,
+            coord: Coord::new((i % width as usize) as i32, (i / width as usize) as i32),
+        })?;
+        cells.push(cell);
+    }
+    Ok(Grid::new_from_fn(Size::new(width, height), |coord| {
+        cells[coord.y as usize * width as usize + coord.x as usize]
+    }))
+}
+
+/// Reads the file at `path` and parses it with `parse_tmx`.
+pub fn load_tmx<P: AsRef<Path>>(
+    path: P,
+    layer: &str,
+    legend: &TileLegend,
+) -> Result<Grid<CellType>, TmxError> {
+    let s = fs::read_to_string(path)?;
+    parse_tmx(&s, layer, legend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::parse_terrain;
+
+    #[test]
+    fn room_fixture() {
+        let legend = TileLegend::empty()
+            .with(1, CellType::Wall)
+            .with(2, CellType::Floor)
+            .with(3, CellType::Door)
+            .with(4, CellType::Window);
+        let grid =
+            parse_tmx(include_str!("../fixtures/room.tmx"), "walls", &legend).unwrap();
+        let expected =
+            parse_terrain(concat!("##+##\n", "#...#\n", "#...=\n", "#####\n")).unwrap();
+        assert_eq!(grid.size(), expected.size());
+        assert!(grid.iter().eq(expected.iter()));
+    }
+}
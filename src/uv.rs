@@ -0,0 +1,293 @@
+use buffers::RelativeBuffers;
+use cgmath::{vec2, vec3, Vector2, Vector3};
+use std::collections::HashMap;
+use style::Config;
+
+impl RelativeBuffers {
+    /// Converts texture coordinates from pixels to the range 0 to 1 across
+    /// an atlas of the given size. This modifies the buffers in place, and
+    /// should only be called once, as calling it again would divide the
+    /// already normalized coordinates.
+    pub fn normalize_uvs(&mut self, atlas_width: f32, atlas_height: f32) {
+        for a in self.attributes.iter_mut() {
+            a.tex_coord_px.x /= atlas_width;
+            a.tex_coord_px.y /= atlas_height;
+        }
+    }
+
+    /// Flips texture coordinates vertically within an atlas of the given
+    /// height, for APIs which put the origin of textures at the opposite edge.
+    /// Like `normalize_uvs`, this modifies the buffers in place. If both are
+    /// used, call this first, since it expects coordinates in pixels.
+    pub fn flip_v(&mut self, atlas_height: f32) {
+        for a in self.attributes.iter_mut() {
+            a.tex_coord_px.y = atlas_height - a.tex_coord_px.y;
+        }
+    }
+
+    /// Moves the texture coordinates of each triangle into an island of a
+    /// larger virtual atlas belonging to the cell the triangle lies in, so
+    /// data baked per cell, such as lightmaps, doesn't bleed between cells.
+    /// The island of the cell at `coord` starts at `coord` multiplied by
+    /// `island_size_px` plus `gutter_px`, so texture coordinates from 0 to
+    /// `island_size_px` stay within the island, with a gutter of `gutter_px`
+    /// between neighbouring islands. Triangles are placed in the cell which
+    /// contains their centre, and vertices shared by triangles in different
+    /// cells are duplicated. `config` must be the one the geometry was
+    /// generated with.
+    pub fn offset_uvs_by_cell(
+        &mut self,
+        island_size_px: Vector2<f32>,
+        gutter_px: f32,
+        config: &Config,
+    ) {
+        let cell_size = config.cell_size_px * config.units_per_px;
+        let stride = island_size_px + vec2(gutter_px, gutter_px);
+        let original = &self.attributes;
+        let mut attributes = original.clone();
+        let mut cell_by_vertex = HashMap::new();
+        let mut copies = HashMap::new();
+        for t in self.indices.chunks_mut(3) {
+            let centre = t.iter().fold(vec2(0., 0.), |sum, &i| {
+                let p = original[i as usize].space_coord_px;
+                sum + vec2(p.x, p.z) / 3.
+            });
+            let cell = (
+                (centre.x / cell_size.x).floor() as i32,
+                (centre.y / cell_size.y).floor() as i32,
+            );
+            for i in t.iter_mut() {
+                let index = *i;
+                // the first cell to use a vertex keeps it, and other cells
+                // use a copy
+                if *cell_by_vertex.entry(index).or_insert(cell) != cell {
+                    *i = *copies.entry((index, cell)).or_insert_with(|| {
+                        attributes.push(original[index as usize]);
+                        attributes.len() as u32 - 1
+                    });
+                    cell_by_vertex.insert(*i, cell);
+                }
+            }
+        }
+        for (&i, &(x, z)) in cell_by_vertex.iter() {
+            let offset = vec2(x as f32 * stride.x, z as f32 * stride.y);
+            attributes[i as usize].tex_coord_px += offset;
+        }
+        self.attributes = attributes;
+    }
+
+    /// Replaces the texture coordinates of each triangle with its position
+    /// projected onto the plane of two world axes which it most nearly faces,
+    /// for triplanar texturing. The direction a triangle faces is the sum of
+    /// the normals of its vertices. Triangles facing mostly up or down take
+    /// their texture coordinates from x and z, and the rest from x or z
+    /// across and y down, so faces along the x axis use x and y. Texture
+    /// coordinates are `tex_px_per_unit` times the projected position.
+    /// Vertices shared by triangles which are projected onto different planes
+    /// are duplicated.
+    pub fn triplanar_uvs(&mut self, tex_px_per_unit: f32) {
+        let original = &self.attributes;
+        let mut attributes = original.clone();
+        let mut axis_by_vertex = HashMap::new();
+        let mut copies = HashMap::new();
+        for t in self.indices.chunks_mut(3) {
+            let normal = t.iter().fold(vec3(0., 0., 0.), |sum, &i| {
+                sum + original[i as usize].normal
+            });
+            let axis = Axis::dominant(normal);
+            for i in t.iter_mut() {
+                let index = *i;
+                // the first triangle to use a vertex decides its projection,
+                // and triangles projected differently use a copy
+                if *axis_by_vertex.entry(index).or_insert(axis) != axis {
+                    *i = *copies.entry((index, axis)).or_insert_with(|| {
+                        attributes.push(original[index as usize]);
+                        attributes.len() as u32 - 1
+                    });
+                    axis_by_vertex.insert(*i, axis);
+                }
+            }
+        }
+        for (&i, &axis) in axis_by_vertex.iter() {
+            let a = &mut attributes[i as usize];
+            let p = a.space_coord_px;
+            let projected = match axis {
+                Axis::X => vec2(p.z, -p.y),
+                Axis::Y => vec2(p.x, p.z),
+                Axis::Z => vec2(p.x, -p.y),
+            };
+            a.tex_coord_px = projected * tex_px_per_unit;
+        }
+        self.attributes = attributes;
+    }
+}
+
+/// The world axis a surface most nearly faces along
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    fn dominant(v: Vector3<f32>) -> Self {
+        let (x, y, z) = (v.x.abs(), v.y.abs(), v.z.abs());
+        if y >= x && y >= z {
+            Axis::Y
+        } else if x >= z {
+            Axis::X
+        } else {
+            Axis::Z
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use collision::aabb_mesh;
+    use style::ConfigBuilder;
+
+    fn buffers(tex_coord_px: Vector2<f32>) -> RelativeBuffers {
+        RelativeBuffers {
+            attributes: vec![Attribute {
+                space_coord_px: vec3(0., 0., 0.),
+                tex_coord_px,
+                normal: vec3(0., 1., 0.),
+                material: 0,
+                tex_layer: 0,
+                ao: 1.,
+            }],
+            indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn normalize_uvs_divides_by_atlas_size() {
+        let mut buffers = buffers(vec2(32., 0.));
+        buffers.normalize_uvs(64., 64.);
+        assert_eq!(buffers.attributes[0].tex_coord_px, vec2(0.5, 0.));
+    }
+
+    #[test]
+    fn flip_v_within_atlas() {
+        let mut buffers = buffers(vec2(0., 8.));
+        buffers.flip_v(32.);
+        assert_eq!(buffers.attributes[0].tex_coord_px, vec2(0., 24.));
+    }
+
+    /// Quads covering the cells (0, 0) and (1, 0) of cells 32 pixels wide, each
+    /// with texture coordinates from 0 to 32
+    fn two_cells() -> RelativeBuffers {
+        let vertex = |x: f32, z: f32, u: f32, v: f32| Attribute {
+            space_coord_px: vec3(x, 0., z),
+            tex_coord_px: vec2(u, v),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        };
+        let mut attributes = Vec::new();
+        let mut indices = Vec::new();
+        for &x in [0., 32.].iter() {
+            let base = attributes.len() as u32;
+            attributes.extend_from_slice(&[
+                vertex(x, 0., 0., 0.),
+                vertex(x + 32., 0., 32., 0.),
+                vertex(x + 32., 32., 32., 32.),
+                vertex(x, 32., 0., 32.),
+            ]);
+            indices.extend_from_slice(&[
+                base,
+                base + 1,
+                base + 2,
+                base,
+                base + 2,
+                base + 3,
+            ]);
+        }
+        RelativeBuffers {
+            attributes,
+            indices,
+        }
+    }
+
+    #[test]
+    fn cell_uv_islands_are_separated_by_gutter() {
+        let config = ConfigBuilder::new().cell_size_px(vec2(32., 32.)).build();
+        let mut buffers = two_cells();
+        buffers.offset_uvs_by_cell(vec2(32., 32.), 4., &config);
+        // the range of u of each quad
+        let range = |quad: &[Attribute]| {
+            let us = quad.iter().map(|a| a.tex_coord_px.x);
+            (
+                us.clone().fold(f32::INFINITY, f32::min),
+                us.fold(f32::NEG_INFINITY, f32::max),
+            )
+        };
+        assert_eq!(range(&buffers.attributes[0..4]), (0., 32.));
+        assert_eq!(range(&buffers.attributes[4..8]), (36., 68.));
+        // islands are in the same row, as the cells are
+        for a in buffers.attributes.iter() {
+            assert!(a.tex_coord_px.y >= 0. && a.tex_coord_px.y <= 32.);
+        }
+    }
+
+    #[test]
+    fn triplanar_uvs_project_onto_faced_plane() {
+        let mut mesh = aabb_mesh(vec3(1., 2., 3.), vec3(4., 6., 8.));
+        mesh.triplanar_uvs(2.);
+        assert_eq!(mesh.attributes.len(), 24);
+        for a in mesh.attributes.iter() {
+            let (p, n) = (a.space_coord_px, a.normal);
+            let expected = if n.y.abs() == 1. {
+                vec2(p.x, p.z)
+            } else if n.x.abs() == 1. {
+                vec2(p.z, -p.y)
+            } else {
+                vec2(p.x, -p.y)
+            };
+            assert_eq!(a.tex_coord_px, expected * 2., "{:?}", n);
+        }
+    }
+
+    #[test]
+    fn triplanar_uvs_duplicate_vertices_between_planes() {
+        let vertex = |p: Vector3<f32>, normal: Vector3<f32>| Attribute {
+            space_coord_px: p,
+            tex_coord_px: vec2(0., 0.),
+            normal,
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        };
+        let up = vec3(0., 1., 0.);
+        let east = vec3(1., 0., 0.);
+        let mut buffers = RelativeBuffers {
+            attributes: vec![
+                // on the edge between the top and the east side
+                vertex(vec3(1., 1., 0.), (up + east) / 2.),
+                vertex(vec3(1., 1., 1.), (up + east) / 2.),
+                vertex(vec3(0., 1., 0.), up),
+                vertex(vec3(1., 0., 0.), east),
+            ],
+            indices: vec![0, 1, 2, 1, 0, 3],
+        };
+        buffers.triplanar_uvs(1.);
+        // the edge is projected onto both planes
+        assert_eq!(buffers.attributes.len(), 6);
+        assert_eq!(buffers.attributes[0].tex_coord_px, vec2(1., 0.));
+        assert_eq!(buffers.attributes[1].tex_coord_px, vec2(1., 1.));
+        let side = &buffers.indices[3..];
+        assert_eq!(
+            buffers.attributes[side[0] as usize].tex_coord_px,
+            vec2(1., -1.)
+        );
+        assert_eq!(
+            buffers.attributes[side[1] as usize].tex_coord_px,
+            vec2(0., -1.)
+        );
+    }
+}
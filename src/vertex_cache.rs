@@ -0,0 +1,193 @@
+use buffers::RelativeBuffers;
+
+// parameters of the vertex scoring from Tom Forsyth's "Linear-Speed Vertex
+// Cache Optimisation"
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+struct Vertex {
+    cache_position: Option<usize>,
+    // triangles using this vertex which haven't been output yet
+    triangles: Vec<usize>,
+    score: f32,
+}
+
+impl Vertex {
+    fn update_score(&mut self) {
+        if self.triangles.is_empty() {
+            self.score = -1.;
+            return;
+        }
+        let cache_score = match self.cache_position {
+            None => 0.,
+            // the vertices of the last triangle are scored the same
+            // regardless of their order
+            Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+            Some(position) => (1. - (position - 3) as f32 / (CACHE_SIZE - 3) as f32)
+                .powf(CACHE_DECAY_POWER),
+        };
+        let valence_boost = VALENCE_BOOST_SCALE
+            * (self.triangles.len() as f32).powf(-VALENCE_BOOST_POWER);
+        self.score = cache_score + valence_boost;
+    }
+}
+
+impl RelativeBuffers {
+    /// Reorders the triangles in the index buffer so that vertices are
+    /// reused while they are still in the post-transform vertex cache, then
+    /// reorders the vertices into the order they are first referred to,
+    /// which keeps vertex fetches local. Vertices not referred to by any
+    /// triangle are moved to the end. The set of triangles, and the vertices
+    /// of each triangle, are unchanged.
+    pub fn optimize_vertex_cache(&mut self) {
+        self.reorder_triangles_for_cache();
+        self.reorder_vertices_by_first_use();
+    }
+
+    fn reorder_triangles_for_cache(&mut self) {
+        let triangle_count = self.indices.len() / 3;
+        let mut vertices = (0..self.attributes.len())
+            .map(|_| Vertex {
+                cache_position: None,
+                triangles: Vec::new(),
+                score: 0.,
+            })
+            .collect::<Vec<_>>();
+        for (t, triangle) in self.indices.chunks(3).enumerate() {
+            for &i in triangle {
+                vertices[i as usize].triangles.push(t);
+            }
+        }
+        for vertex in vertices.iter_mut() {
+            vertex.update_score();
+        }
+        let triangle_score = |vertices: &[Vertex], t: usize| -> f32 {
+            self.indices[t * 3..t * 3 + 3]
+                .iter()
+                .map(|&i| vertices[i as usize].score)
+                .sum()
+        };
+        let mut added = vec![false; triangle_count];
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        // the first triangle not yet added, used when no triangle in the
+        // cache can be added
+        let mut next_unadded = 0;
+        for _ in 0..triangle_count {
+            let mut best = None;
+            let mut best_score = -1.;
+            for &i in cache.iter() {
+                for &t in vertices[i as usize].triangles.iter() {
+                    let score = triangle_score(&vertices, t);
+                    if score > best_score {
+                        best = Some(t);
+                        best_score = score;
+                    }
+                }
+            }
+            let best = best.unwrap_or_else(|| {
+                while added[next_unadded] {
+                    next_unadded += 1;
+                }
+                next_unadded
+            });
+            added[best] = true;
+            let triangle = [
+                self.indices[best * 3],
+                self.indices[best * 3 + 1],
+                self.indices[best * 3 + 2],
+            ];
+            indices.extend_from_slice(&triangle);
+            for &i in triangle.iter() {
+                vertices[i as usize].triangles.retain(|&t| t != best);
+            }
+            // the vertices of the new triangle move to the front of the cache,
+            // once each even if the triangle refers to one more than once
+            let mut new_cache = Vec::with_capacity(CACHE_SIZE + 3);
+            for &i in triangle.iter() {
+                if !new_cache.contains(&i) {
+                    new_cache.push(i);
+                }
+            }
+            new_cache.extend(cache.iter().filter(|i| !triangle.contains(i)));
+            for &i in new_cache.iter().skip(CACHE_SIZE) {
+                vertices[i as usize].cache_position = None;
+                vertices[i as usize].update_score();
+            }
+            new_cache.truncate(CACHE_SIZE);
+            for (position, &i) in new_cache.iter().enumerate() {
+                vertices[i as usize].cache_position = Some(position);
+                vertices[i as usize].update_score();
+            }
+            cache = new_cache;
+        }
+        self.indices = indices;
+    }
+
+    fn reorder_vertices_by_first_use(&mut self) {
+        let mut remap = vec![None; self.attributes.len()];
+        let mut attributes = Vec::with_capacity(self.attributes.len());
+        let old_attributes = &self.attributes;
+        for i in self.indices.iter_mut() {
+            let old_index = *i as usize;
+            *i = *remap[old_index].get_or_insert_with(|| {
+                attributes.push(old_attributes[old_index]);
+                attributes.len() as u32 - 1
+            });
+        }
+        for (i, a) in self.attributes.iter().enumerate() {
+            if remap[i].is_none() {
+                attributes.push(*a);
+            }
+        }
+        self.attributes = attributes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffers::Attribute;
+    use cgmath::{vec2, vec3};
+
+    fn vertex(x: f32) -> Attribute {
+        Attribute {
+            space_coord_px: vec3(x, 0., 0.),
+            tex_coord_px: vec2(x, 0.),
+            normal: vec3(0., 1., 0.),
+            material: 0,
+            tex_layer: 0,
+            ao: 1.,
+        }
+    }
+
+    fn triangles(buffers: &RelativeBuffers) -> Vec<[f32; 3]> {
+        let mut triangles = buffers
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let x = |i: u32| buffers.attributes[i as usize].space_coord_px.x;
+                [x(t[0]), x(t[1]), x(t[2])]
+            })
+            .collect::<Vec<_>>();
+        triangles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        triangles
+    }
+
+    #[test]
+    fn triangles_are_preserved() {
+        let mut buffers = RelativeBuffers {
+            attributes: (0..6).map(|i| vertex(i as f32)).collect(),
+            // the first triangle refers to a vertex twice, but not in
+            // consecutive positions
+            indices: vec![0, 1, 0, 2, 3, 4, 1, 2, 5, 4, 3, 0],
+        };
+        let before = triangles(&buffers);
+        buffers.optimize_vertex_cache();
+        assert_eq!(triangles(&buffers), before);
+        assert_eq!(buffers.attributes.len(), 6);
+    }
+}
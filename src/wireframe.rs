@@ -0,0 +1,52 @@
+use buffers::RelativeBuffers;
+use std::collections::HashSet;
+
+impl RelativeBuffers {
+    /// Returns indices for drawing the edges of each triangle as a line list
+    /// using the same vertex buffer. Each triangle contributes 3 lines, so
+    /// edges shared between triangles are drawn more than once.
+    pub fn wireframe_indices(&self) -> Vec<u32> {
+        self.indices
+            .chunks(3)
+            .flat_map(|t| vec![t[0], t[1], t[1], t[2], t[2], t[0]])
+            .collect()
+    }
+
+    /// Like `wireframe_indices`, but each edge is only included once, even
+    /// if it is shared by several triangles. Edges are compared by vertex
+    /// index, so edges between distinct vertices at the same positions are
+    /// still repeated.
+    pub fn wireframe_indices_deduplicated(&self) -> Vec<u32> {
+        let mut seen = HashSet::new();
+        let mut indices = Vec::new();
+        for edge in self.wireframe_indices().chunks(2) {
+            let key = (edge[0].min(edge[1]), edge[0].max(edge[1]));
+            if seen.insert(key) {
+                indices.extend_from_slice(edge);
+            }
+        }
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangle_edges() {
+        let triangle = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: vec![0, 1, 2],
+        };
+        assert_eq!(triangle.wireframe_indices(), vec![0, 1, 1, 2, 2, 0]);
+        assert_eq!(triangle.wireframe_indices_deduplicated().len(), 3 * 2);
+        // a quad made of two triangles sharing the edge between 1 and 2
+        let quad = RelativeBuffers {
+            attributes: Vec::new(),
+            indices: vec![0, 1, 2, 1, 3, 2],
+        };
+        assert_eq!(quad.wireframe_indices().len(), 6 * 2);
+        assert_eq!(quad.wireframe_indices_deduplicated().len(), 5 * 2);
+    }
+}